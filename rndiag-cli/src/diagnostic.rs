@@ -1,20 +1,29 @@
-//Count the number of received packet from the lasts pings tests during the diagnostic
-pub fn packet_received(packet: &Vec<u32>) -> u8{
+use rndiag_core::error::Result;
+use rndiag_core::pmtu::PmtuReport;
+use rndiag_core::tool::Statistics;
+use std::time::Duration;
+//Count the number of lost packet from the lasts pings tests during the diagnostic
+//A timeout or an error is a no received packet
+pub fn packet_received(stats: &Statistics) -> u8{
 
-    let zero: u8 = packet.iter().filter(|&&x| x == 5000).count() as u8;
-    zero
+    stats.lost() as u8
 }
 
 //Check the latency of lasts pings tests and set a level, 0 => OK, 1 => Warning, 2 => Critical
-pub fn packet_latency(latency_moy_sampled: &u64) -> u8{
-    let latency1: u64 = 59;
-    let latency2: u64 = 130;
+//No reply => the latency is considered as critical
+pub fn packet_latency(latency: Option<Duration>) -> u8{
+    let latency1 = Duration::from_millis(59);
+    let latency2 = Duration::from_millis(130);
 
-        if latency_moy_sampled >= &latency2{
+    let Some(latency) = latency else {
+        return 2;
+    };
+
+        if latency >= latency2{
             2
 
         }
-        else if latency_moy_sampled >= &latency1 {
+        else if latency >= latency1 {
             1
         }
         else {
@@ -24,7 +33,7 @@ pub fn packet_latency(latency_moy_sampled: &u64) -> u8{
 }
 
 //Format the output cli diagnostics depending of latencies and recived packets
-pub fn output_format(latency_moy_vec: &Vec<u8>, zero_vec: &Vec<u8>){
+pub fn output_format(latency_moy_vec: &[u8], zero_vec: &[u8]){
     println!("\n=========== Diagnostic Result ===========\n");
     
     println!("-----Ping Result:");
@@ -94,3 +103,19 @@ pub fn output_format(latency_moy_vec: &Vec<u8>, zero_vec: &Vec<u8>){
 
 
 }
+
+//Format the path MTU result of the diagnostic => a black hole is critical, a MTU smaller than the local one is a warning
+pub fn pmtu_format(report: &Result<PmtuReport>) {
+    println!("\n-----Path MTU Result:");
+    match report {
+        Err(e) => println!("Path MTU ❌ ({})", e),
+        Ok(report) => match report.mtu {
+            None => println!("Path MTU ❌ (no reply)"),
+            Some(mtu) if report.black_hole => {
+                println!("MTU black hole ❌ (packets bigger than {} bytes are dropped without fragmentation needed)", mtu)
+            }
+            Some(mtu) if mtu < report.local_mtu => println!("Path MTU {} bytes ⚠ (local MTU {} bytes)", mtu, report.local_mtu),
+            Some(mtu) => println!("Path MTU {} bytes ✅", mtu),
+        },
+    }
+}
@@ -0,0 +1,621 @@
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::terminal::{self, disable_raw_mode, enable_raw_mode};
+use rndiag_core::error::Result;
+use rndiag_core::firewall::FirewallTool;
+use rndiag_core::multi::{DEFAULT_CONCURRENCY, run_concurrent};
+use rndiag_core::mtr::{MtrCommand, MtrHopStats, MtrTool};
+use rndiag_core::pmtu::PmtuTool;
+use rndiag_core::scan::{PortState, ScanTool};
+use rndiag_core::traceroute::{Hop, TracerouteTool};
+use rndiag_core::tool::{Probe, ProbeCommand, ProbeError, ProbeEvent, ProbeLink, ProbeOutcome, ProbeResult, ProbeRunner, as_ms, initial_ttl};
+use rndiag_graph::graph::graph_display;
+use rndiag_graph::mtr::{MtrRow, mtr_display};
+use std::io::{self, IsTerminal, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+//Number of values of the graph when the size of the terminal is unknown
+const DEFAULT_GRAPH_POINTS: usize = 120;
+
+//Run a latency tool (ping, tcp ping, resolver, tls, http) with the cli front-end
+//Each probe is printed, 'g' displays the graph and Ctrl-C stops the run when a terminal is used
+//Without terminal (pipe, systemd, ...) SIGINT and SIGTERM stop the run
+//The statistics are printed at the end of the run
+pub async fn run_latency_tool<P: Probe>(tool: &mut ProbeRunner<P>) -> Result<()> {
+    let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+    let (commands_tx, commands_rx) = mpsc::unbounded_channel();
+    tool.attach(ProbeLink::new(events_tx, commands_rx));
+
+    //The keyboard is only captured when stdin and stdout are a terminal
+    let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+    let graph_open = Arc::new(AtomicBool::new(false));
+    let finished = Arc::new(AtomicBool::new(false));
+
+    let keyboard = interactive.then(|| {
+        let commands = commands_tx.clone();
+        let graph_open = graph_open.clone();
+        let finished = finished.clone();
+        std::thread::spawn(move || keyboard_loop(commands, graph_open, finished))
+    });
+
+    let signals = tokio::spawn(stop_on_signal(vec![commands_tx]));
+
+    //Print each event of the tool, paused while the graph is displayed
+    let name = tool.name();
+    let timeout = tool.options().timeout;
+    let printer_graph_open = graph_open.clone();
+    let printer = tokio::spawn(async move {
+        while let Some(event) = events_rx.recv().await {
+            while printer_graph_open.load(Ordering::Relaxed) {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            print_event(name, &event, timeout, interactive);
+        }
+    });
+
+    let result = tool.run().await;
+
+    //Detach the front-end => the printer ends when all events are printed
+    tool.attach(ProbeLink::default());
+    let _ = printer.await;
+    finished.store(true, Ordering::Relaxed);
+    if let Some(keyboard) = keyboard {
+        let _ = keyboard.join();
+    }
+    signals.abort();
+
+    //Calculate and display stats: min/avg/max, % of packet loss, ...
+    println!("{}", tool.statistics());
+    result
+}
+
+//Run a latency tool on several targets concurrently with the cli front-end
+//Each probe is printed with its target, Ctrl-C, SIGINT and SIGTERM stop all the runs
+//A summary table with the statistics of each target is printed at the end
+pub async fn run_many<P: Probe + 'static>(runners: Vec<ProbeRunner<P>>) -> Vec<(ProbeRunner<P>, Result<()>)> {
+    let mut commands = Vec::with_capacity(runners.len());
+    let mut printers = Vec::with_capacity(runners.len());
+
+    let runners: Vec<ProbeRunner<P>> = runners
+        .into_iter()
+        .map(|mut runner| {
+            let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+            let (commands_tx, commands_rx) = mpsc::unbounded_channel();
+            runner.attach(ProbeLink::new(events_tx, commands_rx));
+            commands.push(commands_tx);
+
+            //Print each probe of the target on one line
+            let target = runner.target().to_string();
+            let name = runner.name();
+            let timeout = runner.options().timeout;
+            printers.push(tokio::spawn(async move {
+                while let Some(event) = events_rx.recv().await {
+                    match event {
+                        ProbeEvent::Probe { seq, outcome } => println!("{}", format_target_probe(&target, seq, &outcome, timeout)),
+                        ProbeEvent::RouteChange { .. } => println!("{} : {}", target, format_route_change(name, &event)),
+                        ProbeEvent::Start(_) => {}
+                    }
+                }
+            }));
+            runner
+        })
+        .collect();
+
+    let signals = tokio::spawn(stop_on_signal(commands));
+
+    let mut results = run_concurrent(runners, DEFAULT_CONCURRENCY).await;
+
+    //Detach the front-end => the printers end when all events are printed
+    for (runner, _) in &mut results {
+        runner.attach(ProbeLink::default());
+    }
+    for printer in printers {
+        let _ = printer.await;
+    }
+    signals.abort();
+
+    println!("\n{}", summary_table(&results));
+    results
+}
+
+//Format the result of a probe of a target => one line per probe like fping
+fn format_target_probe(target: &str, seq: u32, outcome: &ProbeOutcome, timeout: Duration) -> String {
+    match (&outcome.result, outcome.info.as_deref()) {
+        (ProbeResult::Reply(latency), info) => {
+            let detail: Vec<String> = info
+                .map(str::to_string)
+                .into_iter()
+                .chain(format_ttl(outcome))
+                .chain(format_timings(outcome))
+                .collect();
+            if detail.is_empty() {
+                format!("{} : [{}], {:.3} ms", target, seq, as_ms(*latency))
+            } else {
+                format!("{} : [{}], {:.3} ms ({})", target, seq, as_ms(*latency), detail.join(" "))
+            }
+        }
+        (ProbeResult::Timeout, _) => format!("{} : [{}], timeout ({:.3} ms)", target, seq, as_ms(timeout)),
+        (ProbeResult::Error(e), _) => format!("{} : [{}], error: {}", target, seq, e),
+    }
+}
+
+//Build the summary table of several targets => sent, received, loss and latencies in ms of each target, or its error
+fn summary_table<P: Probe>(results: &[(ProbeRunner<P>, Result<()>)]) -> String {
+    let width = results.iter().map(|(runner, _)| runner.target().len()).max().unwrap_or(0).max(6);
+    let ms = |x: Option<Duration>| x.map_or("-".to_string(), |x| format!("{:.3}", as_ms(x)));
+
+    let mut table = format!(
+        "{:<width$}  {:>5}  {:>5}  {:>7}  {:>9}  {:>9}  {:>9}  {:>9}  {:>9}\n",
+        "TARGET", "SENT", "RECV", "LOSS", "MIN", "AVG", "MAX", "P95", "JITTER",
+    );
+    for (runner, result) in results {
+        let stats = runner.statistics();
+        let line = match result {
+            Err(e) if stats.transmitted == 0 => format!("{:<width$}  error: {}", runner.target(), e),
+            _ => format!(
+                "{:<width$}  {:>5}  {:>5}  {:>6.2}%  {:>9}  {:>9}  {:>9}  {:>9}  {:>9}",
+                runner.target(),
+                stats.transmitted,
+                stats.received,
+                stats.loss_percent,
+                ms(stats.min),
+                ms(stats.avg),
+                ms(stats.max),
+                ms(stats.p95),
+                ms(stats.jitter),
+            ),
+        };
+        table.push_str(&line);
+        table.push('\n');
+    }
+    table
+}
+
+//Run traceroute with the cli front-end => one line per hop like traceroute
+//Ctrl-C, SIGINT and SIGTERM stop the trace, the hops already probed are kept for the export
+pub async fn run_traceroute(tool: &mut TracerouteTool) -> Result<()> {
+    println!("{}", tool.prepare().await?);
+
+    let stop = wait_signal();
+    tokio::pin!(stop);
+    loop {
+        tokio::select! {
+            hop = tool.next_hop() => match hop? {
+                Some(hop) => println!("{}", format_hop(hop)),
+                None => return Ok(()),
+            },
+            _ = &mut stop => return Ok(()),
+        }
+    }
+}
+
+//Run the path MTU discovery with the cli front-end => one line per size tried, then the report
+//Ctrl-C, SIGINT and SIGTERM stop the discovery, the report is done with the sizes already tried
+pub async fn run_pmtu(tool: &mut PmtuTool) -> Result<()> {
+    println!("{}", tool.prepare().await?);
+
+    let stop = wait_signal();
+    tokio::pin!(stop);
+    loop {
+        tokio::select! {
+            probe = tool.next_probe() => match probe? {
+                Some(probe) => println!("{}", probe),
+                None => break,
+            },
+            _ = &mut stop => break,
+        }
+    }
+
+    println!("{}", tool.report());
+    Ok(())
+}
+
+//Run the firewall analysis with the cli front-end => one line per probe, then the verdict with its evidence
+//Ctrl-C, SIGINT and SIGTERM stop the analysis, the verdict is given with the probes already done
+pub async fn run_firewall(tool: &mut FirewallTool) -> Result<()> {
+    println!("{}", tool.prepare().await?);
+
+    let stop = wait_signal();
+    tokio::pin!(stop);
+    loop {
+        tokio::select! {
+            probe = tool.next_probe() => match probe? {
+                Some(probe) => println!("{}", probe),
+                None => break,
+            },
+            _ = &mut stop => break,
+        }
+    }
+
+    println!("{}", tool.report());
+    Ok(())
+}
+
+//Run the port scan with the cli front-end => the open ports are printed when they answer, then the report
+//Ctrl-C, SIGINT and SIGTERM stop the scan, the report and the export are done with the ports already classified
+pub async fn run_scan(tool: &mut ScanTool) -> Result<()> {
+    println!("{}", tool.prepare().await?);
+
+    let stop = wait_signal();
+    tokio::pin!(stop);
+    loop {
+        tokio::select! {
+            result = tool.next_port() => match result? {
+                Some(result) if result.state == PortState::Open => println!("{}", result),
+                Some(_) => {}
+                None => break,
+            },
+            _ = &mut stop => break,
+        }
+    }
+
+    println!("{}", tool.report());
+    Ok(())
+}
+
+//Format a hop like traceroute => ttl, then each answering address with its name once and the RTT of its probes, * for a timeout
+fn format_hop(hop: &Hop) -> String {
+    let mut line = format!("{:>2} ", hop.ttl);
+    let mut last_from = None;
+
+    for reply in &hop.replies {
+        let Some(reply) = reply else {
+            line.push_str(" *");
+            continue;
+        };
+
+        if last_from != Some(reply.from) {
+            match &reply.name {
+                Some(name) => line.push_str(&format!(" {} ({})", name, reply.from)),
+                None => line.push_str(&format!(" {}", reply.from)),
+            }
+            last_from = Some(reply.from);
+        }
+        line.push_str(&format!("  {:.3} ms", as_ms(reply.rtt)));
+        if let Some(annotation) = reply.annotation() {
+            line.push_str(&format!(" {}", annotation));
+        }
+    }
+    line
+}
+
+//Run mtr with the cli front-end
+//With a terminal the hops are displayed in a live table, 'g' displays the graph of the selected hop, 'q' or Ctrl-C stop the run
+//Without terminal (pipe, systemd, ...) SIGINT and SIGTERM stop the run
+//The report with the statistics of each hop is printed at the end of the run
+pub async fn run_mtr(tool: &mut MtrTool) -> Result<()> {
+    let description = tool.prepare().await?;
+    println!("{}", description);
+
+    let (commands_tx, commands_rx) = mpsc::unbounded_channel();
+    tool.attach(commands_rx);
+
+    //The table is only displayed when stdin and stdout are a terminal
+    let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+    let table = interactive.then(|| {
+        let commands = commands_tx.clone();
+        std::thread::spawn(move || mtr_table_loop(&description, commands))
+    });
+
+    let signal_commands = commands_tx.clone();
+    let signals = tokio::spawn(async move {
+        wait_signal().await;
+        let _ = signal_commands.send(MtrCommand::Stop);
+
+        wait_signal().await;
+        let _ = disable_raw_mode();
+        std::process::exit(130);
+    });
+    drop(commands_tx);
+
+    let result = tool.run().await;
+
+    //Detach the front-end => the table sees the end of the run and quits
+    tool.detach();
+    if let Some(table) = table {
+        let _ = tokio::task::spawn_blocking(move || table.join()).await;
+    }
+    signals.abort();
+
+    println!("{}", mtr_report(&tool.hops()));
+    result
+}
+
+//Display the live table of mtr until the end of the run or until the user quits it => the run is then stopped
+fn mtr_table_loop(title: &str, commands: mpsc::UnboundedSender<MtrCommand>) {
+    let rows = || {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        commands.send(MtrCommand::Hops(reply_tx)).ok()?;
+        reply_rx.blocking_recv().ok().map(|hops| hops.iter().map(mtr_row).collect())
+    };
+
+    //Ask the sampled series of the hop then show its graph, at most one value per column of the terminal
+    let graph = |ttl: u8| {
+        let max_points = terminal::size().map_or(DEFAULT_GRAPH_POINTS, |(columns, _)| columns as usize);
+        let (reply_tx, reply_rx) = oneshot::channel();
+        if commands.send(MtrCommand::Series(ttl, max_points, reply_tx)).is_ok()
+            && let Ok(series) = reply_rx.blocking_recv()
+        {
+            graph_display(&series.min, &series.moy, &series.max, &series.p95, &series.jitter)?;
+        }
+        Ok(())
+    };
+
+    mtr_display(title, rows, graph).unwrap_or_else(|e| {
+        eprintln!("Error during mtr table building: {}", e);
+    });
+    let _ = commands.send(MtrCommand::Stop);
+}
+
+//Line of the mtr table of a hop
+fn mtr_row(hop: &MtrHopStats) -> MtrRow {
+    MtrRow {
+        hop: hop.ttl,
+        host: mtr_host(hop),
+        loss_percent: hop.stats.loss_percent,
+        sent: hop.stats.transmitted,
+        last: hop.last,
+        avg: hop.stats.avg,
+        best: hop.stats.min,
+        worst: hop.stats.max,
+        stddev: hop.stats.stddev,
+    }
+}
+
+//Host of a hop like mtr => name (ip) or ip, ??? if no probe was answered, with the annotation and the number of other addresses
+fn mtr_host(hop: &MtrHopStats) -> String {
+    let mut host = match (hop.from, &hop.name) {
+        (Some(from), Some(name)) => format!("{} ({})", name, from),
+        (Some(from), None) => from.to_string(),
+        (None, _) => String::from("???"),
+    };
+    if let Some(annotation) = &hop.annotation {
+        host.push_str(&format!(" {}", annotation));
+    }
+    if hop.others > 0 {
+        host.push_str(&format!(" (+{} other addresses)", hop.others));
+    }
+    host
+}
+
+//Build the report of mtr => one line per hop with its loss and latencies in ms like mtr --report
+fn mtr_report(hops: &[MtrHopStats]) -> String {
+    let hosts: Vec<String> = hops.iter().map(mtr_host).collect();
+    let width = hosts.iter().map(|x| x.len()).max().unwrap_or(0).max(4);
+    let ms = |x: Option<Duration>| x.map_or("-".to_string(), |x| format!("{:.3}", as_ms(x)));
+
+    let mut report = format!(
+        "{:>4}  {:<width$}  {:>7}  {:>5}  {:>9}  {:>9}  {:>9}  {:>9}  {:>9}\n",
+        "HOP", "HOST", "LOSS", "SNT", "LAST", "AVG", "BEST", "WRST", "STDEV",
+    );
+    for (hop, host) in hops.iter().zip(&hosts) {
+        report.push_str(&format!(
+            "{:>3}.  {:<width$}  {:>6.2}%  {:>5}  {:>9}  {:>9}  {:>9}  {:>9}  {:>9}\n",
+            hop.ttl,
+            host,
+            hop.stats.loss_percent,
+            hop.stats.transmitted,
+            ms(hop.last),
+            ms(hop.stats.avg),
+            ms(hop.stats.min),
+            ms(hop.stats.max),
+            ms(hop.stats.stddev),
+        ));
+    }
+    report
+}
+
+//Capture the keyboard in raw mode => 'g' to display the graph, Ctrl-C to stop the run
+fn keyboard_loop(
+    commands: mpsc::UnboundedSender<ProbeCommand>,
+    graph_open: Arc<AtomicBool>,
+    finished: Arc<AtomicBool>,
+) {
+    while !finished.load(Ordering::Relaxed) {
+        //Enable raw terminal to capture correctly input from user, the graph disables it when it is closed
+        if enable_raw_mode().is_err() {
+            return;
+        }
+
+        if !matches!(event::poll(Duration::from_millis(100)), Ok(true)) {
+            continue;
+        }
+        let Ok(Event::Key(key_event)) = event::read() else {
+            continue;
+        };
+
+        match (key_event.code, key_event.modifiers) {
+            (KeyCode::Char('g'), KeyModifiers::NONE) => {
+                graph_open.store(true, Ordering::Relaxed);
+
+                //Ask the latest sampled series to the tool then show the graph (blocking UI)
+                //At most one value per column of the terminal => the store picks the bucket resolution
+                let max_points = terminal::size().map_or(DEFAULT_GRAPH_POINTS, |(columns, _)| columns as usize);
+                let (reply_tx, reply_rx) = oneshot::channel();
+                if commands.send(ProbeCommand::Snapshot(max_points, reply_tx)).is_ok()
+                    && let Ok(series) = reply_rx.blocking_recv()
+                {
+                    graph_display(&series.min, &series.moy, &series.max, &series.p95, &series.jitter)
+                        .unwrap_or_else(|e| {
+                            eprintln!("Error during graph building: {}", e);
+                        });
+                }
+
+                let _ = enable_raw_mode();
+                graph_open.store(false, Ordering::Relaxed);
+            }
+            //If the user do Control C the run will be stopped
+            (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                let _ = commands.send(ProbeCommand::Stop);
+            }
+            _ => {}
+        }
+    }
+
+    let _ = disable_raw_mode();
+}
+
+//Stop the runs on SIGINT or SIGTERM, a second signal quits immediately
+async fn stop_on_signal(commands: Vec<mpsc::UnboundedSender<ProbeCommand>>) {
+    wait_signal().await;
+    for command in &commands {
+        let _ = command.send(ProbeCommand::Stop);
+    }
+
+    wait_signal().await;
+    let _ = disable_raw_mode();
+    std::process::exit(130);
+}
+
+#[cfg(unix)]
+async fn wait_signal() {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut sigterm) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = sigterm.recv() => {}
+            }
+        }
+        Err(_) => {
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+//Print an event of a tool
+fn print_event(name: &str, event: &ProbeEvent, timeout: Duration, raw_mode: bool) {
+    let text = match event {
+        ProbeEvent::Start(description) => format!("{}\n", description),
+        ProbeEvent::Probe { seq, outcome } => format_probe(name, *seq, outcome, timeout),
+        ProbeEvent::RouteChange { .. } => format!("{}\n", format_route_change(name, event)),
+    };
+
+    //In raw mode \n does not move the cursor back to the start of the line
+    let text = if raw_mode { text.replace('\n', "\r\n") } else { text };
+    print!("{}", text);
+    let _ = io::stdout().flush();
+}
+
+//Format the result of a probe depending of the tool
+fn format_probe(name: &str, seq: u32, outcome: &ProbeOutcome, timeout: Duration) -> String {
+    let info = outcome.info.as_deref().unwrap_or_default();
+
+    let text = match (name, &outcome.result) {
+        ("ping", ProbeResult::Reply(latency)) => format_echo_reply(outcome, *latency),
+        ("ping", ProbeResult::Timeout) => format!("Request timeout for icmp_seq={} ({:.3} ms)\n", seq as u16, as_ms(timeout)),
+        ("ping", ProbeResult::Error(ProbeError::Icmp(e))) => format!("icmp_seq={} {}\n", seq as u16, e),
+        ("ping", ProbeResult::Error(e)) => format!("icmp_seq={} error: {}\n", seq as u16, e),
+        ("tping", ProbeResult::Reply(latency)) => match format_ttl(outcome) {
+            Some(ttl) => format!("[{}] Reply in {:.3} ms - {} {}\n", seq + 1, as_ms(*latency), info, ttl),
+            None => format!("[{}] Reply in {:.3} ms - {}\n", seq + 1, as_ms(*latency), info),
+        },
+        ("tping", ProbeResult::Timeout) => format!("[{}] Timeout ({:.3} ms)\n", seq + 1, as_ms(timeout)),
+        ("tping", ProbeResult::Error(e)) => format!(
+            "[{}] Error: {}\n",
+            seq + 1, if info.is_empty() { e.to_string() } else { info.to_string() }
+        ),
+        ("tls", ProbeResult::Reply(latency)) => match format_timings(outcome) {
+            Some(phases) => format!("[{}] Handshake in {:.3} ms - {} ({})\n", seq + 1, as_ms(*latency), info, phases),
+            None => format!("[{}] Handshake in {:.3} ms - {}\n", seq + 1, as_ms(*latency), info),
+        },
+        ("tls", ProbeResult::Timeout) => match format_timings(outcome) {
+            Some(phases) => format!("[{}] Timeout ({:.3} ms) after {}\n", seq + 1, as_ms(timeout), phases),
+            None => format!("[{}] Timeout ({:.3} ms)\n", seq + 1, as_ms(timeout)),
+        },
+        ("tls", ProbeResult::Error(e)) => format!(
+            "[{}] Error: {}\n",
+            seq + 1, if info.is_empty() { e.to_string() } else { info.to_string() }
+        ),
+        ("http", ProbeResult::Reply(latency)) => {
+            let size = outcome.reply.bytes.unwrap_or_default();
+            match format_timings(outcome) {
+                Some(phases) => format!("[{}] {} - {} bytes in {:.3} ms ({})\n", seq + 1, info, size, as_ms(*latency), phases),
+                None => format!("[{}] {} - {} bytes in {:.3} ms\n", seq + 1, info, size, as_ms(*latency)),
+            }
+        }
+        ("http", ProbeResult::Timeout) => match format_timings(outcome) {
+            Some(phases) => format!("[{}] Timeout ({:.3} ms) after {}\n", seq + 1, as_ms(timeout), phases),
+            None => format!("[{}] Timeout ({:.3} ms)\n", seq + 1, as_ms(timeout)),
+        },
+        ("http", ProbeResult::Error(e)) => format!(
+            "[{}] Error: {}\n",
+            seq + 1, if info.is_empty() { e.to_string() } else { info.to_string() }
+        ),
+        ("resolver", ProbeResult::Reply(latency)) => format!(
+            "DNS request n°{} DNS request latency: {:.3} ms\nDNS query result: {}\n\n",
+            seq, as_ms(*latency), info
+        ),
+        ("resolver", ProbeResult::Timeout) => format!("DNS request n°{} DNS lookup timeout ({:.3} ms)\n\n", seq, as_ms(timeout)),
+        ("resolver", ProbeResult::Error(e)) => format!(
+            "DNS request n°{} DNS lookup error: {}\n\n",
+            seq, if info.is_empty() { e.to_string() } else { info.to_string() }
+        ),
+        (_, result) => format!("{} n°{}: {}\n", name, seq, result.status()),
+    };
+
+    //Replies that do not answer this probe => like the DUP! of ping
+    let mut notes = String::new();
+    if outcome.duplicates > 0 {
+        notes.push_str(&format!("(+{} DUP!) ", outcome.duplicates));
+    }
+    if outcome.out_of_order > 0 {
+        notes.push_str(&format!("({} late replies of previous pings) ", outcome.out_of_order));
+    }
+    if notes.is_empty() { text } else { format!("{}\n{}", notes.trim_end(), text) }
+}
+
+//Format an echo reply like ping => size, source, icmp_seq, ttl and latency
+fn format_echo_reply(outcome: &ProbeOutcome, latency: Duration) -> String {
+    let reply = &outcome.reply;
+    let mut text = String::new();
+
+    if let Some(bytes) = reply.bytes {
+        text.push_str(&format!("{} bytes ", bytes));
+    }
+    if let Some(from) = reply.from {
+        text.push_str(&format!("from {}: ", from));
+    }
+    if let Some(seq) = reply.seq {
+        text.push_str(&format!("icmp_seq={} ", seq));
+    }
+    if let Some(ttl) = format_ttl(outcome) {
+        text.push_str(&format!("{} ", ttl));
+    }
+    format!("{}time={:.3} ms\n", text, as_ms(latency))
+}
+
+//Format the TTL of a reply with the number of routers it crossed => "ttl=61 hops=3", None without TTL
+fn format_ttl(outcome: &ProbeOutcome) -> Option<String> {
+    Some(format!("ttl={} hops={}", outcome.reply.ttl?, outcome.reply.hops()?))
+}
+
+//Format the phases of a probe like curl -w => "dns=1.203 connect=12.410 ttfb=45.872 ms", None without phase
+fn format_timings(outcome: &ProbeOutcome) -> Option<String> {
+    let phases: Vec<String> = outcome
+        .reply
+        .timings?
+        .phases()
+        .into_iter()
+        .filter_map(|(name, phase)| Some(format!("{}={:.3}", name, as_ms(phase?))))
+        .collect();
+    (!phases.is_empty()).then(|| format!("{} ms", phases.join(" ")))
+}
+
+//Format the alert of a route change => the hop count of the replies changed during the run
+//The probe is numbered like the lines of the tool => icmp_seq for ping
+fn format_route_change(name: &str, event: &ProbeEvent) -> String {
+    let ProbeEvent::RouteChange { seq, previous, hops, ttl } = event else {
+        return String::new();
+    };
+    let probe = if name == "ping" { format!("icmp_seq={}", *seq as u16) } else { format!("probe {}", seq + 1) };
+    format!("Route change at {}: hop count {} -> {} (ttl={}, initial ttl {})", probe, previous, hops, ttl, initial_ttl(*ttl))
+}
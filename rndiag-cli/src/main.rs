@@ -1,26 +1,43 @@
 use argh::FromArgs;
+use rndiag_core::error::Error;
+use rndiag_core::firewall::FirewallTool;
+use rndiag_core::http::HttpTool;
+use rndiag_core::icmp::DEFAULT_PAYLOAD_SIZE;
 use rndiag_core::nslookup::NSlookup;
 use rndiag_core::ping::PingTool;
+use rndiag_core::pmtu::PmtuTool;
+use rndiag_core::scan::{DEFAULT_SCAN_RATE, ScanTool};
+use rndiag_core::socket::SocketOptions;
 use rndiag_core::speedtest::SpeedTest;
 use rndiag_core::tcp_message;
-use rndiag_core::tcp_ping::TCPPingTool;
+use rndiag_core::tcp_ping::{TCPPingTool, TcpOptions, TcpPingMode};
+use rndiag_core::tls::TlsTool;
 use rndiag_core::tool::ConnectTool;
-use rndiag_core::tool::LatencyTool;
+use rndiag_core::traceroute::{DEFAULT_MAX_HOPS, DEFAULT_QUERIES, TraceOptions, TracerouteTool};
+use rndiag_core::mtr::MtrTool;
+use rndiag_core::tool::Probe;
+use rndiag_core::tool::ProbeRunner;
+use rndiag_core::tool::CsvExport;
+use rndiag_core::tool::ProbeOptions;
 use rndiag_server::{self, web_server};
 use std::thread::sleep;
-use tokio;
 use tokio::time::Duration;
 
 mod diagnostic;
+mod interactive;
 mod sanitizer;
 
 #[derive(FromArgs)]
 ///reach new args
 struct Args {
     #[argh(option, short = 'd', default = r#"String::from("none")"#)]
-    ///destination server ip or name
+    ///destination server ip or name, for ping, tcp ping, resolver and tls a list separated by commas or a CIDR range is also accepted
     dst: String,
 
+    #[argh(option, short = 'F', default = r#"String::from("none")"#)]
+    ///file with the destinations of ping, tcp ping, resolver and tls, one ip, name or CIDR range per line
+    file: String,
+
     #[argh(option, short = 'c', default = "0")]
     ///stop after <count> replies
     count: u32,
@@ -54,9 +71,17 @@ struct Args {
     bitrate: u64,
 
     #[argh(option, short = 'f', default = r#"String::from("none")"#)]
-    ///tcp flag for tcp_ping. S => SYN, A => ACK, R => RST, F => FIN, P => PUSH, U => URG
+    ///tcp flags for tcp_ping, the letters are combined. S => SYN, A => ACK, R => RST, F => FIN, P => PUSH, U => URG, E => ECE (+ CWR on a SYN), C => CWR, N alone => no flag, ex: -f SA, -f FPU
     flag: String,
 
+    #[argh(option, default = r#"String::from("none")"#)]
+    ///tcp options of the tcp_ping segments separated by commas, mss=<bytes>, wscale=<0-14>, sack, ts, ex: --tcp-options mss=1460,sack,ts,wscale=7
+    tcp_options: String,
+
+    #[argh(option, default = "false")]
+    ///tcp ping with connect() instead of a raw segment, --connect true => latency of the handshake, no root or CAP_NET_RAW needed
+    connect: bool,
+
     #[argh(option, short = 'D', default = r#"String::from("none")"#)]
     ///quick network diagnostics (ping latency, resolution latency, tcp_ping). to use diagnostic -D => True
     ///Usage: rndiag -D <speedtestSrv> -d => specify specific server to resolve and to contact for ping and tcp_ ping, -p => specify specific port to contact for tcp_ping
@@ -84,6 +109,74 @@ struct Args {
     /// on server side -S + -s true + specify the listening addr -d + the listening port -p
     nc: bool,
 
+    #[argh(option, default = "false")]
+    ///to use traceroute, --traceroute true + specify destination -d, the probes are chosen with --trace-mode
+    traceroute: bool,
+
+    #[argh(option, default = "false")]
+    ///to use mtr, --mtr true + specify destination -d, each hop is probed every -i ms with the probes of --trace-mode
+    mtr: bool,
+
+    #[argh(option, default = "false")]
+    ///to use path MTU discovery, --pmtu true + specify destination -d, finds the largest packet not fragmented and the MTU black holes
+    pmtu: bool,
+
+    #[argh(option, default = "false")]
+    ///to use the tcp port scan, --scan true + specify destination -d and the ports --ports, each port is classified open, closed or filtered
+    scan: bool,
+
+    #[argh(option, default = r#"String::from("none")"#)]
+    ///ports of the scan, ports and ranges separated by commas, ex: --ports 1-1024, --ports 22,80,443,8000-8100
+    ports: String,
+
+    #[argh(option, default = "DEFAULT_SCAN_RATE")]
+    ///number of SYN sent per second by the scan, default 100
+    rate: u32,
+
+    #[argh(option, default = "false")]
+    ///to use the firewall analysis, --firewall true + specify destination -d and port -p, SYN, ACK, FIN, null and Xmas probes give a verdict on the filters and middleboxes of the path
+    firewall: bool,
+
+    #[argh(option, default = "false")]
+    ///to use the tls probe, --tls true + specify destination -d and port -p (443 by default), each probe connects and does a TLS handshake, with --exporter the destinations are also probed with TLS on port 443
+    tls: bool,
+
+    #[argh(option, default = r#"String::from("none")"#)]
+    ///server name sent in the TLS handshake and checked in the certificate, default the destination if it is a name
+    sni: String,
+
+    #[argh(option, default = r#"String::from("none")"#)]
+    ///protocols offered with ALPN in the TLS handshake separated by commas, ex: --alpn h2,http/1.1
+    alpn: String,
+
+    #[argh(option, default = "false")]
+    ///accept any certificate in the TLS handshake of tls and of https urls of http => expired, self-signed or of another name, --insecure true
+    insecure: bool,
+
+    #[argh(option, default = "false")]
+    ///to use the http probe, --http true + specify the urls --url, each probe fetches the url and measures dns, connect, tls, time to first byte and total time
+    http: bool,
+
+    #[argh(option, default = r#"String::from("none")"#)]
+    ///urls of the http probe separated by commas, http://host[:port][/path] or https://host[:port][/path], with --exporter the urls are also probed by the exporter
+    url: String,
+
+    #[argh(option, default = r#"String::from("icmp")"#)]
+    ///probes of traceroute and mtr, icmp => echo requests, udp => datagrams to port 33434 and more, tcp => SYN to port 80 or -p
+    trace_mode: String,
+
+    #[argh(option, default = "DEFAULT_MAX_HOPS")]
+    ///maximum number of hops of traceroute and mtr, default 30
+    max_hops: u8,
+
+    #[argh(option, default = "DEFAULT_QUERIES")]
+    ///number of probes per hop of traceroute, default 3
+    queries: u8,
+
+    #[argh(option, default = "true")]
+    ///reverse DNS of the hops of traceroute and mtr, default true
+    rdns: bool,
+
     #[argh(option, default = "false")]
     /// provide a web-page with metrics tht can be scrapped by prometheus/grafana, --exporter true
     exporter: bool,
@@ -95,6 +188,46 @@ struct Args {
     #[argh(option, default = "0")]
     /// port of the web-server for exporter mode
     ws_port: u16,
+
+    #[argh(option, short = 'i', default = "500")]
+    ///time in ms between two probes for ping, tcp ping, resolver, tls and http, default 500
+    interval: u64,
+
+    #[argh(option, short = 'W', default = "5000")]
+    ///time in ms to wait for a reply before considering the probe as a timeout, default 5000
+    timeout: u64,
+
+    #[argh(option, short = 'w', default = "0")]
+    ///total run time in secs of ping, tcp ping, resolver, tls and http, default 0 for no deadline
+    deadline: u64,
+
+    #[argh(option, default = "DEFAULT_PAYLOAD_SIZE")]
+    ///number of data bytes of each ping echo request, default 56
+    size: usize,
+
+    #[argh(option, default = r#"String::from("none")"#)]
+    ///hex pattern of up to 16 bytes used to fill the data of each ping echo request, ex: --pattern ff00
+    pattern: String,
+
+    #[argh(option, default = r#"String::from("none")"#)]
+    ///TOS byte (IPv4) or traffic class (IPv6) of the packets of ping, tcp ping, resolver, tls, http, scan, speedtest and nc, decimal or hex, ex: --tos 0xb8
+    tos: String,
+
+    #[argh(option, default = r#"String::from("none")"#)]
+    ///DSCP of the packets of ping, tcp ping, resolver, tls, http, scan, speedtest and nc, 0 to 63 or a class, ex: --dscp ef, --dscp af41, --dscp cs1
+    dscp: String,
+
+    #[argh(option, default = "0")]
+    ///TTL (IPv4) or hop limit (IPv6) of the packets of ping, tcp ping, resolver, tls, http, scan, speedtest and nc, default 0 for the system value
+    ttl: u8,
+
+    #[argh(option, default = r#"String::from("none")"#)]
+    ///source address of the packets of ping, tcp ping, resolver, tls, http, scan, speedtest and nc client, must be an address of the host
+    source: String,
+
+    #[argh(option, default = r#"String::from("none")"#)]
+    ///interface used to send the packets of ping, tcp ping, resolver, tls, http, scan, speedtest and nc whatever the routes, ex: --interface eth1
+    interface: String,
 }
 #[allow(unused_assignments)]
 #[tokio::main]
@@ -111,14 +244,26 @@ async fn main() -> anyhow::Result<()> {
     //Warn the user if useless option depending of the tool are set
     sanitizer::useless_options(&selected_tool, &options);
 
+    //Check and build the interval/timeout/deadline options of the latency tools
+    sanitizer::timing_check(&options);
+    let probe_options: ProbeOptions = sanitizer::probe_options_format(&options);
+
+    //Check and build the TOS/TTL/source/interface options of the packets
+    let socket_options: SocketOptions = sanitizer::socket_options_format(&options);
+
     //We check later the addr given by the user if the user choose diagnostic
     //We cannot check here the addr given by the user because if it is not the case rndiag set a default destination
-    if selected_tool != "diagnostic" {
+    //Ping, tcp ping, resolver, tls and exporter accept several destinations => checked with the targets
+    //The http probe has urls instead of destinations
+    if !matches!(selected_tool.as_str(), "diagnostic" | "exporter" | "ping" | "tping" | "resolver" | "tls" | "http") {
         sanitizer::addr_check(&options.dst);
     }
     //If exporter option is chosen by the user
-    if options.exporter == true {
-        sanitizer::addr_check(&options.dst);
+    if options.exporter {
+        let targets = sanitizer::targets_format(&options);
+        //--tls true adds the TLS probe to the tools of the exporter
+        let tls_options = options.tls.then(|| sanitizer::tls_options_format(&options));
+        let urls = if options.url != "none" { sanitizer::urls_format(&options.url) } else { Vec::new() };
 
         let flag: String = String::from("S");
 
@@ -128,7 +273,9 @@ async fn main() -> anyhow::Result<()> {
 
         web_server::launch_srv(
             120,
-            &options.dst,
+            &targets,
+            tls_options,
+            &urls,
             &options.ws_addr,
             options.port,
             options.ws_port,
@@ -136,66 +283,142 @@ async fn main() -> anyhow::Result<()> {
             &options.output,
             6,
             flag_u8,
+            probe_options,
         )
-        .await;
+        .await
+        .unwrap_or_else(|e| exit_on_error(e));
     } else if selected_tool == "ping" {
         //Sanitization + data conformity checking
-        sanitizer::addr_check(&options.dst);
+        let targets = sanitizer::targets_format(&options);
         sanitizer::output_check(&options.output);
+        sanitizer::size_check(options.size);
+        let pattern = sanitizer::pattern_format(&options.pattern);
 
-        //Create PingTool object and init it with the new() method
-        let mut ping_tool = PingTool::new(&options.dst, &options.output, options.count);
-
-        //Run the PingTool object
-        ping_tool.run().await.unwrap_or_else(|e| {
-            eprintln!("Error during rndiag launching: {}", e);
-        });
+        //Create one PingTool object per destination and init it with the new() method
+        let tools = targets
+            .iter()
+            .map(|dst| ProbeRunner::new(PingTool::new(dst, options.size, &pattern, socket_options.clone()), &options.output, options.count, probe_options))
+            .collect();
 
-        //If the user was defined something for output it means he want to have an output
-        if &options.output != "AjaNuP123YuL903nNNaZY" {
-            //Call export_csv method inerhited of the trait of the object to save results in csv file
-            if let Err(e) = ping_tool.export_csv() {
-                eprintln!("Export CSV error: {}", e);
-            }
-        }
+        //Run the PingTool objects with the cli front-end
+        run_latency(tools, &options.output).await;
     } else if selected_tool == "resolver" {
-        sanitizer::addr_check(&options.dst);
+        let targets = sanitizer::targets_format(&options);
         sanitizer::output_check(&options.output);
-        let mut nslookup_tool = NSlookup::new(&options.dst, &options.output, options.count);
+        let tools = targets
+            .iter()
+            .map(|dst| ProbeRunner::new(NSlookup::new(dst, socket_options.clone()), &options.output, options.count, probe_options))
+            .collect();
 
-        nslookup_tool.run().await.unwrap_or_else(|e| {
-            eprintln!("Error during rndiag launching: {}", e);
-        });
+        run_latency(tools, &options.output).await;
+    } else if selected_tool == "tping" {
+        let targets = sanitizer::targets_format(&options);
+        sanitizer::output_check(&options.output);
 
-        if &options.output != "AjaNuP123YuL903nNNaZY" {
-            if let Err(e) = nslookup_tool.export_csv() {
-                eprintln!("Erreur d'export CSV : {}", e);
-            }
+        //The connect mode needs no flag, the kernel sends the SYN
+        let mode = if options.connect {
+            TcpPingMode::Connect
+        } else {
+            sanitizer::flag_check(&options.flag);
+            flag_u8 = sanitizer::flag_format(&options.flag).ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid TCP flag")
+            })?;
+            TcpPingMode::Raw(flag_u8, sanitizer::tcp_options_format(&options.tcp_options))
+        };
+
+        let tools = targets
+            .iter()
+            .map(|dst| ProbeRunner::new(TCPPingTool::new(dst, options.port, mode, socket_options.clone()), &options.output, options.count, probe_options))
+            .collect();
+
+        run_latency(tools, &options.output).await;
+    } else if selected_tool == "tls" {
+        let targets = sanitizer::targets_format(&options);
+        sanitizer::output_check(&options.output);
+        let tls_options = sanitizer::tls_options_format(&options);
+        let tools = targets
+            .iter()
+            .map(|dst| ProbeRunner::new(TlsTool::new(dst, options.port, tls_options.clone(), socket_options.clone()), &options.output, options.count, probe_options))
+            .collect();
+
+        run_latency(tools, &options.output).await;
+    } else if selected_tool == "http" {
+        let urls = sanitizer::urls_format(&options.url);
+        sanitizer::output_check(&options.output);
+        let tools = urls
+            .iter()
+            .map(|url| ProbeRunner::new(HttpTool::new(url, options.insecure, socket_options.clone()), &options.output, options.count, probe_options))
+            .collect();
+
+        run_latency(tools, &options.output).await;
+    } else if selected_tool == "traceroute" {
+        sanitizer::addr_check(&options.dst);
+        sanitizer::output_check(&options.output);
+        sanitizer::hops_check(options.max_hops, options.queries);
+
+        let trace_options = TraceOptions {
+            mode: sanitizer::trace_mode_format(&options.trace_mode),
+            port: options.port,
+            max_hops: options.max_hops,
+            queries: options.queries,
+            timeout: probe_options.timeout,
+            resolve_names: options.rdns,
+        };
+        let mut traceroute = TracerouteTool::new(&options.dst, trace_options, &options.output);
+        let result = interactive::run_traceroute(&mut traceroute).await;
+
+        //Export the hops probed even if the trace was stopped by the user or an error
+        if options.output != "AjaNuP123YuL903nNNaZY" && let Err(e) = traceroute.export() {
+            eprintln!("Export error: {}", e);
+            std::process::exit(exit_code(&e));
         }
-    } else if selected_tool == "tping" {
+        result.unwrap_or_else(|e| exit_on_error(e));
+    } else if selected_tool == "mtr" {
         sanitizer::addr_check(&options.dst);
-        sanitizer::flag_check(&options.flag);
         sanitizer::output_check(&options.output);
-        flag_u8 = sanitizer::flag_format(&options.flag).ok_or_else(|| {
-            std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid TCP flag")
-        })?;
+        sanitizer::hops_check(options.max_hops, DEFAULT_QUERIES);
+
+        let trace_options = TraceOptions {
+            mode: sanitizer::trace_mode_format(&options.trace_mode),
+            port: options.port,
+            max_hops: options.max_hops,
+            queries: 1,
+            timeout: probe_options.timeout,
+            resolve_names: options.rdns,
+        };
+        let mut mtr = MtrTool::new(&options.dst, trace_options, probe_options.interval, options.count, &options.output);
+        let result = interactive::run_mtr(&mut mtr).await;
+
+        if options.output != "AjaNuP123YuL903nNNaZY" && let Err(e) = mtr.export() {
+            eprintln!("Export error: {}", e);
+            std::process::exit(exit_code(&e));
+        }
+        result.unwrap_or_else(|e| exit_on_error(e));
+    } else if selected_tool == "pmtu" {
+        sanitizer::addr_check(&options.dst);
 
-        let mut tcpping = TCPPingTool::new(
-            &options.dst,
-            &options.output,
-            options.count,
-            options.port,
-            flag_u8,
-        );
-        tcpping.run().await.unwrap_or_else(|e| {
-            eprintln!("Error during rndiag launching: {}", e);
-        });
+        let mut pmtu = PmtuTool::new(&options.dst, probe_options.timeout);
+        interactive::run_pmtu(&mut pmtu).await.unwrap_or_else(|e| exit_on_error(e));
+    } else if selected_tool == "scan" {
+        sanitizer::addr_check(&options.dst);
+        sanitizer::output_check(&options.output);
+        sanitizer::rate_check(options.rate);
+        let ports = sanitizer::ports_format(&options.ports);
 
-        if &options.output != "AjaNuP123YuL903nNNaZY" {
-            if let Err(e) = tcpping.export_csv() {
-                eprintln!("Erreur d'export CSV : {}", e);
-            }
+        let mut scan = ScanTool::new(&options.dst, ports, options.rate, probe_options.timeout, &options.output, socket_options.clone());
+        let result = interactive::run_scan(&mut scan).await;
+
+        //Export the ports classified even if the scan was stopped by the user or an error
+        if options.output != "AjaNuP123YuL903nNNaZY" && let Err(e) = scan.export() {
+            eprintln!("Export error: {}", e);
+            std::process::exit(exit_code(&e));
         }
+        result.unwrap_or_else(|e| exit_on_error(e));
+    } else if selected_tool == "firewall" {
+        sanitizer::addr_check(&options.dst);
+
+        let mut firewall = FirewallTool::new(&options.dst, options.port, probe_options.timeout);
+        interactive::run_firewall(&mut firewall).await.unwrap_or_else(|e| exit_on_error(e));
     } else if selected_tool == "sptest" {
         sanitizer::addr_check(&options.dst);
         sanitizer::mode_check(&options.mode);
@@ -207,17 +430,14 @@ async fn main() -> anyhow::Result<()> {
             options.server,
             options.time,
             options.bitrate,
+            socket_options,
         );
-        speed_test.run().await.unwrap_or_else(|e| {
-            eprintln!("Error during rndiag launching: {}", e);
-        });
+        speed_test.run().await.unwrap_or_else(|e| exit_on_error(e));
     } else if selected_tool == "nc" {
         sanitizer::addr_check(&options.dst);
 
-        let mut nc = tcp_message::TCPMessage::new(options.dst, options.port, options.server);
-        nc.run().await.unwrap_or_else(|e| {
-            eprintln!("Error during rndiag launching: {}", e);
-        });
+        let mut nc = tcp_message::TCPMessage::new(options.dst, options.port, options.server, socket_options);
+        nc.run().await.unwrap_or_else(|e| exit_on_error(e));
     } else if selected_tool == "diagnostic" {
         if options.dst == "none" {
             //If the user dosen't given the IP + Address, we set a dst addr and dst port by default (cloudflare) + flag by default
@@ -231,40 +451,47 @@ async fn main() -> anyhow::Result<()> {
             })?;
 
             //Create objects that will be used for the diagnostic
-            let mut dping = PingTool::new(&dst, "none", 6);
-            let mut dtping = TCPPingTool::new(&dst, "none", 6, port, flag_u8);
-            let mut dresolver = NSlookup::new(&dst, "none", 6);
+            let mut dping = ProbeRunner::new(PingTool::new(&dst, DEFAULT_PAYLOAD_SIZE, &[], SocketOptions::default()), "none", 6, probe_options);
+            let mut dtping = ProbeRunner::new(TCPPingTool::new(&dst, port, TcpPingMode::Raw(flag_u8, TcpOptions::default()), SocketOptions::default()), "none", 6, probe_options);
+            let mut dresolver = ProbeRunner::new(NSlookup::new(&dst, SocketOptions::default()), "none", 6, probe_options);
 
             //Run each object tool
-            dping.run().await.unwrap_or_else(|e| {
+            interactive::run_latency_tool(&mut dping).await.unwrap_or_else(|e| {
                 eprintln!("Error during rndiag launching: {}", e);
             });
             sleep(Duration::from_millis(1000));
-            dtping.run().await.unwrap_or_else(|e| {
+            interactive::run_latency_tool(&mut dtping).await.unwrap_or_else(|e| {
                 eprintln!("Error during rndiag launching: {}", e);
             });
             sleep(Duration::from_millis(1000));
-            dresolver.run().await.unwrap_or_else(|e| {
+            interactive::run_latency_tool(&mut dresolver).await.unwrap_or_else(|e| {
                 eprintln!("Error during rndiag launching: {}", e);
             });
 
             let mut zero_vec: Vec<u8> = Vec::new();
             let mut latency_max_vec: Vec<u8> = Vec::new();
 
+            //Get the statistics of each object tool
+            let dping_stats = dping.statistics();
+            let dtping_stats = dtping.statistics();
+            let dresolver_stats = dresolver.statistics();
+
             //Store the number of packet received of each object tool
-            zero_vec.push(diagnostic::packet_received(&dping.data()));
-            zero_vec.push(diagnostic::packet_received(&dtping.data()));
-            zero_vec.push(diagnostic::packet_received(&dresolver.data()));
+            zero_vec.push(diagnostic::packet_received(&dping_stats));
+            zero_vec.push(diagnostic::packet_received(&dtping_stats));
+            zero_vec.push(diagnostic::packet_received(&dresolver_stats));
+
+            //Store each latency moy of each tools (6 pings currently)
+            latency_max_vec.push(diagnostic::packet_latency(dping_stats.avg));
+            latency_max_vec.push(diagnostic::packet_latency(dtping_stats.avg));
+            latency_max_vec.push(diagnostic::packet_latency(dresolver_stats.avg));
 
-            //Store each latency moy sampled during the execution of each tools (6 pings currently)
-            latency_max_vec.push(diagnostic::packet_latency(&dping.latency_moy_sampled()[0]));
-            latency_max_vec.push(diagnostic::packet_latency(&dtping.latency_moy_sampled()[0]));
-            latency_max_vec.push(diagnostic::packet_latency(
-                &dresolver.latency_moy_sampled()[0],
-            ));
+            //Path MTU with a short timeout => a black hole makes the small probes work and large transfers hang
+            let dpmtu = PmtuTool::new(&dst, Duration::from_millis(1000)).run().await;
 
             //Call the function that format correctly in cli diagnostic result
             diagnostic::output_format(&latency_max_vec, &zero_vec);
+            diagnostic::pmtu_format(&dpmtu);
         } else {
             //The case if the user given addr + port
             sanitizer::addr_check(&options.dst);
@@ -276,37 +503,91 @@ async fn main() -> anyhow::Result<()> {
                 std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid TCP flag")
             })?;
 
-            let mut dping = PingTool::new(&options.dst, "none", 6);
-            let mut dtping = TCPPingTool::new(&options.dst, "none", 6, options.port, flag_u8);
-            let mut dresolver = NSlookup::new(&options.dst, "none", 6);
+            let mut dping = ProbeRunner::new(PingTool::new(&options.dst, DEFAULT_PAYLOAD_SIZE, &[], SocketOptions::default()), "none", 6, probe_options);
+            let mut dtping = ProbeRunner::new(TCPPingTool::new(&options.dst, options.port, TcpPingMode::Raw(flag_u8, TcpOptions::default()), SocketOptions::default()), "none", 6, probe_options);
+            let mut dresolver = ProbeRunner::new(NSlookup::new(&options.dst, SocketOptions::default()), "none", 6, probe_options);
 
-            dping.run().await.unwrap_or_else(|e| {
+            interactive::run_latency_tool(&mut dping).await.unwrap_or_else(|e| {
                 eprintln!("Error during rndiag launching: {}", e);
             });
             sleep(Duration::from_millis(1000));
-            dtping.run().await.unwrap_or_else(|e| {
+            interactive::run_latency_tool(&mut dtping).await.unwrap_or_else(|e| {
                 eprintln!("Error during rndiag launching: {}", e);
             });
             sleep(Duration::from_millis(1000));
-            dresolver.run().await.unwrap_or_else(|e| {
+            interactive::run_latency_tool(&mut dresolver).await.unwrap_or_else(|e| {
                 eprintln!("Error during rndiag launching: {}", e);
             });
 
             let mut zero_vec: Vec<u8> = Vec::new();
             let mut latency_max_vec: Vec<u8> = Vec::new();
 
-            zero_vec.push(diagnostic::packet_received(&dping.data()));
-            zero_vec.push(diagnostic::packet_received(&dtping.data()));
-            zero_vec.push(diagnostic::packet_received(&dresolver.data()));
+            //Get the statistics of each object tool
+            let dping_stats = dping.statistics();
+            let dtping_stats = dtping.statistics();
+            let dresolver_stats = dresolver.statistics();
 
-            latency_max_vec.push(diagnostic::packet_latency(&dping.latency_max_sampled()[0]));
-            latency_max_vec.push(diagnostic::packet_latency(&dtping.latency_max_sampled()[0]));
-            latency_max_vec.push(diagnostic::packet_latency(
-                &dresolver.latency_max_sampled()[0],
-            ));
+            zero_vec.push(diagnostic::packet_received(&dping_stats));
+            zero_vec.push(diagnostic::packet_received(&dtping_stats));
+            zero_vec.push(diagnostic::packet_received(&dresolver_stats));
+
+            latency_max_vec.push(diagnostic::packet_latency(dping_stats.max));
+            latency_max_vec.push(diagnostic::packet_latency(dtping_stats.max));
+            latency_max_vec.push(diagnostic::packet_latency(dresolver_stats.max));
+
+            let dpmtu = PmtuTool::new(&options.dst, Duration::from_millis(1000)).run().await;
 
             diagnostic::output_format(&latency_max_vec, &zero_vec);
+            diagnostic::pmtu_format(&dpmtu);
         }
     }
     Ok(())
 }
+
+//Run a latency tool on its destinations with the cli front-end and export the results if the user asked it
+//One destination => the probes are printed with the graph, several destinations => they are probed concurrently with a summary table
+async fn run_latency<P: Probe + 'static>(mut tools: Vec<ProbeRunner<P>>, output: &str) {
+    //If the user was defined something for output it means he want to have an output
+    //The file is created before the run and each probe is written to it as it is done
+    if output != "AjaNuP123YuL903nNNaZY" && let Some(first) = tools.first() {
+        //Several destinations share the file => each row starts with its destination
+        let csv = CsvExport::create(output, &first.csv_columns(), tools.len() > 1).unwrap_or_else(|e| {
+            eprintln!("Export CSV error: {}", e);
+            std::process::exit(exit_code(&e));
+        });
+        tools.iter_mut().for_each(|x| x.export_to(csv.clone()));
+    }
+
+    if tools.len() == 1 {
+        let mut tool = tools.remove(0);
+        interactive::run_latency_tool(&mut tool).await.unwrap_or_else(|e| exit_on_error(e));
+        return;
+    }
+
+    let results = interactive::run_many(tools).await;
+
+    //The errors of each destination are in the summary table => quit with an error only if no destination could be probed
+    if results.iter().all(|(_, result)| result.is_err())
+        && let Some((_, Err(e))) = results.into_iter().next()
+    {
+        std::process::exit(exit_code(&e));
+    }
+}
+
+//Print the error of a tool and quit with the exit code of its kind
+fn exit_on_error(e: Error) -> ! {
+    eprintln!("Error during rndiag launching: {}", e);
+    std::process::exit(exit_code(&e));
+}
+
+//Exit codes of rndiag => 1 for a network or system error, sysexits codes for the other errors
+fn exit_code(e: &Error) -> i32 {
+    match e {
+        Error::InvalidAddress(_) => 64, //EX_USAGE
+        Error::TargetFile { .. } => 66, //EX_NOINPUT
+        Error::Resolve { .. } => 68, //EX_NOHOST
+        Error::Export { .. } => 73, //EX_CANTCREAT
+        Error::PermissionDenied(_) => 77, //EX_NOPERM
+        Error::Task(_) | Error::Io(_) => 1,
+    }
+}
@@ -1,5 +1,16 @@
 use std::net::IpAddr;
+use std::time::Duration;
 use regex::Regex;
+use rndiag_core::http::HttpUrl;
+use rndiag_core::icmp::{DEFAULT_PAYLOAD_SIZE, MAX_PATTERN_SIZE, MAX_PAYLOAD_SIZE};
+use pnet::packet::tcp::TcpFlags;
+use rndiag_core::scan::{DEFAULT_SCAN_RATE, MAX_SCAN_RATE, parse_ports};
+use rndiag_core::socket::SocketOptions;
+use rndiag_core::tcp_ping::TcpOptions;
+use rndiag_core::targets::{parse_targets, read_targets};
+use rndiag_core::tls::TlsOptions;
+use rndiag_core::tool::ProbeOptions;
+use rndiag_core::traceroute::{DEFAULT_MAX_HOPS, DEFAULT_QUERIES, TraceMode};
 
 use crate::Args;
 
@@ -10,40 +21,76 @@ pub fn tool_is_selected(options: &Args) -> String{
 
     let mut selected_tool = String::new();
 
-    if options.exporter == true {
+    if options.exporter {
         is_selected +=1;
         selected_tool = "exporter".to_string();
     }
 
-    if options.ping == true{
+    if options.ping{
         is_selected +=1;
         selected_tool = "ping".to_string();
     }
 
-    if options.tping == true{
+    if options.tping{
         is_selected +=1;
         selected_tool = "tping".to_string();
     }
 
-    if options.resolver == true{
+    if options.resolver{
         is_selected +=1;
         selected_tool = "resolver".to_string();
     }
 
-    if options.sptest == true{
+    if options.sptest{
         is_selected +=1;
         selected_tool = "sptest".to_string();
     }
 
-    if options.nc == true {
+    if options.nc {
         is_selected +=1;
         selected_tool = "nc".to_string();
     }
 
+    if options.traceroute {
+        is_selected +=1;
+        selected_tool = "traceroute".to_string();
+    }
+
+    if options.mtr {
+        is_selected +=1;
+        selected_tool = "mtr".to_string();
+    }
+
+    if options.pmtu {
+        is_selected +=1;
+        selected_tool = "pmtu".to_string();
+    }
+
+    if options.scan {
+        is_selected +=1;
+        selected_tool = "scan".to_string();
+    }
+
+    if options.firewall {
+        is_selected +=1;
+        selected_tool = "firewall".to_string();
+    }
+
+    //With the exporter the tls probe is one of the exported tools
+    if options.tls && !options.exporter {
+        is_selected +=1;
+        selected_tool = "tls".to_string();
+    }
+
+    if options.http {
+        is_selected +=1;
+        selected_tool = "http".to_string();
+    }
+
 
     if is_selected == 0{
         if options.diagnostic == "none" {
-            eprintln!("Error ! Please select one tool: ping, tcp_ping, resolver, speedtest or nc");
+            eprintln!("Error ! Please select one tool: ping, tcp_ping, resolver, tls, http, traceroute, mtr, pmtu, scan, firewall, speedtest or nc");
             std::process::exit(1);
         }
         else {
@@ -63,40 +110,45 @@ pub fn tool_is_selected(options: &Args) -> String{
 
 //Check if needed options are set by the user depending of the tool
 pub fn correct_options(selected_tool: &String, options: &Args){
-    if options.exporter == true {
-        if options.port == 0 || options.dst == "none" || options.ws_addr == "none" || options.output == "output.csv" || options.ws_port == 0 {
-            eprintln!("Error ! The destination IP/Hostname, the destination port for the target/exporter web-server, and the output file is required for exporter mode");
-            std::process::exit(1);
-        }
+    if options.exporter && (options.port == 0 || (options.dst == "none" && options.file == "none") || options.ws_addr == "none" || options.output == "output.csv" || options.ws_port == 0) {
+        eprintln!("Error ! The destination IP/Hostname, the destination port for the target/exporter web-server, and the output file is required for exporter mode");
+        std::process::exit(1);
     }
 
-    if selected_tool == "tping" || (selected_tool == "diagnostic" && options.dst != "none") {
-        if options.port == 0{
-            eprintln!("Error ! You must specify the destination port number for tping");
-            std::process::exit(1);
-        }
-
+    if (selected_tool == "tping" || (selected_tool == "diagnostic" && options.dst != "none")) && options.port == 0 {
+        eprintln!("Error ! You must specify the destination port number for tping");
+        std::process::exit(1);
     }
     
-    if selected_tool == "sptest" {
-        if options.port == 0{
-            eprintln!("Error ! You must sepcify the destination port number for sptest");
-            std::process::exit(1);
-        }
+    if selected_tool == "firewall" && options.port == 0 {
+        eprintln!("Error ! You must specify the destination port number for firewall");
+        std::process::exit(1);
     }
 
-    if selected_tool == "nc" {
-         if options.port == 0{
-            eprintln!("Error ! You must sepcify the destination port number for sptest");
-            std::process::exit(1);
-        }
+    if selected_tool == "http" && options.url == "none" {
+        eprintln!("Error ! You must specify the url to fetch with --url, ex: --url http://example.com/");
+        std::process::exit(1);
+    }
+
+    if selected_tool == "scan" && options.ports == "none" {
+        eprintln!("Error ! You must specify the ports to scan with --ports, ex: --ports 1-1024");
+        std::process::exit(1);
+    }
+
+    if selected_tool == "sptest" && options.port == 0 {
+        eprintln!("Error ! You must sepcify the destination port number for sptest");
+        std::process::exit(1);
+    }
 
+    if selected_tool == "nc" && options.port == 0 {
+        eprintln!("Error ! You must sepcify the destination port number for sptest");
+        std::process::exit(1);
     }
 }
 
 //Check if depending of the tool somes options given by the user are useless and if it the case, inform the user that rndiag will ignore it
 pub fn useless_options(selected_tool: &String, options: &Args) {
-    if (selected_tool == "ping" || selected_tool == "resolver") && options.port != 0 {
+    if matches!(selected_tool.as_str(), "ping" | "resolver" | "pmtu" | "scan" | "http") && options.port != 0 {
         println!("Warning ! The port number is no needed for this tool. This parameter will be ignored");
     }
 
@@ -104,15 +156,104 @@ pub fn useless_options(selected_tool: &String, options: &Args) {
         println!("Warning ! The flag is no needed for this tool. This parameter will be ignored");
     }
 
+    if selected_tool != "tping" && options.connect {
+        println!("Warning ! The connect option is only available for tcp ping. It will be ignored");
+    }
+
+    if selected_tool == "tping" && options.connect && options.flag != "none" {
+        println!("Warning ! The flag is no needed with connect, the kernel sends a SYN. This parameter will be ignored");
+    }
+
+    if (selected_tool != "tping" || options.connect) && options.tcp_options != "none" {
+        println!("Warning ! The tcp options are only available for tcp ping without connect. It will be ignored");
+    }
+
+    if selected_tool != "tls" && !(options.exporter && options.tls) && (options.sni != "none" || options.alpn != "none") {
+        println!("Warning ! sni, alpn, are options that only available for tls. It will be ignored");
+    }
+
+    let handshakes = matches!(selected_tool.as_str(), "tls" | "http") || (options.exporter && options.tls);
+    if options.insecure && !handshakes {
+        println!("Warning ! insecure is an option that only available for tls and http. It will be ignored");
+    }
+
     if selected_tool != "sptest" && (options.mode != "full" || options.time != 30 || options.bitrate != 50000) {
         println!("Warning ! time, bitrate, mode, are options that only available for speedtest. It will be ignored");
     }
 
-    if selected_tool == "diagnostic" && (options.ping == true || options.resolver == true || options.tping == true || options.sptest == true || options.nc == true) {
+    if selected_tool == "diagnostic" && (options.ping || options.resolver || options.tping || options.tls || options.sptest || options.nc || options.traceroute || options.mtr || options.pmtu || options.scan || options.firewall || options.http) {
         println!("Warning ! With diagnostic, you cannot select another tool. The selected tool will be ignored and not be runned");
     }
 
-    if (selected_tool != "sptest" && selected_tool != "nc") && options.server == true {
+    if selected_tool != "traceroute" && selected_tool != "mtr"
+        && (options.trace_mode != "icmp" || options.max_hops != DEFAULT_MAX_HOPS || !options.rdns)
+    {
+        println!("Warning ! trace-mode, max-hops, rdns, are options that only available for traceroute and mtr. It will be ignored");
+    }
+
+    if selected_tool != "traceroute" && options.queries != DEFAULT_QUERIES {
+        println!("Warning ! queries is an option that only available for traceroute, mtr sends one probe per hop at each interval. It will be ignored");
+    }
+
+    if (selected_tool == "traceroute" || selected_tool == "mtr") && options.trace_mode == "icmp" && options.port != 0 {
+        println!("Warning ! The port number is no needed for an icmp traceroute or mtr. This parameter will be ignored");
+    }
+
+    if selected_tool == "pmtu" && (options.interval != 500 || options.deadline != 0 || options.count != 0 || options.output != "AjaNuP123YuL903nNNaZY") {
+        println!("Warning ! interval, deadline, count, output, are options that not available for pmtu. It will be ignored");
+    }
+
+    if selected_tool == "firewall" && (options.interval != 500 || options.deadline != 0 || options.count != 0 || options.output != "AjaNuP123YuL903nNNaZY") {
+        println!("Warning ! interval, deadline, count, output, are options that not available for firewall. It will be ignored");
+    }
+
+    if selected_tool != "scan" && (options.ports != "none" || options.rate != DEFAULT_SCAN_RATE) {
+        println!("Warning ! ports and rate are options that only available for scan. It will be ignored");
+    }
+
+    if selected_tool == "scan" && (options.interval != 500 || options.deadline != 0 || options.count != 0) {
+        println!("Warning ! interval, deadline, count, are options that not available for scan, use rate to set the pace of the SYN. It will be ignored");
+    }
+
+    if selected_tool == "mtr" && options.deadline != 0 {
+        println!("Warning ! deadline is an option that not available for mtr, use count to limit the number of rounds. It will be ignored");
+    }
+
+    if selected_tool == "traceroute" && (options.interval != 500 || options.deadline != 0 || options.count != 0) {
+        println!("Warning ! interval, deadline, count, are options that not available for traceroute. It will be ignored");
+    }
+
+    if (selected_tool == "sptest" || selected_tool == "nc") && (options.interval != 500 || options.timeout != 5000 || options.deadline != 0) {
+        println!("Warning ! interval, timeout, deadline, are options that only available for ping, tcp ping and resolver. It will be ignored");
+    }
+
+    if selected_tool != "http" && selected_tool != "exporter" && options.url != "none" {
+        println!("Warning ! The url is only available for the http probe and exporter. It will be ignored");
+    }
+
+    if selected_tool == "http" && (options.dst != "none" || options.file != "none") {
+        println!("Warning ! The http probe fetches the urls given with --url, the destinations -d and -F will be ignored");
+    }
+
+    if !matches!(selected_tool.as_str(), "ping" | "tping" | "resolver" | "tls" | "exporter" | "http") && options.file != "none" {
+        println!("Warning ! The file of destinations is only available for ping, tcp ping, resolver, tls and exporter. It will be ignored");
+    }
+
+    if selected_tool != "ping" && (options.size != DEFAULT_PAYLOAD_SIZE || options.pattern != "none") {
+        println!("Warning ! size and pattern are options that only available for ping. It will be ignored");
+    }
+
+    if !matches!(selected_tool.as_str(), "ping" | "tping" | "resolver" | "tls" | "http" | "scan" | "sptest" | "nc")
+        && (options.tos != "none" || options.dscp != "none" || options.ttl != 0 || options.source != "none" || options.interface != "none")
+    {
+        println!("Warning ! tos, dscp, ttl, source, interface, are options that only available for ping, tcp ping, resolver, tls, http, scan, speedtest and nc. It will be ignored");
+    }
+
+    if (selected_tool == "sptest" || selected_tool == "nc") && options.server && options.source != "none" {
+        println!("Warning ! The source address is no needed in server mode, the server listens on -d. This parameter will be ignored");
+    }
+
+    if (selected_tool != "sptest" && selected_tool != "nc") && options.server {
         println!("Warning ! The server option is no needed for this tool. This parameter will be ignored");
     }
 }
@@ -131,34 +272,310 @@ pub fn addr_check(addr: &String) {
     else if addr.is_empty() || addr.len() > 253 {
         is_addr = false;
     }
-    else if addr.split('.').all(|label| {!label.is_empty() && label.len() <= 63 && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') && !label.starts_with('-') && !label.ends_with('-')}){
-        is_addr = true;
-    }
     else{
-        is_addr = false;
+        is_addr = addr.split('.').all(|label| {!label.is_empty() && label.len() <= 63 && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') && !label.starts_with('-') && !label.ends_with('-')});
     }
 
-    if is_addr == false{
+    if !is_addr{
         eprintln!("Error! The given addr is not a valid ip address or a valid hostname");
         std::process::exit(1);
     }
 }
 
-//Return the right flag number to use it with tcp_ping tool
-pub fn flag_format(flag: &String) -> Option<u8> {
-    match flag.to_uppercase().as_str() {
-        "S" => Some(pnet::packet::tcp::TcpFlags::SYN),
-        "A" => Some(pnet::packet::tcp::TcpFlags::ACK),
-        "F" => Some(pnet::packet::tcp::TcpFlags::FIN),
-        "R" => Some(pnet::packet::tcp::TcpFlags::RST),
-        "P" => Some(pnet::packet::tcp::TcpFlags::PSH),
-        "U" => Some(pnet::packet::tcp::TcpFlags::URG),
+//Return the destinations given by the user with -d and -F => lists and CIDR ranges are expanded, each destination is checked
+pub fn targets_format(options: &Args) -> Vec<String> {
+    let mut targets = Vec::new();
+
+    if options.dst != "none" {
+        targets.extend(parse_targets(&options.dst).unwrap_or_else(|e| crate::exit_on_error(e)));
+    }
+    if options.file != "none" {
+        targets.extend(read_targets(&options.file).unwrap_or_else(|e| crate::exit_on_error(e)));
+    }
+
+    if targets.is_empty() {
+        eprintln!("Error! Please specify a destination with -d or a file of destinations with -F");
+        std::process::exit(1);
+    }
+
+    for target in &targets {
+        addr_check(target);
+    }
+    targets
+}
+
+//Return the urls of the http probe given by the user => separated by commas, each url is checked
+pub fn urls_format(urls: &str) -> Vec<String> {
+    let urls: Vec<String> = urls.split(',').map(str::trim).filter(|x| !x.is_empty()).map(str::to_string).collect();
+    if urls.is_empty() {
+        eprintln!("Error ! Please specify the url to fetch with --url, ex: --url http://example.com/");
+        std::process::exit(1);
+    }
+
+    for url in &urls {
+        match HttpUrl::parse(url) {
+            Some(parsed) => addr_check(&parsed.host),
+            None => {
+                eprintln!("Error ! The given url '{}' is not a valid url, use http://host[:port][/path] or https://host[:port][/path]", url);
+                std::process::exit(1);
+            }
+        }
+    }
+    urls
+}
+
+//Return the right flag number to use it with tcp_ping tool, the letters are combined => SA, FPU (Xmas), SE (ECN setup SYN)
+//N alone is the null segment, E is ECE + CWR on a SYN without ACK (RFC 3168 ECN setup) and ECE alone on the other segments
+pub fn flag_format(flag: &str) -> Option<u8> {
+    let flag = flag.to_uppercase();
+    if flag == "N" {
+        return Some(0);
+    }
+    if flag.is_empty() {
+        return None;
+    }
+
+    let mut flags: u8 = 0;
+    for letter in flag.chars() {
+        flags |= match letter {
+            'S' => TcpFlags::SYN,
+            'A' => TcpFlags::ACK,
+            'F' => TcpFlags::FIN,
+            'R' => TcpFlags::RST,
+            'P' => TcpFlags::PSH,
+            'U' => TcpFlags::URG,
+            'E' => TcpFlags::ECE,
+            'C' => TcpFlags::CWR,
+            _ => return None,
+        };
+    }
+    if flags & (TcpFlags::ECE | TcpFlags::SYN | TcpFlags::ACK) == TcpFlags::ECE | TcpFlags::SYN {
+        flags |= TcpFlags::CWR;
+    }
+    Some(flags)
+}
+
+//Build the TCP options of the tcp ping probes => mss=<bytes>, wscale=<0-14>, sack, ts separated by commas, ex: mss=1460,sack,ts,wscale=7
+pub fn tcp_options_format(tcp_options: &str) -> TcpOptions {
+    let mut options = TcpOptions::default();
+    if tcp_options == "none" {
+        return options;
+    }
+
+    for option in tcp_options.split(',').map(str::trim) {
+        let valid = match option.split_once('=') {
+            Some(("mss", mss)) => {
+                options.mss = mss.parse::<u16>().ok().filter(|x| *x > 0);
+                options.mss.is_some()
+            }
+            Some(("wscale", wscale)) => {
+                options.wscale = wscale.parse::<u8>().ok().filter(|x| *x <= 14);
+                options.wscale.is_some()
+            }
+            None if option == "sack" => {
+                options.sack_permitted = true;
+                true
+            }
+            None if option == "ts" => {
+                options.timestamp = true;
+                true
+            }
+            _ => false,
+        };
+        if !valid {
+            eprintln!("Error ! Do not recognize the tcp option '{}', use mss=<1-65535>, wscale=<0-14>, sack or ts separated by commas", option);
+            std::process::exit(1);
+        }
+    }
+    options
+}
+
+//Build the list of ports of the scan
+pub fn ports_format(ports: &str) -> Vec<u16> {
+    match parse_ports(ports) {
+        Some(ports) => ports,
+        None => {
+            eprintln!("Error ! The ports must be ports from 1 to 65535 or ranges separated by commas, ex: --ports 22,80,443,8000-8100");
+            std::process::exit(1);
+        }
+    }
+}
+
+//Check if the rate of the scan given by the user is conform
+pub fn rate_check(rate: u32) {
+    if rate == 0 || rate > MAX_SCAN_RATE {
+        eprintln!("Error ! The rate of the scan must be between 1 and {} SYN per second", MAX_SCAN_RATE);
+        std::process::exit(1);
+    }
+}
+
+//Check if the interval, timeout and deadline options given by the user are conform
+pub fn timing_check(options: &Args) {
+    if options.interval == 0 {
+        eprintln!("Error ! The interval must be at least 1 ms");
+        std::process::exit(1);
+    }
+
+    if options.timeout == 0 {
+        eprintln!("Error ! The timeout must be at least 1 ms");
+        std::process::exit(1);
+    }
+}
+
+//Return the interval, timeout and deadline options to use it with ping, tcp_ping and resolver tools
+pub fn probe_options_format(options: &Args) -> ProbeOptions {
+    ProbeOptions {
+        interval: Duration::from_millis(options.interval),
+        timeout: Duration::from_millis(options.timeout),
+        //deadline = 0 => no deadline
+        deadline: if options.deadline == 0 { None } else { Some(Duration::from_secs(options.deadline)) },
+    }
+}
+
+//Return the TOS, TTL, source and interface options to use with ping, tcp ping, resolver, tls, speedtest and nc
+//--dscp is the 6 high bits of the TOS byte => they cannot be given together
+pub fn socket_options_format(options: &Args) -> SocketOptions {
+    if options.tos != "none" && options.dscp != "none" {
+        eprintln!("Error ! tos and dscp set the same bits, please give only one of them");
+        std::process::exit(1);
+    }
+
+    let tos = if options.tos != "none" {
+        let tos = match options.tos.strip_prefix("0x").or_else(|| options.tos.strip_prefix("0X")) {
+            Some(hex) => u8::from_str_radix(hex, 16).ok(),
+            None => options.tos.parse::<u8>().ok(),
+        };
+        if tos.is_none() {
+            eprintln!("Error ! The tos must be a byte between 0 and 255 or 0x00 and 0xff, ex: --tos 0xb8");
+            std::process::exit(1);
+        }
+        tos
+    } else if options.dscp != "none" {
+        match dscp_format(&options.dscp) {
+            Some(dscp) => Some(dscp << 2),
+            None => {
+                eprintln!("Error ! Do not recognize the specified dscp: '{}', use 0 to 63, cs0 to cs7, af11 to af43 or ef", options.dscp);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    let source = if options.source != "none" {
+        match options.source.parse::<IpAddr>() {
+            Ok(source) => Some(source),
+            Err(_) => {
+                eprintln!("Error ! The source must be an ip address of the host");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    //IFNAMSIZ => an interface name has at most 15 characters
+    let interface = if options.interface != "none" {
+        if options.interface.is_empty() || options.interface.len() > 15 || options.interface.contains(['/', ' ']) {
+            eprintln!("Error ! The given interface '{}' is not a valid interface name", options.interface);
+            std::process::exit(1);
+        }
+        Some(options.interface.clone())
+    } else {
+        None
+    };
+
+    SocketOptions {
+        tos,
+        //ttl = 0 => the TTL of the system
+        ttl: if options.ttl == 0 { None } else { Some(options.ttl) },
+        source,
+        interface,
+    }
+}
+
+//Return the SNI, ALPN and certificate options of the tls probe
+//An ALPN protocol is 1 to 255 bytes
+pub fn tls_options_format(options: &Args) -> TlsOptions {
+    let sni = if options.sni != "none" {
+        if options.sni.parse::<IpAddr>().is_ok() {
+            eprintln!("Error ! The sni must be a server name, not an ip address");
+            std::process::exit(1);
+        }
+        addr_check(&options.sni);
+        Some(options.sni.clone())
+    } else {
+        None
+    };
+
+    let alpn: Vec<String> = if options.alpn != "none" {
+        options.alpn.split(',').map(|x| x.trim().to_string()).collect()
+    } else {
+        Vec::new()
+    };
+    if alpn.iter().any(|x| x.is_empty() || x.len() > 255) {
+        eprintln!("Error ! The alpn protocols must be 1 to 255 characters separated by commas, ex: --alpn h2,http/1.1");
+        std::process::exit(1);
+    }
+
+    TlsOptions { sni, alpn, insecure: options.insecure }
+}
+
+//Return the DSCP value of a number or of a class name => csN = N * 8, afXY = X * 8 + Y * 2, ef = 46
+fn dscp_format(dscp: &str) -> Option<u8> {
+    let dscp = dscp.to_lowercase();
+    if let Ok(value) = dscp.parse::<u8>() {
+        return (value <= 63).then_some(value);
+    }
+    if dscp == "ef" {
+        return Some(46);
+    }
+    if let Some(class) = dscp.strip_prefix("cs") {
+        return class.parse::<u8>().ok().filter(|x| *x <= 7).map(|x| x * 8);
+    }
+
+    let digits: Vec<u8> = dscp.strip_prefix("af")?.bytes().map(|x| x.wrapping_sub(b'0')).collect();
+    match digits[..] {
+        [class @ 1..=4, drop @ 1..=3] => Some(class * 8 + drop * 2),
         _ => None,
     }
 }
+
+//Check if the size of the echo requests given by the user fits in an IP packet
+pub fn size_check(size: usize) {
+    if size > MAX_PAYLOAD_SIZE {
+        eprintln!("Error ! The size must be at most {} bytes", MAX_PAYLOAD_SIZE);
+        std::process::exit(1);
+    }
+}
+
+//Return the bytes of the hex pattern given by the user, empty if no pattern is given
+pub fn pattern_format(pattern: &str) -> Vec<u8> {
+    if pattern == "none" {
+        return Vec::new();
+    }
+
+    let bytes: Option<Vec<u8>> = if pattern.is_empty() || !pattern.len().is_multiple_of(2) || !pattern.is_ascii() {
+        None
+    } else {
+        (0..pattern.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&pattern[i..i + 2], 16).ok())
+            .collect()
+    };
+
+    match bytes {
+        Some(bytes) if bytes.len() <= MAX_PATTERN_SIZE => bytes,
+        _ => {
+            eprintln!("Error ! The pattern must be an hex string of 1 to {} bytes, ex: ff00", MAX_PATTERN_SIZE);
+            std::process::exit(1);
+        }
+    }
+}
+
 //Check if the given flag by the user is conform
-pub fn flag_check(flag: &String) {
-    if flag != "S" && flag != "A" && flag != "F" && flag != "F" && flag != "R" && flag != "P" && flag != "U" {
+pub fn flag_check(flag: &str) {
+    if flag_format(flag).is_none() {
         eprintln!("Error ! Do not recognize the specified flag !");
         std::process::exit(1);
     }
@@ -173,6 +590,32 @@ pub fn output_check(output: &String) {
     }
 }
 
+//Return the probe mode of traceroute given by the user
+pub fn trace_mode_format(mode: &str) -> TraceMode {
+    match mode.to_lowercase().as_str() {
+        "icmp" => TraceMode::Icmp,
+        "udp" => TraceMode::Udp,
+        "tcp" => TraceMode::Tcp,
+        _ => {
+            eprintln!("Error ! Do not recognize the specified traceroute mode: '{}', use icmp, udp or tcp", mode);
+            std::process::exit(1);
+        }
+    }
+}
+
+//Check if the number of hops and of probes per hop of traceroute are conform
+pub fn hops_check(max_hops: u8, queries: u8) {
+    if max_hops == 0 {
+        eprintln!("Error ! The maximum number of hops must be at least 1");
+        std::process::exit(1);
+    }
+
+    if queries == 0 || queries > 10 {
+        eprintln!("Error ! The number of probes per hop must be between 1 and 10");
+        std::process::exit(1);
+    }
+}
+
 //Check if the given mode by the user is conform
 pub fn mode_check(mode: &String) {
     if mode != "full" && mode != "upload" && mode != "download" {
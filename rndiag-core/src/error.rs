@@ -0,0 +1,63 @@
+use std::fmt;
+use std::io;
+
+//Errors of rndiag => returned by the tools, the metrics and the web-server, the front-end decides what to do with them
+#[derive(Debug)]
+pub enum Error {
+    Resolve { target: String, reason: String }, //The target cannot be resolved => unknown host, no address, ...
+    PermissionDenied(String), //The tool needs privileges => raw sockets without root or CAP_NET_RAW
+    InvalidAddress(String), //An address given by the user cannot be used
+    TargetFile { path: String, source: io::Error }, //The file of targets cannot be read
+    Export { path: String, source: io::Error }, //The csv or metrics file cannot be written
+    Task(String), //A blocking task of a tool has been cancelled or has panicked
+    Io(io::Error), //Other network or system error
+}
+
+//Result of rndiag
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    //Build the error of a tool from an io error => permission denied is kept apart
+    pub fn from_io(tool: &str, e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied(format!("{} failed: operation not permitted", tool)),
+            _ => Error::Io(e),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Resolve { target, reason } => write!(f, "cannot resolve {}: {}", target, reason),
+            Error::PermissionDenied(e) => write!(f, "{}", e),
+            Error::InvalidAddress(addr) => write!(f, "invalid address: {}", addr),
+            Error::TargetFile { path, source } => write!(f, "cannot read {}: {}", path, source),
+            Error::Export { path, source } => write!(f, "cannot write {}: {}", path, source),
+            Error::Task(e) => write!(f, "task error: {}", e),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Export { source, .. } | Error::TargetFile { source, .. } => Some(source),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<tokio::task::JoinError> for Error {
+    fn from(e: tokio::task::JoinError) -> Self {
+        Error::Task(e.to_string())
+    }
+}
@@ -0,0 +1,453 @@
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant};
+
+use pnet::packet::tcp::TcpFlags;
+use tokio::task::JoinSet;
+use tokio::time::timeout_at;
+
+use crate::error::{Error, Result};
+use crate::icmp::{DEFAULT_PAYLOAD_SIZE, IcmpError, IcmpSocket, echo_payload, next_ident};
+use crate::socket::SocketOptions;
+use crate::tcp_ping::{TCPPingTool, TcpOptions, TcpPingMode, TcpReply, decode_tcp_flags};
+use crate::tool::{Probe, as_ms, hop_count, initial_ttl, resolve_target};
+
+//Segments of the battery sent to the port => name in the evidence and TCP flags
+pub const FIREWALL_PROBES: [(&str, u8); 5] = [
+    ("SYN", TcpFlags::SYN),
+    ("ACK", TcpFlags::ACK),
+    ("FIN", TcpFlags::FIN),
+    ("NULL", 0),
+    ("XMAS", TcpFlags::FIN | TcpFlags::PSH | TcpFlags::URG),
+];
+
+//Number of tries of a probe before considering it is dropped => a single loss is not a filter
+pub const FIREWALL_TRIES: u8 = 2;
+
+//Answer to a probe of the battery
+#[derive(Debug, Clone, PartialEq)]
+pub enum FirewallAnswer {
+    Reply { latency: Duration, flags: Option<u8>, ttl: Option<u8> }, //Segment of the target with its flags, None for the echo reply
+    Icmp(IcmpError), //ICMP error of a router or of the target about the probe
+    NoResponse, //No answer to any try
+}
+
+//One probe of the battery => the echo request that gives the distance of the host, then the segments
+#[derive(Debug, Clone, PartialEq)]
+pub struct FirewallProbe {
+    pub name: &'static str, //ECHO, SYN, ACK, FIN, NULL or XMAS
+    pub flags: Option<u8>, //TCP flags, None for the echo request
+    pub tries: u8,
+    pub answer: FirewallAnswer,
+}
+
+impl FirewallProbe {
+    //Flags of the segment answering the probe, None for the echo reply and without reply
+    fn reply_flags(&self) -> Option<u8> {
+        match self.answer {
+            FirewallAnswer::Reply { flags, .. } => flags,
+            _ => None,
+        }
+    }
+
+    //True if the probe got a RST
+    fn is_reset(&self) -> bool {
+        self.reply_flags().is_some_and(|x| x & TcpFlags::RST != 0)
+    }
+
+    //True if the probe got a SYN|ACK
+    fn is_syn_ack(&self) -> bool {
+        self.reply_flags().is_some_and(|x| x & (TcpFlags::SYN | TcpFlags::ACK) == TcpFlags::SYN | TcpFlags::ACK)
+    }
+
+    //True if the probe did not reach the host => dropped or rejected with an ICMP error
+    fn is_blocked(&self) -> bool {
+        !matches!(self.answer, FirewallAnswer::Reply { .. })
+    }
+
+    //Number of routers crossed by the reply, None without reply or TTL
+    fn hops(&self) -> Option<u8> {
+        match self.answer {
+            FirewallAnswer::Reply { ttl, .. } => ttl.map(hop_count),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FirewallProbe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<5} ", format!("{}:", self.name))?;
+        match &self.answer {
+            FirewallAnswer::Reply { latency, flags, ttl } => {
+                match flags {
+                    Some(flags) => write!(f, "{} in {:.3} ms", decode_tcp_flags(*flags), as_ms(*latency))?,
+                    None => write!(f, "echo reply in {:.3} ms", as_ms(*latency))?,
+                }
+                match ttl {
+                    Some(ttl) => write!(f, ", ttl {} ({})", ttl, hops(hop_count(*ttl))),
+                    None => Ok(()),
+                }
+            }
+            FirewallAnswer::Icmp(error) => write!(f, "{}", error),
+            FirewallAnswer::NoResponse => write!(f, "no response to {} tries", self.tries),
+        }
+    }
+}
+
+//Behaviour deduced from the pattern of the answers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FirewallVerdict {
+    SynProxy, //The SYN|ACK does not come from the host => SYN proxy, load balancer or transparent proxy
+    Injection, //RST sent by another device than the host => injected by a firewall, an IPS or a proxy
+    Reject, //Probes rejected with ICMP errors
+    StatefulFirewall, //SYN answered, segments without connection dropped
+    StatelessFilter, //SYN dropped, ACK reaches the host => ACL matching the SYN flag, a stateful firewall also drops the ACK without connection
+    FlagFilter, //The closed port resets SYN and ACK but FIN, NULL or XMAS are dropped => normalizer, scrub
+    NoFilter, //Every probe reaches the host
+    DropAll, //No segment answered while the host answers ping
+    Unreachable, //Nothing answers
+    Inconclusive, //Pattern not explained by a known behaviour
+}
+
+impl FirewallVerdict {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            FirewallVerdict::SynProxy => "middlebox answering the SYN: SYN proxy, load balancer or transparent proxy",
+            FirewallVerdict::Injection => "middlebox injection: segments sent by another device than the host",
+            FirewallVerdict::Reject => "filter rejecting with ICMP errors",
+            FirewallVerdict::StatefulFirewall => "stateful firewall: the segments without connection are dropped",
+            FirewallVerdict::StatelessFilter => "stateless filter: the SYN is dropped but the ACK reaches the host, a stateful firewall drops both",
+            FirewallVerdict::FlagFilter => "filter of invalid flag combinations: FIN, NULL or XMAS dropped before the host",
+            FirewallVerdict::NoFilter => "no filter: every segment reaches the host",
+            FirewallVerdict::DropAll => "filter dropping every segment to the port",
+            FirewallVerdict::Unreachable => "nothing answers: the host is down or every packet is dropped",
+            FirewallVerdict::Inconclusive => "inconclusive: the answers do not match a known behaviour",
+        }
+    }
+}
+
+//One behaviour found with the answers that show it
+#[derive(Debug, Clone, PartialEq)]
+pub struct FirewallFinding {
+    pub verdict: FirewallVerdict,
+    pub evidence: Vec<String>,
+}
+
+//Result of the firewall analysis => state of the port and findings, the most significant first
+#[derive(Debug, Clone, PartialEq)]
+pub struct FirewallReport {
+    pub target_ip: IpAddr,
+    pub port: u16,
+    pub host_ttl: Option<u8>, //TTL of the echo reply of the host, else of its SYN|ACK => reference of the TTL of the replies
+    pub probes: Vec<FirewallProbe>,
+    pub findings: Vec<FirewallFinding>,
+}
+
+impl FirewallReport {
+    //Interpret the pattern of the answers
+    fn new(target_ip: IpAddr, port: u16, probes: &[FirewallProbe]) -> Self {
+        let find = |name: &str| probes.iter().find(|x| x.name == name);
+        let segments: Vec<&FirewallProbe> = probes.iter().filter(|x| x.flags.is_some()).collect();
+        let evidence = |probe: &FirewallProbe, why: &str| format!("{} => {}", probe, why);
+
+        //The host is the device answering ping, else the one answering the SYN
+        let echo_ttl = find("ECHO").and_then(|x| match x.answer {
+            FirewallAnswer::Reply { ttl, .. } => ttl,
+            _ => None,
+        });
+        let syn_ack_ttl = find("SYN").filter(|x| x.is_syn_ack()).and_then(|x| match x.answer {
+            FirewallAnswer::Reply { ttl, .. } => ttl,
+            _ => None,
+        });
+        let host_ttl = echo_ttl.or(syn_ack_ttl);
+        let host_hops = host_ttl.map(hop_count);
+
+        let mut findings = Vec::new();
+
+        //A reply crossing another number of routers than the echo reply is not sent by the host
+        if let Some(host_hops) = host_hops {
+            let foreign: Vec<&&FirewallProbe> = segments.iter().filter(|x| x.hops().is_some_and(|hops| hops != host_hops)).collect();
+            let why = |probe: &FirewallProbe| {
+                format!("sent {} away while the host is {} away", hops(probe.hops().unwrap_or_default()), hops(host_hops))
+            };
+            if echo_ttl.is_some() && let Some(syn) = foreign.iter().find(|x| x.is_syn_ack()) {
+                findings.push(FirewallFinding { verdict: FirewallVerdict::SynProxy, evidence: vec![evidence(syn, &why(syn))] });
+            }
+            let injected: Vec<String> = foreign.iter().filter(|x| !x.is_syn_ack()).map(|x| evidence(x, &why(x))).collect();
+            if !injected.is_empty() {
+                findings.push(FirewallFinding { verdict: FirewallVerdict::Injection, evidence: injected });
+            }
+        }
+
+        let rejected: Vec<String> = segments
+            .iter()
+            .filter_map(|x| match &x.answer {
+                FirewallAnswer::Icmp(error) if error.from == target_ip => Some(evidence(x, "rejected by the firewall of the host")),
+                FirewallAnswer::Icmp(_) => Some(evidence(x, "rejected by a router or a firewall of the path")),
+                _ => None,
+            })
+            .collect();
+        if !rejected.is_empty() {
+            findings.push(FirewallFinding { verdict: FirewallVerdict::Reject, evidence: rejected });
+        }
+
+        //Behaviour of a stack without filter: SYN => SYN|ACK or RST, ACK without connection => RST,
+        //FIN, NULL and XMAS => RST on a closed port, ignored on an open port (RFC 9293 3.10.7)
+        if let (Some(syn), Some(ack)) = (find("SYN"), find("ACK")) {
+            let invalid: Vec<&&FirewallProbe> = segments.iter().filter(|x| !matches!(x.name, "SYN" | "ACK")).collect();
+
+            if segments.iter().all(|x| x.answer == FirewallAnswer::NoResponse) {
+                let finding = match find("ECHO").map(|x| &x.answer) {
+                    Some(FirewallAnswer::Reply { .. }) => FirewallFinding {
+                        verdict: FirewallVerdict::DropAll,
+                        evidence: vec![
+                            evidence(find("ECHO").unwrap(), "the host is up"),
+                            evidence(syn, "every segment is dropped without ICMP error, a reachable host answers a SYN with SYN|ACK or RST"),
+                        ],
+                    },
+                    _ => FirewallFinding {
+                        verdict: FirewallVerdict::Unreachable,
+                        evidence: probes.iter().map(|x| x.to_string()).collect(),
+                    },
+                };
+                findings.push(finding);
+            } else if syn.is_blocked() && ack.is_reset() {
+                findings.push(FirewallFinding {
+                    verdict: FirewallVerdict::StatelessFilter,
+                    evidence: vec![
+                        evidence(syn, "the new connections are blocked"),
+                        evidence(ack, "reaches the host, a stateful firewall drops an ACK without connection (unfiltered in the ACK scan of nmap)"),
+                    ],
+                });
+            } else if !syn.is_blocked() && ack.is_blocked() {
+                findings.push(FirewallFinding {
+                    verdict: FirewallVerdict::StatefulFirewall,
+                    evidence: vec![
+                        evidence(syn, "the connection is allowed"),
+                        evidence(ack, "dropped as out of state, the host answers it with RST"),
+                    ],
+                });
+            } else if syn.is_reset() && ack.is_reset() && invalid.iter().any(|x| x.is_blocked()) {
+                let mut dropped = vec![evidence(syn, "the port is closed and reached")];
+                dropped.extend(invalid.iter().filter(|x| x.is_blocked()).map(|x| evidence(x, "a closed port answers RST")));
+                findings.push(FirewallFinding { verdict: FirewallVerdict::FlagFilter, evidence: dropped });
+            } else if !syn.is_blocked() && ack.is_reset() {
+                let mut reached = vec![evidence(syn, "the port is reached"), evidence(ack, "the segments without connection reach the host")];
+                if syn.is_reset() {
+                    reached.extend(invalid.iter().map(|x| evidence(x, "a closed port answers RST")));
+                } else if syn.is_syn_ack() {
+                    let why = if invalid.iter().all(|x| x.answer == FirewallAnswer::NoResponse) {
+                        "ignored on an open port, RFC 793 stack (Linux, BSD, macOS)"
+                    } else if invalid.iter().all(|x| x.is_reset()) {
+                        "reset even on an open port, not RFC 793 stack (Windows, some devices)"
+                    } else {
+                        "answered differently by the host"
+                    };
+                    reached.extend(invalid.iter().map(|x| evidence(x, why)));
+                }
+                findings.push(FirewallFinding { verdict: FirewallVerdict::NoFilter, evidence: reached });
+            }
+        }
+
+        if findings.is_empty() {
+            findings.push(FirewallFinding {
+                verdict: FirewallVerdict::Inconclusive,
+                evidence: probes.iter().map(|x| x.to_string()).collect(),
+            });
+        }
+
+        Self { target_ip, port, host_ttl, probes: probes.to_vec(), findings }
+    }
+
+    //State of the port given by the answer to the SYN
+    pub fn port_state(&self) -> &'static str {
+        match self.probes.iter().find(|x| x.name == "SYN") {
+            Some(syn) if syn.is_syn_ack() => "open",
+            Some(syn) if syn.is_reset() => "closed",
+            Some(_) => "filtered",
+            None => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for FirewallReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "\n--- Firewall analysis of {} port {} ---\n", self.target_ip, self.port)?;
+        write!(f, "Port {}: {}", self.port, self.port_state())?;
+        match self.host_ttl {
+            Some(ttl) => writeln!(f, ", host {} away (ttl {}, initial ttl {})\n", hops(hop_count(ttl)), ttl, initial_ttl(ttl))?,
+            None => writeln!(f, "\n")?,
+        }
+
+        for (i, finding) in self.findings.iter().enumerate() {
+            let label = if i == 0 { "Verdict" } else { "Also" };
+            writeln!(f, "{}: {}", label, finding.verdict.describe())?;
+            for evidence in &finding.evidence {
+                writeln!(f, "  {}", evidence)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//Number of hops for the evidence => "1 hop", "3 hops"
+fn hops(count: u8) -> String {
+    if count == 1 { String::from("1 hop") } else { format!("{} hops", count) }
+}
+
+//Firewall and middlebox analysis => an echo request, then SYN, ACK, FIN, NULL and XMAS segments to one port
+//The segments are sent together, each from its own raw socket => the analysis lasts at most the tries of the echo and of one segment
+//The pattern of the answers and their TTL are compared with the behaviour of a TCP stack without filter
+pub struct FirewallTool {
+    pub target: String, //IP or host to test
+    port: u16,
+    timeout: Duration, //Time to wait for the answer of a try
+    target_ip: IpAddr,
+    tpings: Vec<TCPPingTool>, //Raw segments of the battery, one tool per segment, one source port per try
+    running: JoinSet<(usize, io::Result<FirewallProbe>)>, //Segments waiting their answer with their index in the battery
+    icmp: Option<IcmpSocket>, //Echo request giving the TTL of the host, None if it cannot be opened
+    ident: u16,
+    seq: u16,
+    probes: Vec<FirewallProbe>,
+}
+
+impl FirewallTool {
+    //Init attributes of the object
+    pub fn new(target: &str, port: u16, timeout: Duration) -> Self {
+        Self {
+            target: target.to_string(),
+            port,
+            timeout,
+            target_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            tpings: Vec::new(),
+            running: JoinSet::new(),
+            icmp: None,
+            ident: next_ident(),
+            seq: 0,
+            probes: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        "firewall"
+    }
+
+    //Resolve the target and open the raw sockets of the segments and of the echo request
+    //The target is resolved once => every segment goes to the same address of a name with several addresses
+    pub async fn prepare(&mut self) -> Result<String> {
+        self.target_ip = resolve_target(&self.target)?;
+        for (_, flags) in FIREWALL_PROBES {
+            let mode = TcpPingMode::Raw(flags, TcpOptions::default());
+            let mut tping = TCPPingTool::new(&self.target_ip.to_string(), self.port, mode, SocketOptions::default());
+            tping.prepare().await.map_err(|e| match e {
+                Error::PermissionDenied(_) => {
+                    Error::PermissionDenied(format!("{} failed: operation not permitted, raw sockets need root or CAP_NET_RAW", self.name()))
+                }
+                e => e,
+            })?;
+            self.tpings.push(tping);
+        }
+        self.icmp = IcmpSocket::open(self.target_ip).ok();
+
+        let names: Vec<&str> = FIREWALL_PROBES.iter().map(|x| x.0).collect();
+        let longest = self.timeout * 2 * FIREWALL_TRIES as u32;
+        Ok(format!(
+            "FIREWALL {}:{} ({}), ECHO then {} probes sent together, {} tries each, up to {:.1} s",
+            self.target,
+            self.port,
+            self.target_ip,
+            names.join(", "),
+            FIREWALL_TRIES,
+            longest.as_secs_f64()
+        ))
+    }
+
+    //Send the next probe of the battery, None when every probe is done
+    //The echo request is answered first, then the segments in the order of their answer
+    pub async fn next_probe(&mut self) -> Result<Option<&FirewallProbe>> {
+        if self.probes.is_empty() {
+            let mut tries = 0;
+            let mut answer = FirewallAnswer::NoResponse;
+            while tries < FIREWALL_TRIES && answer == FirewallAnswer::NoResponse {
+                tries += 1;
+                answer = self.echo().await.map_err(|e| Error::from_io(self.name(), e))?;
+            }
+            self.probes.push(FirewallProbe { name: "ECHO", flags: None, tries, answer });
+            return Ok(self.probes.last());
+        }
+
+        let timeout = self.timeout;
+        for (index, tping) in self.tpings.drain(..).enumerate() {
+            self.running.spawn(async move { (index, segment_probe(tping, index, timeout).await) });
+        }
+
+        match self.running.join_next().await {
+            Some(Ok((_, probe))) => {
+                self.probes.push(probe.map_err(|e| Error::from_io(self.name(), e))?);
+                Ok(self.probes.last())
+            }
+            Some(Err(e)) => Err(Error::Task(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    //Run the whole analysis without front-end
+    pub async fn run(&mut self) -> Result<FirewallReport> {
+        self.prepare().await?;
+        while self.next_probe().await?.is_some() {}
+        Ok(self.report())
+    }
+
+    //Verdict of the analysis with the probes done, in the order of the battery
+    pub fn report(&self) -> FirewallReport {
+        let mut probes = self.probes.clone();
+        probes.sort_by_key(|x| FIREWALL_PROBES.iter().position(|(name, _)| *name == x.name).map_or(0, |i| i + 1));
+        FirewallReport::new(self.target_ip, self.port, &probes)
+    }
+
+    //Send one echo request and wait its reply or an ICMP error about it, no response without ICMP socket
+    async fn echo(&mut self) -> io::Result<FirewallAnswer> {
+        let Some(socket) = self.icmp.as_ref() else {
+            return Ok(FirewallAnswer::NoResponse);
+        };
+        self.seq = self.seq.wrapping_add(1);
+
+        let start = Instant::now();
+        socket.send_echo(self.target_ip, self.ident, self.seq, &echo_payload(DEFAULT_PAYLOAD_SIZE, &[]))?;
+
+        let deadline = tokio::time::Instant::from_std(start + self.timeout);
+        loop {
+            let Ok(message) = timeout_at(deadline, socket.recv()).await else {
+                return Ok(FirewallAnswer::NoResponse);
+            };
+            let message = message?;
+
+            if socket.own_echo_error(&message, self.ident) == Some(self.seq)
+                && let Some(error) = message.error
+            {
+                return Ok(FirewallAnswer::Icmp(error));
+            }
+            if socket.is_own_echo(&message, self.ident) && message.seq == self.seq && message.from == self.target_ip {
+                let latency = message.received.saturating_duration_since(start);
+                return Ok(FirewallAnswer::Reply { latency, flags: None, ttl: message.ttl });
+            }
+        }
+    }
+}
+
+//Try a segment of the battery until it is answered or FIREWALL_TRIES times
+async fn segment_probe(mut tping: TCPPingTool, index: usize, timeout: Duration) -> io::Result<FirewallProbe> {
+    let (name, flags) = FIREWALL_PROBES[index];
+    let mut tries = 0;
+    let mut answer = FirewallAnswer::NoResponse;
+    while tries < FIREWALL_TRIES && answer == FirewallAnswer::NoResponse {
+        tries += 1;
+        answer = match tping.exchange(flags, TcpOptions::default(), timeout).await? {
+            Some(TcpReply::Segment(latency, flags, _, ttl)) => FirewallAnswer::Reply { latency, flags: Some(flags), ttl },
+            Some(TcpReply::Icmp(_, error)) => FirewallAnswer::Icmp(error),
+            None => FirewallAnswer::NoResponse,
+        };
+    }
+    Ok(FirewallProbe { name, flags: Some(flags), tries, answer })
+}
@@ -0,0 +1,670 @@
+use std::fmt;
+use std::io;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::os::fd::AsRawFd;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use pnet::packet::util::checksum;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use tokio::io::Interest;
+use tokio::io::unix::AsyncFd;
+
+use crate::socket::SocketOptions;
+
+//Size of the header of an ICMP message => type, code, checksum + 4 bytes (identifier and sequence for echo messages)
+pub const ICMP_HEADER_SIZE: usize = 8;
+
+//Default number of data bytes of an echo request => 64 bytes ICMP packet like ping
+pub const DEFAULT_PAYLOAD_SIZE: usize = 56;
+
+//Maximum number of data bytes of an echo request => IPv4 packet of 65535 bytes
+pub const MAX_PAYLOAD_SIZE: usize = 65_507;
+
+//Maximum number of bytes of the pattern used to fill the data of an echo request
+pub const MAX_PATTERN_SIZE: usize = 16;
+
+//ICMP and ICMPv6 echo types
+pub const ICMP_ECHO_REPLY: u8 = 0;
+pub const ICMP_ECHO_REQUEST: u8 = 8;
+pub const ICMPV6_ECHO_REQUEST: u8 = 128;
+pub const ICMPV6_ECHO_REPLY: u8 = 129;
+
+//ICMP and ICMPv6 error types
+pub const ICMP_DEST_UNREACHABLE: u8 = 3;
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+pub const ICMP_PARAMETER_PROBLEM: u8 = 12;
+pub const ICMPV6_DEST_UNREACHABLE: u8 = 1;
+pub const ICMPV6_PACKET_TOO_BIG: u8 = 2;
+pub const ICMPV6_TIME_EXCEEDED: u8 = 3;
+pub const ICMPV6_PARAMETER_PROBLEM: u8 = 4;
+
+//Code of ICMP destination unreachable => fragmentation needed and DF set, the IPv4 equivalent of packet too big
+pub const ICMP_FRAG_NEEDED: u8 = 4;
+
+//IP protocol numbers of the quoted packets
+pub const IPPROTO_ICMP: u8 = 1;
+pub const IPPROTO_TCP: u8 = 6;
+pub const IPPROTO_UDP: u8 = 17;
+pub const IPPROTO_ICMPV6: u8 = 58;
+
+//Size of an IPv4 header without options and of an IPv6 header
+pub const IPV4_HEADER_SIZE: usize = 20;
+pub const IPV6_HEADER_SIZE: usize = 40;
+
+//ICMP message received on an ICMP socket
+#[derive(Debug, Clone, PartialEq)]
+pub struct IcmpMessage {
+    pub from: IpAddr, //Source of the message
+    pub icmp_type: u8,
+    pub code: u8,
+    pub ident: u16, //Identifier of an echo message
+    pub seq: u16, //Sequence number of an echo message
+    pub ttl: Option<u8>, //TTL or hop limit of the IP packet of the message
+    pub size: usize, //Number of bytes of the ICMP message => header + data
+    pub data: Vec<u8>, //Data of the message after the header
+    pub received: Instant,
+    pub error: Option<IcmpError>, //Set when the message is an ICMP error
+    pub quoted: Option<QuotedPacket>, //Packet that caused the ICMP error
+}
+
+impl IcmpMessage {
+    //True if the message is an echo reply, ICMP or ICMPv6
+    pub fn is_echo_reply(&self) -> bool {
+        match self.from {
+            IpAddr::V4(_) => self.icmp_type == ICMP_ECHO_REPLY,
+            IpAddr::V6(_) => self.icmp_type == ICMPV6_ECHO_REPLY,
+        }
+    }
+}
+
+//ICMP or ICMPv6 error sent by a router or the target about one of our packets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IcmpError {
+    pub from: IpAddr, //Router or host that reported the error
+    pub icmp_type: u8,
+    pub code: u8,
+    pub mtu: Option<u32>, //MTU of the next hop for packet too big and fragmentation needed
+}
+
+impl IcmpError {
+    //True if the type is an ICMP error of the IP version of from
+    pub fn is_error_type(ipv6: bool, icmp_type: u8) -> bool {
+        if ipv6 {
+            matches!(icmp_type, ICMPV6_DEST_UNREACHABLE | ICMPV6_PACKET_TOO_BIG | ICMPV6_TIME_EXCEEDED | ICMPV6_PARAMETER_PROBLEM)
+        } else {
+            matches!(icmp_type, ICMP_DEST_UNREACHABLE | ICMP_TIME_EXCEEDED | ICMP_PARAMETER_PROBLEM)
+        }
+    }
+
+    //Name of the type and meaning of the code
+    pub fn describe(&self) -> (&'static str, &'static str) {
+        match (self.from.is_ipv6(), self.icmp_type, self.code) {
+            (false, ICMP_DEST_UNREACHABLE, code) => ("Destination Unreachable", match code {
+                0 => "network unreachable",
+                1 => "host unreachable",
+                2 => "protocol unreachable",
+                3 => "port unreachable",
+                ICMP_FRAG_NEEDED => "fragmentation needed",
+                5 => "source route failed",
+                6 => "destination network unknown",
+                7 => "destination host unknown",
+                9 => "network administratively prohibited",
+                10 => "host administratively prohibited",
+                11 => "network unreachable for TOS",
+                12 => "host unreachable for TOS",
+                13 => "communication administratively prohibited",
+                14 => "host precedence violation",
+                15 => "precedence cutoff",
+                _ => "unknown code",
+            }),
+            (false, ICMP_TIME_EXCEEDED, 0) => ("Time Exceeded", "TTL exceeded in transit"),
+            (false, ICMP_TIME_EXCEEDED, _) => ("Time Exceeded", "fragment reassembly time exceeded"),
+            (false, ICMP_PARAMETER_PROBLEM, _) => ("Parameter Problem", "bad IP header"),
+            (true, ICMPV6_DEST_UNREACHABLE, code) => ("Destination Unreachable", match code {
+                0 => "no route to destination",
+                1 => "communication administratively prohibited",
+                2 => "beyond scope of source address",
+                3 => "address unreachable",
+                4 => "port unreachable",
+                5 => "source address failed ingress/egress policy",
+                6 => "reject route to destination",
+                _ => "unknown code",
+            }),
+            (true, ICMPV6_PACKET_TOO_BIG, _) => ("Packet Too Big", "packet larger than the MTU of the next hop"),
+            (true, ICMPV6_TIME_EXCEEDED, 0) => ("Time Exceeded", "hop limit exceeded in transit"),
+            (true, ICMPV6_TIME_EXCEEDED, _) => ("Time Exceeded", "fragment reassembly time exceeded"),
+            (true, ICMPV6_PARAMETER_PROBLEM, _) => ("Parameter Problem", "bad IPv6 header"),
+            _ => ("ICMP error", "unknown type"),
+        }
+    }
+}
+
+//Display the error as "Destination Unreachable (port unreachable) from 10.0.0.1, type=3 code=3"
+impl fmt::Display for IcmpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, detail) = self.describe();
+        write!(f, "{} ({}) from {}, type={} code={}", name, detail, self.from, self.icmp_type, self.code)?;
+        if let Some(mtu) = self.mtu {
+            write!(f, " mtu={}", mtu)?;
+        }
+        Ok(())
+    }
+}
+
+//Start of the packet quoted by an ICMP error => used to find the probe that caused the error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotedPacket {
+    pub protocol: u8, //IP protocol of the quoted packet => ICMP, TCP, UDP, ...
+    pub dst: IpAddr, //Destination of the quoted packet
+    pub header: Vec<u8>, //Start of the transport header of the quoted packet, at least 8 bytes
+}
+
+impl QuotedPacket {
+    //Source and destination ports of a quoted TCP or UDP packet
+    pub fn ports(&self) -> Option<(u16, u16)> {
+        let header = self.header.get(..4)?;
+        Some((u16::from_be_bytes([header[0], header[1]]), u16::from_be_bytes([header[2], header[3]])))
+    }
+}
+
+//Kind of ICMP socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketKind {
+    Raw, //Receives every ICMP message of the host, needs root or CAP_NET_RAW
+    Datagram, //Unprivileged echo socket, allowed by net.ipv4.ping_group_range => the kernel sets the identifier and filters the replies
+}
+
+//ICMP or ICMPv6 socket => sends echo requests and receives ICMP messages
+pub struct IcmpSocket {
+    fd: AsyncFd<Socket>,
+    ipv6: bool,
+    kind: SocketKind,
+}
+
+impl IcmpSocket {
+    //Open a socket for the IP version of the target => raw socket if allowed, else datagram socket
+    pub fn open(target: IpAddr) -> io::Result<Self> {
+        match Self::open_kind(target, SocketKind::Raw) {
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Self::open_kind(target, SocketKind::Datagram),
+            result => result,
+        }
+    }
+
+    //Open a socket of the given kind for the IP version of the target
+    pub fn open_kind(target: IpAddr, kind: SocketKind) -> io::Result<Self> {
+        let socket_type = match kind {
+            SocketKind::Raw => Type::RAW,
+            SocketKind::Datagram => Type::DGRAM,
+        };
+
+        let socket = match target {
+            IpAddr::V4(_) => {
+                let socket = Socket::new(Domain::IPV4, socket_type, Some(Protocol::ICMPV4))?;
+                //A datagram socket does not give the IP header with the message => ask the TTL as ancillary data
+                //Its ICMP errors are only given in its error queue
+                if kind == SocketKind::Datagram {
+                    set_option(&socket, libc::IPPROTO_IP, libc::IP_RECVTTL)?;
+                    set_option(&socket, libc::IPPROTO_IP, libc::IP_RECVERR)?;
+                }
+                socket
+            }
+            IpAddr::V6(_) => {
+                let socket = Socket::new(Domain::IPV6, socket_type, Some(Protocol::ICMPV6))?;
+                //The IPv6 header is not given with the message => ask the hop limit as ancillary data
+                socket.set_recv_hoplimit_v6(true)?;
+                if kind == SocketKind::Datagram {
+                    set_option(&socket, libc::IPPROTO_IPV6, libc::IPV6_RECVERR)?;
+                }
+                socket
+            }
+        };
+        //Reception time given by the kernel => the latency does not depend on when the message is read
+        set_option(&socket, libc::SOL_SOCKET, libc::SO_TIMESTAMPNS)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            fd: AsyncFd::new(socket)?,
+            ipv6: target.is_ipv6(),
+            kind,
+        })
+    }
+
+    pub fn kind(&self) -> SocketKind {
+        self.kind
+    }
+
+    //TTL or hop limit of the next echo requests => used by traceroute
+    pub fn set_ttl(&self, ttl: u8) -> io::Result<()> {
+        if self.ipv6 {
+            self.fd.get_ref().set_unicast_hops_v6(ttl as u32)
+        } else {
+            self.fd.get_ref().set_ttl_v4(ttl as u32)
+        }
+    }
+
+    //TOS, TTL, interface and source address of the echo requests given by the user
+    pub fn set_options(&self, options: &SocketOptions, target: IpAddr) -> io::Result<()> {
+        options.apply(self.fd.get_ref(), target)?;
+        options.bind(self.fd.get_ref())
+    }
+
+    //Send the next echo requests with the Don't Fragment bit, even above the path MTU known by the kernel => used by the PMTU discovery
+    //IPv6 routers never fragment, DONTFRAG stops the fragmentation by the host itself
+    pub fn set_dont_fragment(&self) -> io::Result<()> {
+        let socket = self.fd.get_ref();
+        if self.ipv6 {
+            set_int_option(socket, libc::IPPROTO_IPV6, libc::IPV6_MTU_DISCOVER, libc::IPV6_PMTUDISC_PROBE)?;
+            set_option(socket, libc::IPPROTO_IPV6, libc::IPV6_DONTFRAG)
+        } else {
+            set_int_option(socket, libc::IPPROTO_IP, libc::IP_MTU_DISCOVER, libc::IP_PMTUDISC_PROBE)
+        }
+    }
+
+    //True if an echo reply answers the echo requests sent with ident
+    //A datagram socket only receives the replies of its own requests, the kernel replaces the identifier by its own one
+    pub fn is_own_echo(&self, message: &IcmpMessage, ident: u16) -> bool {
+        message.is_echo_reply() && (self.kind == SocketKind::Datagram || message.ident == ident)
+    }
+
+    //Sequence number of our echo request quoted by an ICMP error, None if the error is about another packet
+    pub fn own_echo_error(&self, message: &IcmpMessage, ident: u16) -> Option<u16> {
+        let quoted = message.quoted.as_ref()?;
+        let (protocol, request) = if self.ipv6 { (IPPROTO_ICMPV6, ICMPV6_ECHO_REQUEST) } else { (IPPROTO_ICMP, ICMP_ECHO_REQUEST) };
+        let header = quoted.header.get(..ICMP_HEADER_SIZE)?;
+
+        let own = message.error.is_some()
+            && quoted.protocol == protocol
+            && header[0] == request
+            && (self.kind == SocketKind::Datagram || u16::from_be_bytes([header[4], header[5]]) == ident);
+        own.then(|| u16::from_be_bytes([header[6], header[7]]))
+    }
+
+    //Send an echo request with its identifier, sequence number and data
+    pub fn send_echo(&self, dst: IpAddr, ident: u16, seq: u16, data: &[u8]) -> io::Result<()> {
+        let mut packet = vec![0u8; ICMP_HEADER_SIZE + data.len()];
+        packet[0] = if self.ipv6 { ICMPV6_ECHO_REQUEST } else { ICMP_ECHO_REQUEST };
+        packet[4..6].copy_from_slice(&ident.to_be_bytes());
+        packet[6..8].copy_from_slice(&seq.to_be_bytes());
+        packet[ICMP_HEADER_SIZE..].copy_from_slice(data);
+
+        //The kernel computes the checksum of ICMPv6 with the pseudo-header
+        if !self.ipv6 {
+            let sum = checksum(&packet, 1);
+            packet[2..4].copy_from_slice(&sum.to_be_bytes());
+        }
+
+        self.fd.get_ref().send_to(&packet, &SockAddr::from(SocketAddr::new(dst, 0)))?;
+        Ok(())
+    }
+
+    //Wait the next ICMP message received by the socket
+    pub async fn recv(&self) -> io::Result<IcmpMessage> {
+        let mut buf = vec![0u8; 65_536];
+
+        loop {
+            //An ICMP error in the error queue of a datagram socket is only signaled as an error event
+            let mut guard = self.fd.ready(Interest::READABLE | Interest::ERROR).await?;
+            let message = match guard.try_io(|fd| self.read(fd.get_ref(), &mut buf)) {
+                Ok(message) => message?,
+                Err(_would_block) => continue,
+            };
+
+            if let Some(message) = message {
+                return Ok(message);
+            }
+        }
+    }
+
+    //Return the next ICMP message already received, None if there is none => used by the tools that poll their sockets
+    pub fn try_recv(&self) -> io::Result<Option<IcmpMessage>> {
+        let mut buf = vec![0u8; 65_536];
+
+        loop {
+            match self.read(self.fd.get_ref(), &mut buf) {
+                Ok(Some(message)) => return Ok(Some(message)),
+                Ok(None) => continue,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    //Read one packet of the socket => the ICMP errors of a datagram socket are read first in its error queue
+    //None if the packet is not a valid ICMP message
+    fn read(&self, socket: &Socket, buf: &mut [u8]) -> io::Result<Option<IcmpMessage>> {
+        if self.kind == SocketKind::Datagram {
+            match recv_message(socket, buf, libc::MSG_ERRQUEUE) {
+                Ok(received) => return Ok(self.parse_queued_error(&buf[..received.len], &received, received.time)),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let received = recv_message(socket, buf, 0)?;
+        Ok(self.parse(&buf[..received.len], received.from, received.ttl, received.time))
+    }
+
+    //Read an ICMP message => a raw IPv4 socket gives the IP header with the message, the other sockets only the message
+    fn parse(&self, packet: &[u8], from: IpAddr, ttl: Option<u8>, received: Instant) -> Option<IcmpMessage> {
+        let (icmp, ttl) = if self.ipv6 || self.kind == SocketKind::Datagram {
+            (packet, ttl)
+        } else {
+            let header_len = (*packet.first()? & 0x0f) as usize * 4;
+            (packet.get(header_len..)?, Some(*packet.get(8)?))
+        };
+
+        if icmp.len() < ICMP_HEADER_SIZE {
+            return None;
+        }
+
+        //ICMP errors quote the IP header and the start of the packet that caused them
+        let (error, quoted) = if IcmpError::is_error_type(self.ipv6, icmp[0]) {
+            //Next hop MTU => 16 bits after the unused field in ICMP, 32 bits in ICMPv6
+            let mtu = match (self.ipv6, icmp[0], icmp[1]) {
+                (false, ICMP_DEST_UNREACHABLE, ICMP_FRAG_NEEDED) => Some(u16::from_be_bytes([icmp[6], icmp[7]]) as u32),
+                (true, ICMPV6_PACKET_TOO_BIG, _) => Some(u32::from_be_bytes([icmp[4], icmp[5], icmp[6], icmp[7]])),
+                _ => None,
+            };
+            let error = IcmpError { from, icmp_type: icmp[0], code: icmp[1], mtu };
+            (Some(error), parse_quoted(&icmp[ICMP_HEADER_SIZE..], self.ipv6))
+        } else {
+            (None, None)
+        };
+
+        Some(IcmpMessage {
+            from,
+            icmp_type: icmp[0],
+            code: icmp[1],
+            ident: u16::from_be_bytes([icmp[4], icmp[5]]),
+            seq: u16::from_be_bytes([icmp[6], icmp[7]]),
+            ttl,
+            size: icmp.len(),
+            data: icmp[ICMP_HEADER_SIZE..].to_vec(),
+            received,
+            error,
+            quoted,
+        })
+    }
+
+    //Read an ICMP error of the error queue => the kernel gives the type, code and reporter, the data is our echo request
+    fn parse_queued_error(&self, packet: &[u8], received: &Received, time: Instant) -> Option<IcmpMessage> {
+        let queued = received.error.as_ref()?;
+        if queued.origin != libc::SO_EE_ORIGIN_ICMP && queued.origin != libc::SO_EE_ORIGIN_ICMP6 {
+            return None;
+        }
+
+        let mtu = match (self.ipv6, queued.icmp_type, queued.code) {
+            (false, ICMP_DEST_UNREACHABLE, ICMP_FRAG_NEEDED) | (true, ICMPV6_PACKET_TOO_BIG, _) => Some(queued.info),
+            _ => None,
+        };
+        let error = IcmpError { from: queued.offender, icmp_type: queued.icmp_type, code: queued.code, mtu };
+
+        Some(IcmpMessage {
+            from: queued.offender,
+            icmp_type: queued.icmp_type,
+            code: queued.code,
+            ident: 0,
+            seq: 0,
+            ttl: None,
+            size: packet.len(),
+            data: Vec::new(),
+            received: time,
+            error: Some(error),
+            quoted: Some(QuotedPacket {
+                protocol: if self.ipv6 { IPPROTO_ICMPV6 } else { IPPROTO_ICMP },
+                dst: received.from,
+                header: packet.to_vec(),
+            }),
+        })
+    }
+}
+
+//Read the IP header quoted by an ICMP error => protocol, destination and start of the transport header
+pub fn parse_quoted(data: &[u8], ipv6: bool) -> Option<QuotedPacket> {
+    if ipv6 {
+        let header = data.get(..IPV6_HEADER_SIZE)?;
+        let dst: [u8; 16] = header[24..40].try_into().ok()?;
+        Some(QuotedPacket {
+            protocol: header[6],
+            dst: IpAddr::V6(Ipv6Addr::from(dst)),
+            header: data[IPV6_HEADER_SIZE..].to_vec(),
+        })
+    } else {
+        let header_len = (*data.first()? & 0x0f) as usize * 4;
+        let header = data.get(..header_len.max(20))?;
+        Some(QuotedPacket {
+            protocol: header[9],
+            dst: IpAddr::V4(Ipv4Addr::new(header[16], header[17], header[18], header[19])),
+            header: data.get(header_len..)?.to_vec(),
+        })
+    }
+}
+
+//Enable an int socket option not available in socket2 => IP_RECVTTL, IP_RECVERR, IPV6_RECVERR
+fn set_option(socket: &Socket, level: libc::c_int, name: libc::c_int) -> io::Result<()> {
+    set_int_option(socket, level, name, 1)
+}
+
+//Set an int socket option not available in socket2 => IP_MTU_DISCOVER, IPV6_DONTFRAG, ...
+pub(crate) fn set_int_option(socket: &Socket, level: libc::c_int, name: libc::c_int, value: libc::c_int) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+//Packet read by recvmsg with its source and its ancillary data
+pub(crate) struct Received {
+    pub len: usize,
+    pub from: IpAddr, //Source of the packet, destination of the packet that caused a queued error
+    pub ttl: Option<u8>, //Only given with IP_RECVTTL or IPV6_RECVHOPLIMIT
+    time: Instant, //Reception time given by the kernel, else the time of the read
+    error: Option<QueuedError>,
+}
+
+//Extended error of the error queue => IP_RECVERR and IPV6_RECVERR
+struct QueuedError {
+    origin: u8, //Local, ICMP or ICMPv6
+    icmp_type: u8,
+    code: u8,
+    info: u32, //MTU for packet too big and fragmentation needed
+    offender: IpAddr, //Router or host that sent the ICMP error
+}
+
+//recvmsg on a socket with flags => socket2 does not give access to the ancillary data
+pub(crate) fn recv_message(socket: &Socket, buf: &mut [u8], flags: libc::c_int) -> io::Result<Received> {
+    let mut addr: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut control = [0u8; 256];
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = &mut addr as *mut libc::sockaddr_storage as *mut libc::c_void;
+    msg.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control.len() as _;
+
+    let len = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, flags | libc::MSG_DONTWAIT) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    //TTL of IPv4 (IP_TTL) or hop limit of IPv6 (IPV6_HOPLIMIT), both are given as an int
+    //Extended error of the error queue (IP_RECVERR or IPV6_RECVERR) followed by the address of the offender
+    let mut ttl = None;
+    let mut error = None;
+    let mut time = Instant::now();
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while !cmsg.is_null() {
+        let header = unsafe { &*cmsg };
+        let level_type = (header.cmsg_level, header.cmsg_type);
+        if level_type == (libc::IPPROTO_IP, libc::IP_TTL) || level_type == (libc::IPPROTO_IPV6, libc::IPV6_HOPLIMIT) {
+            let value = unsafe { std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int) };
+            ttl = Some(value as u8);
+        } else if level_type == (libc::SOL_SOCKET, libc::SCM_TIMESTAMPNS) {
+            let value = unsafe { std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::timespec) };
+            time = kernel_instant(&value);
+        } else if level_type == (libc::IPPROTO_IP, libc::IP_RECVERR) || level_type == (libc::IPPROTO_IPV6, libc::IPV6_RECVERR) {
+            let extended = unsafe { libc::CMSG_DATA(cmsg) as *const libc::sock_extended_err };
+            let value = unsafe { std::ptr::read_unaligned(extended) };
+            //The offender is a sockaddr_in or sockaddr_in6 => copied in a sockaddr_storage
+            let mut offender: libc::sockaddr_storage = unsafe { mem::zeroed() };
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    libc::SO_EE_OFFENDER(extended) as *const u8,
+                    &mut offender as *mut libc::sockaddr_storage as *mut u8,
+                    mem::size_of::<libc::sockaddr_in6>(),
+                )
+            };
+            error = Some(QueuedError {
+                origin: value.ee_origin,
+                icmp_type: value.ee_type,
+                code: value.ee_code,
+                info: value.ee_info,
+                offender: socket_ip(&offender).unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            });
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+
+    let from = socket_ip(&addr).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unknown address family"))?;
+
+    Ok(Received { len: len as usize, from, ttl, time, error })
+}
+
+//Convert a wall-clock timestamp of the kernel in an Instant => now minus the age of the timestamp
+fn kernel_instant(timestamp: &libc::timespec) -> Instant {
+    let now = Instant::now();
+    let wall = std::time::Duration::new(timestamp.tv_sec as u64, timestamp.tv_nsec as u32);
+    let age = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(std::time::Duration::ZERO, |x| x.saturating_sub(wall));
+    now.checked_sub(age).unwrap_or(now)
+}
+
+//IP of a sockaddr_in or sockaddr_in6
+fn socket_ip(addr: &libc::sockaddr_storage) -> Option<IpAddr> {
+    match addr.ss_family as libc::c_int {
+        libc::AF_INET => {
+            let addr = unsafe { &*(addr as *const libc::sockaddr_storage as *const libc::sockaddr_in) };
+            Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr))))
+        }
+        libc::AF_INET6 => {
+            let addr = unsafe { &*(addr as *const libc::sockaddr_storage as *const libc::sockaddr_in6) };
+            Some(IpAddr::V6(Ipv6Addr::from(addr.sin6_addr.s6_addr)))
+        }
+        _ => None,
+    }
+}
+
+//Added to the process id => each tool of the process has its own echo identifier
+static NEXT_IDENT: AtomicU16 = AtomicU16::new(0);
+
+//Identifier of the echo requests of a new tool => replies and errors of the other tools of the process are ignored
+pub fn next_ident() -> u16 {
+    (std::process::id() as u16).wrapping_add(NEXT_IDENT.fetch_add(1, Ordering::Relaxed))
+}
+
+//Data of an echo request => the pattern repeated, or 0, 1, 2, ... when no pattern is given
+pub fn echo_payload(size: usize, pattern: &[u8]) -> Vec<u8> {
+    if pattern.is_empty() {
+        (0..size).map(|i| i as u8).collect()
+    } else {
+        pattern.iter().copied().cycle().take(size).collect()
+    }
+}
+
+//Privileges of the process to send echo requests => used to explain why no ICMP socket can be opened
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Privileges {
+    pub root: bool, //Effective user is root
+    pub cap_net_raw: bool, //CAP_NET_RAW in the effective capabilities => raw sockets
+    pub ping_group_range: Option<(u32, u32)>, //Groups allowed to open datagram ICMP sockets, None if unknown
+    pub groups: Vec<u32>, //Effective group and supplementary groups of the process
+}
+
+//Bit of CAP_NET_RAW in the capability sets of /proc/self/status
+const CAP_NET_RAW: u32 = 13;
+
+impl Privileges {
+    //Read the privileges of the current process
+    pub fn detect() -> Self {
+        let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+        let cap_net_raw = status
+            .lines()
+            .find_map(|line| line.strip_prefix("CapEff:"))
+            .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+            .is_some_and(|caps| caps & (1 << CAP_NET_RAW) != 0);
+
+        let ping_group_range = std::fs::read_to_string("/proc/sys/net/ipv4/ping_group_range")
+            .ok()
+            .and_then(|range| {
+                let mut bounds = range.split_whitespace().map(|x| x.parse::<u32>().ok());
+                Some((bounds.next()??, bounds.next()??))
+            });
+
+        Self {
+            root: unsafe { libc::geteuid() } == 0,
+            cap_net_raw,
+            ping_group_range,
+            groups: process_groups(),
+        }
+    }
+
+    //True if a group of the process is in net.ipv4.ping_group_range
+    pub fn ping_group_allowed(&self) -> bool {
+        self.ping_group_range
+            .is_some_and(|(low, high)| self.groups.iter().any(|group| (low..=high).contains(group)))
+    }
+
+    //Explain what is missing to open an ICMP socket for the tool and how to get it
+    pub fn explain(&self, tool: &str) -> String {
+        if self.root || self.cap_net_raw || self.ping_group_allowed() {
+            return format!("{} failed: operation not permitted even with raw or datagram ICMP sockets allowed (sandbox, seccomp, ...)", tool);
+        }
+
+        let range = match self.ping_group_range {
+            Some((low, high)) => format!("{} {}", low, high),
+            None => String::from("unknown"),
+        };
+        let groups: Vec<String> = self.groups.iter().map(|x| x.to_string()).collect();
+
+        format!(
+            "{} failed: operation not permitted, not root, no CAP_NET_RAW capability and no group of the user ({}) in net.ipv4.ping_group_range ({})\n\
+             Run rndiag with sudo, give it the capability with 'setcap cap_net_raw+ep <rndiag path>' \
+             or allow the group with 'sysctl -w net.ipv4.ping_group_range=\"0 2147483647\"'",
+            tool,
+            groups.join(", "),
+            range
+        )
+    }
+}
+
+//Effective group and supplementary groups of the process
+fn process_groups() -> Vec<u32> {
+    let mut groups = vec![unsafe { libc::getegid() }];
+
+    let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    if count > 0 {
+        let mut supplementary = vec![0 as libc::gid_t; count as usize];
+        let count = unsafe { libc::getgroups(count, supplementary.as_mut_ptr()) };
+        supplementary.truncate(count.max(0) as usize);
+        groups.extend(supplementary.into_iter().filter(|x| !groups.contains(x)).collect::<Vec<_>>());
+    }
+    groups
+}
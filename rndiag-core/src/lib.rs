@@ -1,6 +1,19 @@
+pub mod error;
 pub mod tool;
+pub mod store;
+pub mod targets;
+pub mod multi;
+pub mod mtr;
 pub mod ping;
+pub mod pmtu;
+pub mod icmp;
+pub mod socket;
 pub mod nslookup;
 pub mod speedtest;
 pub mod tcp_ping;
-pub mod tcp_message;
\ No newline at end of file
+pub mod traceroute;
+pub mod scan;
+pub mod firewall;
+pub mod http;
+pub mod tcp_message;
+pub mod tls;
\ No newline at end of file
@@ -0,0 +1,332 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use csv::Writer;
+use serde_json::json;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinSet;
+use tokio::time::sleep_until;
+
+use crate::error::{Error, Result};
+use crate::icmp::SocketKind;
+use crate::store::ProbeStore;
+use crate::tool::{ProbeResult, ProbeTime, ReplyDetail, SampledSeries, Statistics, as_ms, get_time, resolve_target};
+use crate::traceroute::{HopReply, NameCache, TraceMode, TraceOptions, TraceProber};
+
+//Columns of the csv export of mtr
+pub const MTR_CSV_COLUMNS: [&str; 10] = ["Hop", "Address", "Name", "Sent", "Loss", "Last", "Avg", "Best", "Worst", "StDev"];
+
+//Commands sent by the front-end to a running mtr
+#[derive(Debug)]
+pub enum MtrCommand {
+    Stop, //Stop the run, the probes of the round in progress are abandoned
+    Hops(oneshot::Sender<Vec<MtrHopStats>>), //Ask the statistics of each hop => used to refresh the table
+    Series(u8, usize, oneshot::Sender<SampledSeries>), //Ask the sampled series of the hop with this TTL with at most N values => used to display its graph
+}
+
+//Statistics of a hop at a time => sent to the front-end and exported
+#[derive(Debug, Clone, PartialEq)]
+pub struct MtrHopStats {
+    pub ttl: u8,
+    pub from: Option<IpAddr>, //Last router or target that answered, None if no probe was answered
+    pub name: Option<String>, //Reverse DNS name of from
+    pub others: usize, //Number of other addresses that answered at this TTL => load balancing
+    pub annotation: Option<String>, //Annotation (!N, !H, ...) of the last unreachable sent by the hop
+    pub last: Option<Duration>, //RTT of the last probe, None if it was lost
+    pub stats: Statistics,
+}
+
+//One hop of the path
+struct MtrHop {
+    ttl: u8,
+    addresses: Vec<IpAddr>, //Addresses that answered at this TTL, the last one first
+    annotation: Option<String>,
+    last: Option<Duration>,
+    store: ProbeStore,
+}
+
+impl MtrHop {
+    fn new(ttl: u8) -> Self {
+        Self { ttl, addresses: Vec::new(), annotation: None, last: None, store: ProbeStore::new() }
+    }
+
+    //Store the answer of a probe => any answer of the hop is a received probe, time exceeded included
+    fn push(&mut self, time: ProbeTime, reply: Option<&HopReply>) {
+        self.last = reply.map(|x| x.rtt);
+        let Some(reply) = reply else {
+            self.store.push(time, ProbeResult::Timeout, &ReplyDetail::default());
+            return;
+        };
+
+        self.addresses.retain(|x| *x != reply.from);
+        self.addresses.insert(0, reply.from);
+        self.annotation = reply.annotation();
+        self.store.push(time, ProbeResult::Reply(reply.rtt), &ReplyDetail::default());
+    }
+}
+
+//MTR tool => discovers the hops like traceroute then probes all of them at each round
+//Each hop keeps its sent/loss/last/avg/best/worst/stddev and its time series for the graph
+pub struct MtrTool {
+    pub target: String, //IP or host to trace
+    options: TraceOptions, //queries is not used => one probe per hop at each round
+    interval: Duration, //Time between the begin of two rounds
+    count: u32, //Number of rounds, 0 for no limit
+    output: String, //Output filename => json if it ends with .json, else csv
+    probers: Vec<TraceProber>, //One per TTL => the probes of a round are sent concurrently
+    hops: Vec<MtrHop>,
+    path_len: u8, //TTL of the target or of the last hop before an unreachable, max_hops until it is known
+    names: NameCache,
+    commands: Option<mpsc::UnboundedReceiver<MtrCommand>>,
+    begin_instant: Instant,
+}
+
+impl MtrTool {
+    //Init attributes of the object
+    pub fn new(target: &str, options: TraceOptions, interval: Duration, count: u32, output: &str) -> Self {
+        Self {
+            target: target.to_string(),
+            options,
+            interval,
+            count,
+            output: output.to_string(),
+            probers: Vec::new(),
+            hops: Vec::new(),
+            path_len: options.max_hops,
+            names: NameCache::default(),
+            commands: None,
+            begin_instant: Instant::now(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        "mtr"
+    }
+
+    //Link the tool with its front-end
+    pub fn attach(&mut self, commands: mpsc::UnboundedReceiver<MtrCommand>) {
+        self.commands = Some(commands);
+    }
+
+    //Unlink the front-end => its next commands fail
+    pub fn detach(&mut self) {
+        self.commands = None;
+    }
+
+    //Resolve the target and open the sockets of each TTL, return the description of the run
+    pub async fn prepare(&mut self) -> Result<String> {
+        let target_ip = resolve_target(&self.target)?;
+
+        self.probers.clear();
+        for _ in 0..self.options.max_hops {
+            self.probers.push(TraceProber::open(target_ip, self.options.mode, self.options.port, self.name())?);
+        }
+        self.hops = (1..=self.options.max_hops).map(MtrHop::new).collect();
+
+        let mode = match (self.probers[0].icmp_kind(), self.options.mode) {
+            (SocketKind::Datagram, TraceMode::Icmp) => String::from("icmp probes (unprivileged ICMP datagram socket)"),
+            (_, TraceMode::Icmp) => String::from("icmp probes"),
+            (_, mode) => format!("{} probes to port {}", mode.name(), self.probers[0].port()),
+        };
+
+        Ok(format!(
+            "mtr to {} ({}), {} hops max, {}",
+            self.target, target_ip, self.options.max_hops, mode
+        ))
+    }
+
+    //Probe all the hops of the path at each interval until count rounds or a Stop command
+    pub async fn run(&mut self) -> Result<()> {
+        self.begin_instant = Instant::now();
+        let mut round: u32 = 0;
+
+        while self.count == 0 || round < self.count {
+            let start = Instant::now();
+            if !self.probe_round().await? {
+                break;
+            }
+            round += 1;
+
+            if (self.count == 0 || round < self.count) && !self.wait_next_round(start).await {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    //Send one probe to each hop of the path concurrently, the commands of the front-end are handled while waiting the answers
+    //Return false if the front-end asked to stop the run => the probes still waiting are aborted and the round is not recorded
+    async fn probe_round(&mut self) -> Result<bool> {
+        let (timeout, path_len) = (self.options.timeout, self.path_len);
+        let mut tasks = JoinSet::new();
+        for (index, mut prober) in self.probers.drain(..).enumerate() {
+            let ttl = index as u8 + 1;
+            tasks.spawn(async move {
+                //The hops after the target are not probed anymore, their prober is kept
+                let reply = if ttl <= path_len { prober.probe(ttl, timeout).await } else { Ok(None) };
+                (ttl, prober, reply)
+            });
+        }
+
+        let mut replies: Vec<(u8, io::Result<Option<HopReply>>)> = Vec::new();
+        let mut probers = Vec::new();
+
+        loop {
+            tokio::select! {
+                joined = tasks.join_next() => match joined {
+                    Some(Ok((ttl, prober, reply))) => {
+                        probers.push((ttl, prober));
+                        replies.push((ttl, reply));
+                    }
+                    Some(Err(e)) => return Err(Error::Task(e.to_string())),
+                    None => break,
+                },
+                Some(command) = recv_command(&mut self.commands) => {
+                    if !self.handle_command(command) {
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+
+        probers.sort_by_key(|(ttl, _)| *ttl);
+        self.probers = probers.into_iter().map(|(_, prober)| prober).collect();
+        replies.sort_by_key(|(ttl, _)| *ttl);
+
+        let time = ProbeTime { wall_ms: get_time(), mono: self.begin_instant.elapsed() };
+        for (ttl, reply) in replies {
+            if ttl > self.path_len {
+                continue;
+            }
+            let mut reply = reply.map_err(|e| Error::from_io("mtr", e))?;
+            if let Some(reply) = reply.as_mut() {
+                if reply.reached || reply.annotation().is_some() {
+                    self.path_len = self.path_len.min(ttl);
+                }
+                if self.options.resolve_names {
+                    reply.name = self.names.resolve(reply.from).await;
+                }
+            }
+            self.hops[ttl as usize - 1].push(time, reply.as_ref());
+        }
+        Ok(true)
+    }
+
+    //Wait the interval after a round started at `start` while handling the commands of the front-end
+    //Return false if the front-end asked to stop the run
+    async fn wait_next_round(&mut self, start: Instant) -> bool {
+        let until = tokio::time::Instant::from_std(start + self.interval);
+        loop {
+            tokio::select! {
+                _ = sleep_until(until) => return true,
+                Some(command) = recv_command(&mut self.commands) => {
+                    if !self.handle_command(command) {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+
+    //Answer a command of the front-end, return false for Stop
+    fn handle_command(&mut self, command: MtrCommand) -> bool {
+        match command {
+            MtrCommand::Stop => return false,
+            MtrCommand::Hops(reply) => {
+                let _ = reply.send(self.hops());
+            }
+            MtrCommand::Series(ttl, max_points, reply) => {
+                if let Some(hop) = self.hops.iter().find(|x| x.ttl == ttl) {
+                    let _ = reply.send(hop.store.sampled_series(max_points));
+                }
+            }
+        }
+        true
+    }
+
+    //Statistics of each hop of the path
+    pub fn hops(&self) -> Vec<MtrHopStats> {
+        self.hops
+            .iter()
+            .take(self.path_len as usize)
+            .map(|hop| {
+                let from = hop.addresses.first().copied();
+                MtrHopStats {
+                    ttl: hop.ttl,
+                    from,
+                    name: from.and_then(|x| self.names.get(x)),
+                    others: hop.addresses.len().saturating_sub(1),
+                    annotation: hop.annotation.clone(),
+                    last: hop.last,
+                    stats: hop.store.statistics(),
+                }
+            })
+            .collect()
+    }
+
+    //Export the statistics of the hops in json if the output ends with .json, else in csv
+    pub fn export(&self) -> Result<()> {
+        let export_error = |source: io::Error| Error::Export { path: self.output.clone(), source };
+        let hops = self.hops();
+        let ms = |x: Option<Duration>| x.map(as_ms);
+
+        if self.output.ends_with(".json") {
+            let hops: Vec<serde_json::Value> = hops
+                .iter()
+                .map(|hop| {
+                    json!({
+                        "ttl": hop.ttl,
+                        "address": hop.from.map(|x| x.to_string()),
+                        "name": hop.name,
+                        "sent": hop.stats.transmitted,
+                        "loss_percent": hop.stats.loss_percent,
+                        "last_ms": ms(hop.last),
+                        "avg_ms": ms(hop.stats.avg),
+                        "best_ms": ms(hop.stats.min),
+                        "worst_ms": ms(hop.stats.max),
+                        "stddev_ms": ms(hop.stats.stddev),
+                    })
+                })
+                .collect();
+            let report = json!({ "target": self.target, "mode": self.options.mode.name(), "hops": hops });
+
+            let mut file = File::create(&self.output).map_err(export_error)?;
+            serde_json::to_writer_pretty(&mut file, &report).map_err(|e| export_error(e.into()))?;
+            return file.write_all(b"\n").map_err(export_error);
+        }
+
+        let file = File::create(&self.output).map_err(export_error)?;
+        let mut writer = Writer::from_writer(file);
+        writer.write_record(MTR_CSV_COLUMNS).map_err(|e| export_error(e.into()))?;
+
+        let ms = |x: Option<Duration>| x.map_or(String::new(), |x| format!("{:.3}", as_ms(x)));
+        for hop in &hops {
+            writer
+                .write_record([
+                    hop.ttl.to_string(),
+                    hop.from.map_or(String::new(), |x| x.to_string()),
+                    hop.name.clone().unwrap_or_default(),
+                    hop.stats.transmitted.to_string(),
+                    format!("{:.2}", hop.stats.loss_percent),
+                    ms(hop.last),
+                    ms(hop.stats.avg),
+                    ms(hop.stats.min),
+                    ms(hop.stats.max),
+                    ms(hop.stats.stddev),
+                ])
+                .map_err(|e| export_error(e.into()))?;
+        }
+        writer.flush().map_err(export_error)?;
+        Ok(())
+    }
+}
+
+//Next command of the front-end, pending forever without front-end
+async fn recv_command(commands: &mut Option<mpsc::UnboundedReceiver<MtrCommand>>) -> Option<MtrCommand> {
+    match commands.as_mut() {
+        Some(commands) => commands.recv().await,
+        None => std::future::pending().await,
+    }
+}
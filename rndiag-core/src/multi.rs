@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use tokio::sync::Semaphore;
+
+use crate::error::Result;
+use crate::tool::{Probe, ProbeRunner};
+
+//Default number of targets probed at the same time
+pub const DEFAULT_CONCURRENCY: usize = 256;
+
+//Run the latency tools of several targets concurrently => one task per target, at most `concurrency` targets probed at the same time
+//Each tool is returned with the result of its run, in the order of the given tools
+pub async fn run_concurrent<P: Probe + 'static>(
+    runners: Vec<ProbeRunner<P>>,
+    concurrency: usize,
+) -> Vec<(ProbeRunner<P>, Result<()>)> {
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+
+    let tasks: Vec<_> = runners
+        .into_iter()
+        .map(|mut runner| {
+            let semaphore = semaphore.clone();
+            tokio::spawn(async move {
+                //The semaphore is never closed => the permit is always given
+                let _permit = semaphore.acquire_owned().await;
+                let result = runner.run().await;
+                (runner, result)
+            })
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        //A panic of a tool is forwarded to the caller
+        results.push(task.await.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic())));
+    }
+    results
+}
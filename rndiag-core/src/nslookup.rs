@@ -1,376 +1,594 @@
-use anyhow::Result;
-use crossterm::event::KeyModifiers;
-use rndiag_graph::graph::graph_display;
 use tokio::task;
-use crate::tool::LatencyTool;
-use std::net::IpAddr;
-use std::net::Ipv4Addr;
-use std::time::Duration;
-use std::time::Instant;
-use std::io::{self};
-use tokio::time::{sleep, timeout};
-use crossterm::event::{self, Event, KeyCode};
+use crate::error::{Error, Result};
+use crate::icmp::next_ident;
+use crate::socket::SocketOptions;
+use crate::tool::{Probe, ProbeError, ProbeOutcome, ProbeResult};
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::time::{timeout, timeout_at};
 use dns_lookup::{lookup_host, lookup_addr};
 
-//Ping object definition
+//File of the nameservers of the system resolver
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+//DNS record types asked by the resolver
+const DNS_TYPE_A: u16 = 1;
+const DNS_TYPE_PTR: u16 = 12;
+const DNS_TYPE_AAAA: u16 = 28;
+
+//Size of the header of a DNS message
+const DNS_HEADER_SIZE: usize = 12;
+
+//Nameservers of resolv.conf asked at most, as the system resolver => MAXNS of glibc
+const MAX_NAMESERVERS: usize = 3;
+
+//NSlookup object definition => one DNS request per probe, run by ProbeRunner
+//A reverse lookup is done for an IP target, a name lookup for a hostname target
+//Without socket options the system resolver is used, else the nameservers of resolv.conf are asked directly in their order
+//=> the system resolver cannot mark its requests or send them from a given source
 pub struct NSlookup {
-    pub target: String, //IP or host to ping
-    data: Vec<u32>, //Latency of each pin
-    begin_time: u64, //Time when pings start => Used to determine the elapsed time to detect scale for sampling
-    elapsed_time: u64, //Elasped time since the first ping => Used with the begin_time to determine the scale for sampling
-    sys_time: Vec<u64>, //Get the current system timestamp of each ping => used to get the number of data ping's and used to calculate elapsed_time
-    latency_time: Vec<u64>, //Store the system timestamp of each sampled graph data point ping
-    latency_min: Vec<u32>, //Store each latency ping => used for min latency sampling calculation
-    latency_moy: Vec<u32>, //Store each latency ping => used for moy latency sampling calculation
-    latency_max: Vec<u32>, //Store each latency ping => used for max latency sampling calculation
-    latency_min_sampled: Vec<u64>, //Store sampled min latency values
-    latency_moy_sampled: Vec<u64>, //Store sampled moy latency values
-    latency_max_sampled: Vec<u64>, //Store sampled max latency values
-    output: String, //Destination csv file
-    nb_ping: u32, //The number of ping defined by the user or if default => infinity ping
+    pub target: String, //IP or host to resolve
+    socket_options: SocketOptions, //TOS, TTL, source and interface of the DNS requests
+    nameservers: Vec<IpAddr>, //Nameservers asked directly, empty => system resolver
 }
 
-impl LatencyTool for NSlookup {
+//Answer of a DNS request
+enum DnsResult {
+    Host(String),
+    Ip(Vec<IpAddr>),
+}
+
+impl Probe for NSlookup {
     //Return only the name of the object
     fn name(&self) -> &'static str {
         "resolver"
     }
 
-    //Return the data vec => used in the definition of NetworkTool Methods trait when it wants to read object attribute
-    fn data(&self) -> &Vec<u32> {
-        &self.data
+    //Return the target attribute
+    fn target(&self) -> &str {
+        &self.target
     }
 
-    //Return the data vec => used in the definition of NetworkTool Methods trait when it wants to read object attribute
-    fn nb_ping(&self) -> &u32{
-        &self.nb_ping
-    }
+    //Find the nameservers to ask directly if socket options are given
+    //With a source address only the nameservers of its IP version can be asked
+    async fn prepare(&mut self) -> Result<Option<String>> {
+        if self.socket_options.is_default() {
+            return Ok(None);
+        }
+
+        let nameservers = nameservers().map_err(|e| Error::from_io(self.name(), e))?;
+        let first = nameservers[0];
+        self.nameservers = nameservers.into_iter().filter(|x| self.socket_options.check(*x).is_ok()).collect();
+        if self.nameservers.is_empty() {
+            //No nameserver of the IP version of the source => the error of the first one
+            self.socket_options.check(first)?;
+        }
 
-    //Return the sys_time vec => used in the definition of NetworkTool Methods trait when it wants to read object attribute
-    fn sys_time(&self) -> &Vec<u64> {
-        &self.sys_time
+        let nameservers: Vec<String> = self.nameservers.iter().map(|x| x.to_string()).collect();
+        let label = if nameservers.len() == 1 { "nameserver" } else { "nameservers" };
+        Ok(Some(format!("RESOLVE {} with {} {} {}", self.target, label, nameservers.join(", "), self.socket_options)))
     }
 
-    //Return the elapsed_time var => used in the definition of NetworkTool Methods trait when it wants to read and modify object attribute
-    fn elapsed_time(&mut self) -> &mut u64 {
-        &mut self.elapsed_time
+    //Do one DNS request
+    async fn probe(&mut self, _seq: u32, dns_timeout: Duration) -> ProbeOutcome {
+        let target_host = self.target.clone();
+
+        //Start to count the time
+        let start = Instant::now();
+        let nslookup_result = if self.nameservers.is_empty() {
+            system_lookup(target_host, dns_timeout).await
+        } else {
+            query_nameservers(&self.nameservers, &self.socket_options, &target_host, start, dns_timeout).await
+        };
+        //Elasped time calculation
+        let elapsed = start.elapsed();
+
+        //Convert the DNS result in a probe result => reply, timeout or error with its reason
+        //The DNS answer or the resolver error is the detail for the front-end
+        let (result, info) = match nslookup_result {
+            Ok(DnsResult::Host(h)) => (ProbeResult::Reply(elapsed), Some(h)),
+            Ok(DnsResult::Ip(ips)) => (ProbeResult::Reply(elapsed), Some(format!("{:?}", ips))),
+            Err((ProbeResult::Timeout, _)) => (ProbeResult::Timeout, None),
+            Err((result, e)) => (result, Some(e)),
+        };
+        ProbeOutcome::new(result, info)
     }
+}
 
-    //Return the begin_time var => used in the definition of NetworkTool Methods trait when it wants to read and modify object attribute
-    fn begin_time(&self) -> &u64 {
-        &self.begin_time
+//Specific methods of NSlookup that not match with the Probe Trait general definition
+impl NSlookup {
+    //Init attributes of the object
+    pub fn new(target: &str, socket_options: SocketOptions) -> Self {
+        Self {
+            target: target.to_string(),
+            socket_options,
+            nameservers: Vec::new(),
+        }
     }
+}
 
-    //Return the latency_time vec => used in the definition of NetworkTool Methods trait when it wants to read and modify object attribute
-    fn latency_time(&mut self) -> &mut Vec<u64> {
-        &mut self.latency_time
+//Failed DNS request => the result of the probe and its reason
+type DnsError = (ProbeResult, String);
+
+//Resolve with the system resolver
+async fn system_lookup(target_host: String, dns_timeout: Duration) -> std::result::Result<DnsResult, DnsError> {
+    //Async nslookup task => the blocking lookup is done in a dedicated thread
+    match timeout(
+        dns_timeout,
+        task::spawn_blocking(move || -> std::result::Result<DnsResult, Box<dyn std::error::Error + Send>> {
+            match target_host.parse::<IpAddr>() {
+                Ok(target_ip) => {
+                    let result_host = lookup_addr(&target_ip)
+                        .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)?;
+                    Ok(DnsResult::Host(result_host))
+                }
+                Err(_) => {
+                    let result_ip = lookup_host(&target_host)
+                        .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)?
+                        .collect::<Vec<_>>();
+                    Ok(DnsResult::Ip(result_ip))
+                }
+            }
+        })
+    ).await {
+        Ok(Ok(Ok(result))) => Ok(result),
+        //The resolver answered with an error => DNS failure
+        Ok(Ok(Err(e))) => Err((ProbeResult::Error(ProbeError::DnsFailure), e.to_string())),
+        Ok(Err(e)) => Err((ProbeResult::Error(ProbeError::Other(e.to_string())), String::from("Task error"))),
+        // Timeout elapsed
+        Err(_) => Err((ProbeResult::Timeout, String::from("Timeout"))),
     }
+}
 
-    //Return the latency_min vec => used in the definition of NetworkTool Methods trait when it wants to read and modify object attribute
-    fn latency_min(&mut self) -> &mut Vec<u32> {
-        &mut self.latency_min
+//Resolve by asking the nameservers over UDP from sockets with the options of the user
+//A hostname is asked as A and AAAA records, an IP as a PTR record
+//As the system resolver, the next nameserver is asked when one does not answer in its share of the timeout,
+//cannot be reached (ICMP error, no route through the interface) or fails with SERVFAIL, NOTIMP or REFUSED
+async fn query_nameservers(
+    nameservers: &[IpAddr],
+    options: &SocketOptions,
+    target_host: &str,
+    start: Instant,
+    dns_timeout: Duration,
+) -> std::result::Result<DnsResult, DnsError> {
+    let questions = match target_host.parse::<IpAddr>() {
+        Ok(ip) => vec![(reverse_name(ip), DNS_TYPE_PTR)],
+        Err(_) => vec![(target_host.to_string(), DNS_TYPE_A), (target_host.to_string(), DNS_TYPE_AAAA)],
+    };
+
+    let mut last = Err((ProbeResult::Timeout, String::from("Timeout")));
+    for (index, nameserver) in nameservers.iter().enumerate() {
+        //The time left is shared between the nameservers not asked yet
+        let left = dns_timeout.saturating_sub(start.elapsed()) / (nameservers.len() - index) as u32;
+        let deadline = tokio::time::Instant::now() + left;
+
+        last = match query_nameserver(*nameserver, options, &questions, deadline).await {
+            Ok(answers) if answers.records.is_empty() && matches!(answers.rcode, 2 | 4 | 5) => {
+                Err((ProbeResult::Error(ProbeError::DnsFailure), format!("{} from {}", rcode_name(answers.rcode), nameserver)))
+            }
+            Ok(answers) => return answers.result(),
+            Err(e) => Err(e),
+        };
     }
+    last
+}
 
-    //Return the latency_moy vec => used in the definition of NetworkTool Methods trait when it wants to read and modify object attribute
-    fn latency_moy(&mut self) -> &mut Vec<u32> {
-        &mut self.latency_moy
+//Ask one nameserver the questions and wait its answers until the deadline
+async fn query_nameserver(
+    nameserver: IpAddr,
+    options: &SocketOptions,
+    questions: &[(String, u16)],
+    deadline: tokio::time::Instant,
+) -> std::result::Result<DnsAnswers, DnsError> {
+    let io_error = |e: io::Error| (ProbeResult::Error(ProbeError::from(&e)), format!("{} ({})", e, nameserver));
+    let socket = dns_socket(nameserver, options).map_err(io_error)?;
+
+    //Each question has its own id => the answers are matched whatever their order
+    let base_id = next_ident();
+    let mut pending: Vec<u16> = Vec::new();
+    for (index, (name, qtype)) in questions.iter().enumerate() {
+        let id = base_id.wrapping_add(index as u16);
+        socket.send(&dns_query(id, name, *qtype)).await.map_err(io_error)?;
+        pending.push(id);
     }
 
-    //Return the latency_max vec => used in the definition of NetworkTool Methods trait when it wants to read and modify object attribute
-    fn latency_max(&mut self) -> &mut Vec<u32> {
-        &mut self.latency_max
+    let mut buf = vec![0u8; 4096];
+    let mut answers = DnsAnswers::default();
+
+    while !pending.is_empty() {
+        let n = match timeout_at(deadline, socket.recv(&mut buf)).await {
+            Ok(Ok(n)) => n,
+            Ok(Err(e)) => return Err(io_error(e)),
+            Err(_) => return Err((ProbeResult::Timeout, String::from("Timeout"))),
+        };
+        let Some(answer) = parse_answer(&buf[..n]) else {
+            continue;
+        };
+        if !pending.contains(&answer.id) {
+            continue;
+        }
+        pending.retain(|x| *x != answer.id);
+
+        if answer.rcode != 0 {
+            answers.rcode = answer.rcode;
+        }
+        answers.truncated |= answer.truncated;
+        answers.records.extend(answer.records);
     }
+    Ok(answers)
+}
 
-    //Return the latency_min_sampled vec => used in the definition of NetworkTool Methods trait when it wants to read and modify object attribute
-    fn latency_min_sampled(&mut self) -> &mut Vec<u64> {
-        &mut self.latency_min_sampled
+//Nameservers of resolv.conf in their order
+fn nameservers() -> io::Result<Vec<IpAddr>> {
+    let nameservers = parse_nameservers(&fs::read_to_string(RESOLV_CONF)?);
+    if nameservers.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("no nameserver in {}", RESOLV_CONF)));
     }
+    Ok(nameservers)
+}
+
+//Nameservers of the content of resolv.conf, at most MAX_NAMESERVERS
+fn parse_nameservers(content: &str) -> Vec<IpAddr> {
+    content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        //An IPv6 link-local nameserver may have a zone => not supported
+        .filter_map(|x| x.trim().parse::<IpAddr>().ok())
+        .take(MAX_NAMESERVERS)
+        .collect()
+}
+
+//UDP socket connected to the nameserver with the options of the user
+fn dns_socket(nameserver: IpAddr, options: &SocketOptions) -> io::Result<UdpSocket> {
+    let address = SocketAddr::new(nameserver, 53);
+    let socket = Socket::new(Domain::for_address(address), Type::DGRAM, Some(Protocol::UDP))?;
+    options.apply(&socket, nameserver)?;
+    options.bind(&socket)?;
+    socket.connect(&address.into())?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
 
-    //Return the latency_moy_sampled vec => used in the definition of NetworkTool Methods trait when it wants to read and modify object attribute
-    fn latency_moy_sampled(&mut self) -> &mut Vec<u64> {
-        &mut self.latency_moy_sampled
+//Name of the PTR record of an IP => 4.3.2.1.in-addr.arpa, b.a.9.8. ... .ip6.arpa
+fn reverse_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, d] = ip.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a)
+        }
+        IpAddr::V6(ip) => {
+            let nibbles: Vec<String> = ip
+                .octets()
+                .iter()
+                .rev()
+                .flat_map(|x| [x & 0x0f, x >> 4])
+                .map(|x| format!("{:x}", x))
+                .collect();
+            format!("{}.ip6.arpa", nibbles.join("."))
+        }
     }
+}
 
-    //Return the latency_max_sampled vec => used in the definition of NetworkTool Methods trait when it wants to read and modify object attribute
-    fn latency_max_sampled(&mut self) -> &mut Vec<u64> {
-        &mut self.latency_max_sampled
+//DNS request of one question with recursion desired
+fn dns_query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(DNS_HEADER_SIZE + name.len() + 6);
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&0x0100u16.to_be_bytes()); //Standard query, recursion desired
+    packet.extend_from_slice(&1u16.to_be_bytes()); //One question
+    packet.extend_from_slice(&[0; 6]); //No answer, authority or additional record
+
+    for label in name.trim_end_matches('.').split('.').filter(|x| !x.is_empty()) {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label);
     }
+    packet.push(0);
+    packet.extend_from_slice(&qtype.to_be_bytes());
+    packet.extend_from_slice(&1u16.to_be_bytes()); //Class IN
+    packet
+}
+
+//Record of a DNS answer used by the resolver
+enum DnsRecord {
+    Ip(IpAddr), //A or AAAA
+    Name(String), //PTR
+}
+
+//DNS answer => its id, its response code and its A, AAAA and PTR records
+struct DnsAnswer {
+    id: u16,
+    rcode: u8,
+    truncated: bool, //TC bit => the records that did not fit are missing, the complete ones are kept
+    records: Vec<DnsRecord>,
+}
+
+//Answers of a nameserver to the questions of a probe
+#[derive(Default)]
+struct DnsAnswers {
+    rcode: u8, //Last response code that is not NOERROR
+    truncated: bool,
+    records: Vec<DnsRecord>,
+}
 
-    //Return the output csv filename attribute
-    fn output(&self) -> &str {
-        &self.output
+impl DnsAnswers {
+    //Result of the probe => the name of a PTR record, else the addresses
+    //A name with only an A or only an AAAA record is resolved
+    fn result(self) -> std::result::Result<DnsResult, DnsError> {
+        let ips: Vec<IpAddr> = self.records.iter().filter_map(|x| match x {
+            DnsRecord::Ip(ip) => Some(*ip),
+            DnsRecord::Name(_) => None,
+        }).collect();
+        let host = self.records.into_iter().find_map(|x| match x {
+            DnsRecord::Name(name) => Some(name),
+            DnsRecord::Ip(_) => None,
+        });
+
+        match (host, ips.is_empty()) {
+            (Some(host), _) => Ok(DnsResult::Host(host)),
+            (None, false) => Ok(DnsResult::Ip(ips)),
+            (None, true) => {
+                let reason = match (self.rcode, self.truncated) {
+                    (0, true) => String::from("truncated answer without address"),
+                    (0, false) => String::from("no address in the answer"),
+                    (rcode, _) => rcode_name(rcode),
+                };
+                Err((ProbeResult::Error(ProbeError::DnsFailure), reason))
+            }
+        }
     }
+}
 
-    //Return the dst attribute object
-    fn target(&self) -> &str {
-        &self.target
+//Parse a DNS answer, None if it is not a valid answer
+fn parse_answer(packet: &[u8]) -> Option<DnsAnswer> {
+    let header = packet.get(..DNS_HEADER_SIZE)?;
+    //QR bit => only responses
+    if header[2] & 0x80 == 0 {
+        return None;
+    }
+    let id = u16::from_be_bytes([header[0], header[1]]);
+    let truncated = header[2] & 0x02 != 0;
+    let rcode = header[3] & 0x0f;
+    let questions = u16::from_be_bytes([header[4], header[5]]);
+    let answers = u16::from_be_bytes([header[6], header[7]]);
+
+    let mut pos = DNS_HEADER_SIZE;
+    for _ in 0..questions {
+        pos = skip_name(packet, pos)? + 4; //Type and class
     }
 
-    //The run function of the ping object
-    //Async because we have the normal continous ping task, and the keyboard key capture task for graph or quit the program
-    #[allow(unused_assignments)]
-    async fn run(&mut self) -> Result <(), io::Error> {
-        let hostname: bool;
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        //A truncated answer may end in the middle of a record => the complete records are kept
+        let Some((rtype, start, len)) = read_record(packet, pos) else {
+            if truncated {
+                break;
+            }
+            return None;
+        };
+        let data = &packet[start..start + len];
 
-        let target_host = self.target.clone();
-        let mut target_ip: std::net::IpAddr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
-        enum DnsResult {
-            Host(String),
-            Ip(Vec<std::net::IpAddr>),
+        match (rtype, len) {
+            (DNS_TYPE_A, 4) => records.push(DnsRecord::Ip(IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3])))),
+            (DNS_TYPE_AAAA, 16) => {
+                let octets: [u8; 16] = data.try_into().ok()?;
+                records.push(DnsRecord::Ip(IpAddr::V6(Ipv6Addr::from(octets))));
+            }
+            (DNS_TYPE_PTR, _) => records.push(DnsRecord::Name(read_name(packet, start)?)),
+            //CNAME and other records => only the final records are kept
+            _ => {}
         }
+        pos = start + len;
+    }
 
-        if target_host.parse::<IpAddr>().is_ok() {
-            hostname = false;
-            target_ip = self.target.parse().expect("Invalid IP address");
+    Some(DnsAnswer { id, rcode, truncated, records })
+}
 
-        }
-        else{
+//Type of the record at a position of a DNS message with the position and the length of its data, None if it is cut
+fn read_record(packet: &[u8], pos: usize) -> Option<(u16, usize, usize)> {
+    let pos = skip_name(packet, pos)?;
+    let fixed = packet.get(pos..pos + 10)?; //Type, class, TTL and data length
+    let rtype = u16::from_be_bytes([fixed[0], fixed[1]]);
+    let len = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+    packet.get(pos + 10..pos + 10 + len)?;
+    Some((rtype, pos + 10, len))
+}
 
-            hostname = true;
+//Position after a name in a DNS message => a compressed name ends with its 2 bytes pointer
+fn skip_name(packet: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *packet.get(pos)? as usize;
+        match len {
+            0 => return Some(pos + 1),
+            x if x & 0xc0 == 0xc0 => return Some(pos + 2),
+            _ => pos += len + 1,
         }
-        
-        let nb_ping: u32 = self.nb_ping;
-
-        //used to do ping while i is < nb_ping
-        let mut i: u32 = 0;
-        
-        //used to do ping while is not equal to scale. 1 scale = 1 sampling for graph
-        let mut j: u16 = 0;
-
-        //used to save the "progression" in vectors to sampling values of news pings only
-        let mut k: usize = 0;
-
-        //define the scale value for sampling
-        let mut scale: u16 = 5;
-
-        let opt_graph = true;
-        
-
-        self.begin_time = self.get_time();
-        
-        
-        //Run this loop while the number of defined executed ping is not exceeded or in infinity
-        while i < nb_ping || nb_ping == 0 {
-
-            //Enable raw terminal to capture correctly input from user
-            use crossterm::terminal::enable_raw_mode;
-            enable_raw_mode()?;
-            if event::poll(Duration::from_millis(20))? {
-                if let Event::Key(key_event) = event::read().unwrap() {
-                    match (key_event.code, key_event.modifiers) {
-                        (KeyCode::Char('g'), KeyModifiers::NONE) => {
-                            // compute sampling before showing graph for latest data
-                            k = self.sampling(k, scale);
-                            // show graph (blocking UI). This will block this async task until closed.
-                            graph_display(
-                                &self.latency_min_sampled,
-                                &self.latency_moy_sampled,
-                                &self.latency_max_sampled,
-                            ).unwrap_or_else(|e|{
-                            eprintln!("Error during graph building: {}", e);
-                            });
-                        }
-                        //If the user do Control C the program will be exit.
-                        (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
-                            use crossterm::terminal::disable_raw_mode;
-                            //Disable raw terminal to prevent displaying issue of println!
-                            disable_raw_mode()?;
-                            //Before quit the program => calcul statistics: min/avg/max and % of packet loss
-                            self.latency_data();
-                            return Ok(())
-
-                        }
-                        
-                        _ => {}
-                    }
-                }
-            }
-            
-            let target_host_clone = target_host.clone();
-            //Start to count the time
-            let start = Instant::now();
-            //Async nslookup task => Async because we have this task + key input detection task above in same time
-            let nslookup_result = match timeout(
-                Duration::from_secs(5),
-                task::spawn_blocking(move || -> Result<DnsResult, Box<dyn std::error::Error + Send>> {
-                if !hostname {
-                    let result_host = lookup_addr(&target_ip)
-                        .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)?;
-                    Ok(DnsResult::Host(result_host))
-                } else {
-                    let result_ip = lookup_host(&target_host_clone)
-                        .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)?
-                        .collect::<Vec<_>>();
-                    Ok(DnsResult::Ip(result_ip))
-                }
-            })
-            ).await {
-                    Ok(task_result) => {
-                match task_result {
-                    Ok(Ok(result)) => result,
-                    Ok(Err(e)) => {
-                        use crossterm::terminal::disable_raw_mode;
-                        disable_raw_mode()?;
-                        eprintln!("DNS lookup error: {}", e);
-                        if e.to_string().contains("Name or service not known"){
-                            std::process::exit(1);
-                        }
-                        enable_raw_mode()?;
-                        continue; // Skip this iteration
-                    }
-                    Err(e) => {
-                        eprintln!("Task spawn error: {}", e);
-                        DnsResult::Host(String::from("Task error"))
-                    }
-                }
-            }
-            // Timeout elapsed (5 seconds)
-            Err(_) => {
-                use crossterm::terminal::disable_raw_mode;
-                disable_raw_mode()?;
-                println!("DNS lookup timeout (5 seconds)");
-                enable_raw_mode()?;
-                DnsResult::Host(String::from("Timeout")) // Skip this iteration
+    }
+}
+
+//Read a name of a DNS message, following the compression pointers
+fn read_name(packet: &[u8], mut pos: usize) -> Option<String> {
+    let mut labels = Vec::new();
+    //At most 128 labels and pointers => a loop of pointers is rejected
+    for _ in 0..128 {
+        let len = *packet.get(pos)? as usize;
+        match len {
+            0 => return Some(labels.join(".")),
+            x if x & 0xc0 == 0xc0 => pos = (u16::from_be_bytes([x as u8, *packet.get(pos + 1)?]) & 0x3fff) as usize,
+            _ => {
+                labels.push(String::from_utf8_lossy(packet.get(pos + 1..pos + 1 + len)?).into_owned());
+                pos += len + 1;
             }
-        };
-            //Elasped time calculation
-            let elapsed = start.elapsed();
+        }
+    }
+    None
+}
 
-            let mut time: u16 = 0;
-            //Convert elasped time in ms to display after the ping latency
-            time = elapsed.as_millis().try_into().unwrap();
+//Name of a DNS response code
+fn rcode_name(rcode: u8) -> String {
+    match rcode {
+        1 => String::from("FORMERR"),
+        2 => String::from("SERVFAIL"),
+        3 => String::from("NXDOMAIN"),
+        4 => String::from("NOTIMP"),
+        5 => String::from("REFUSED"),
+        x => format!("RCODE {}", x),
+    }
+}
 
-            //Disable raw terminal to prevent displaying issue of println!
-            use crossterm::terminal::disable_raw_mode;
-            disable_raw_mode()?;
-            println!("DNS request n°{} DNS request latency: {} ms", i, time);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            match nslookup_result {
-                DnsResult::Host(h) => println!("DNS query result: {}\n", h),
-                DnsResult::Ip(ips) => println!("DNS query result: {:?}\n", ips),
-            }
-            enable_raw_mode()?;
+    //Header and question of an answer => id 0x1a2b, the flags and the counts of questions, answers, authority and additional records
+    fn answer_head(flags: u16, answers: u16, authority: u16, question: &[u8], qtype: u16) -> Vec<u8> {
+        let mut packet = vec![0x1a, 0x2b];
+        for value in [flags, 1, answers, authority, 0] {
+            packet.extend_from_slice(&value.to_be_bytes());
+        }
+        packet.extend_from_slice(question);
+        packet.extend_from_slice(&qtype.to_be_bytes());
+        packet.extend_from_slice(&[0x00, 0x01]);
+        packet
+    }
 
-            //If the ping is >= to 5 sec it is a timeout
-            if time >= 5000{
-            self.data.push(5000);
-            self.sys_time.push(self.get_time());
+    fn ips(answer: &DnsAnswer) -> Vec<IpAddr> {
+        answer.records.iter().filter_map(|x| match x {
+            DnsRecord::Ip(ip) => Some(*ip),
+            DnsRecord::Name(_) => None,
+        }).collect()
+    }
 
-            }
-            else{
-            self.data.push(time as u32);
-            self.sys_time.push(self.get_time());
-            }
+    const EXAMPLE_COM: &[u8] = b"\x07example\x03com\x00";
 
-            //if j == scale we make a sampling of data => make 1 point in the graph
-            if j == scale && opt_graph == true {
-                
-                j = 0;
-                let scale_changed: u16 = scale;
+    #[test]
+    fn query_of_a_name() {
+        let query = dns_query(0x1234, "example.com.", DNS_TYPE_A);
+        let mut expected = vec![0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0];
+        expected.extend_from_slice(EXAMPLE_COM);
+        expected.extend_from_slice(&[0x00, 0x01, 0x00, 0x01]);
+        assert_eq!(query, expected);
+        assert_eq!(dns_query(0x1234, "example.com", DNS_TYPE_A), expected);
+    }
 
-                //Depending of the elapsed time the number of value (number of ping) for sampling is different.
-                if opt_graph == true && self.elapsed_time <= 300{
-                scale = 5;
-                }
-                else if opt_graph == true && self.elapsed_time <= 1800{
-                    scale = 15;
-                }
-                else if opt_graph == true && self.elapsed_time <= 3600{
-                    scale = 30;
-                }
-                else if opt_graph == true && self.elapsed_time <= 7200{
-                    scale = 60;
-                }
-                else if opt_graph == true && self.elapsed_time <= 14400{
-                    scale = 120;
-                }
-                else if opt_graph == true && self.elapsed_time <= 28800{
-                    scale = 240;
-                }
-                else if opt_graph == true && self.elapsed_time <= 57600{
-                    scale = 480;
-                }
-                else if opt_graph == true && self.elapsed_time <= 115200{
-                    scale = 960;
-                }
-                else if opt_graph == true && self.elapsed_time <= 230400{
-                    scale = 1920;
-                }
-                else if opt_graph == true && self.elapsed_time <= 460800{
-                    scale = 3840;
-                }
-                else if opt_graph == true && self.elapsed_time <= 921600{
-                    scale = 7680;
-                }
-                else if opt_graph == true && self.elapsed_time <= 1843200{
-                    scale = 15360;
-                }
+    #[test]
+    fn query_labels_are_cut_at_63_bytes() {
+        let label = "a".repeat(70);
+        let query = dns_query(1, &format!("{}.test", label), DNS_TYPE_AAAA);
+        assert_eq!(query[DNS_HEADER_SIZE], 63);
+        assert_eq!(&query[DNS_HEADER_SIZE + 64..DNS_HEADER_SIZE + 69], b"\x04test");
+        assert_eq!(&query[query.len() - 4..], &[0x00, 0x1c, 0x00, 0x01]);
+    }
 
-                //Detect if the scale value has changed => if its the case we sampling again all ping latency data for the new scale
-                if scale_changed != scale{
-                    k = 0;
+    #[test]
+    fn reverse_names() {
+        assert_eq!(reverse_name("10.99.0.1".parse().unwrap()), "1.0.99.10.in-addr.arpa");
+        let v6 = reverse_name("2001:db8::567:89ab".parse().unwrap());
+        assert_eq!(v6, "b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa");
+    }
 
-                    //For each new scale we clear sampled data to re-sample again all current data but in the new scale of sampling.
-                    self.latency_max_sampled.clear();
-                    self.latency_moy_sampled.clear();
-                    self.latency_min_sampled.clear();
+    #[test]
+    fn answer_with_compressed_name() {
+        //A record of example.com, its name is a pointer to the question
+        let mut packet = answer_head(0x8180, 1, 0, EXAMPLE_COM, DNS_TYPE_A);
+        packet.extend_from_slice(&[0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x04, 93, 184, 215, 14]);
+
+        let answer = parse_answer(&packet).unwrap();
+        assert_eq!(answer.id, 0x1a2b);
+        assert_eq!(answer.rcode, 0);
+        assert!(!answer.truncated);
+        assert_eq!(ips(&answer), vec![IpAddr::V4(Ipv4Addr::new(93, 184, 215, 14))]);
+    }
 
-                    //Send 0 as scale to detect in sampling() that we have changed the scale to sampling again values for this scale
-                    k = self.sampling(k, 0);
-                }
-                k = self.sampling(k, scale);
+    #[test]
+    fn answer_with_cname_chain() {
+        //www.example.org CNAME example.org AAAA 2606:2800:21f:cb07:6820:80da:af6b:8b2c, example.org is at offset 16
+        let mut packet = answer_head(0x8180, 2, 0, b"\x03www\x07example\x03org\x00", DNS_TYPE_AAAA);
+        packet.extend_from_slice(&[0xc0, 0x0c, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x02, 0xc0, 0x10]);
+        packet.extend_from_slice(&[0xc0, 0x10, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x10]);
+        let ip: Ipv6Addr = "2606:2800:21f:cb07:6820:80da:af6b:8b2c".parse().unwrap();
+        packet.extend_from_slice(&ip.octets());
+
+        let answer = parse_answer(&packet).unwrap();
+        assert_eq!(ips(&answer), vec![IpAddr::V6(ip)]);
+    }
 
-            }
-            //Incremente j for each loop trip except if j == scale
-            else if opt_graph == true {
-                j+=1;
-            }
-            i+=1;
-            
-            //Do a sleep of 500ms to do ping each 500 ms => Around 2 pings/sec depending of the ping latency of course
-            sleep(Duration::from_millis(500)).await;
-            }
-            
-        //Disable raw terminal to prevent displaying issue of println!    
-        use crossterm::terminal::disable_raw_mode;
-        disable_raw_mode()?;
+    #[test]
+    fn ptr_answer_follows_the_pointers() {
+        //PTR of 1.0.99.10.in-addr.arpa => web.test
+        let question = b"\x011\x010\x0299\x0210\x07in-addr\x04arpa\x00";
+        let mut packet = answer_head(0x8180, 1, 0, question, DNS_TYPE_PTR);
+        packet.extend_from_slice(&[0xc0, 0x0c, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x0a]);
+        packet.extend_from_slice(b"\x03web\x04test\x00");
+
+        let answer = parse_answer(&packet).unwrap();
+        assert!(matches!(answer.records.as_slice(), [DnsRecord::Name(name)] if name == "web.test"));
+
+        //The name of the data ends with a pointer to the question
+        let mut compressed = answer_head(0x8180, 1, 0, b"\x04test\x00", DNS_TYPE_PTR);
+        compressed.extend_from_slice(&[0xc0, 0x0c, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x06]);
+        compressed.extend_from_slice(&[0x03, b'w', b'e', b'b', 0xc0, 0x0c]);
+        let answer = parse_answer(&compressed).unwrap();
+        assert!(matches!(answer.records.as_slice(), [DnsRecord::Name(name)] if name == "web.test"));
+    }
 
-        //Call this method to calculate and display ping results stats when the number of ping exceed the number defined by the user
-        self.latency_data();
-    Ok(())
+    #[test]
+    fn loop_of_pointers_is_rejected() {
+        let mut packet = answer_head(0x8180, 0, 0, &[0xc0, 0x0c], DNS_TYPE_PTR);
+        packet.truncate(DNS_HEADER_SIZE + 2);
+        assert_eq!(read_name(&packet, DNS_HEADER_SIZE), None);
     }
 
-    
-}
+    #[test]
+    fn nxdomain_answer() {
+        //NXDOMAIN with the SOA of the zone in the authority section
+        let mut packet = answer_head(0x8183, 0, 1, b"\x07missing\x04test\x00", DNS_TYPE_A);
+        packet.extend_from_slice(&[0xc0, 0x14, 0x00, 0x06, 0x00, 0x01, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x02, 0xc0, 0x14]);
 
-//Specific methods of PingTool that not match with the NetworkTool Trait general definition
-impl NSlookup{
-    //Function to set PingTool attributes
-    #[allow(dead_code)]
-    fn setting (&mut self, output: &str, nb_ping: u32){
-        self.output = output.to_string();
-        self.nb_ping = nb_ping;
+        let answer = parse_answer(&packet).unwrap();
+        assert_eq!(answer.rcode, 3);
+        assert!(answer.records.is_empty());
 
+        let answers = DnsAnswers { rcode: answer.rcode, truncated: answer.truncated, records: answer.records };
+        assert!(matches!(answers.result(), Err((ProbeResult::Error(ProbeError::DnsFailure), reason)) if reason == "NXDOMAIN"));
     }
 
-    //Init attributes of the object
-    pub fn new(target: &str, output: &str, nb_ping: u32) -> Self {
-        Self {
-            target: target.to_string(),
-            output: output.to_string(),
-            nb_ping,
-            data: Vec::new(),
-            sys_time: Vec::new(),
-            begin_time: 0,
-            elapsed_time: 0,
-            latency_time: Vec::new(),
-            latency_min: Vec::new(),
-            latency_moy: Vec::new(),
-            latency_max: Vec::new(),
-            latency_min_sampled: Vec::new(),
-            latency_moy_sampled: Vec::new(),
-            latency_max_sampled: Vec::new(),
-        }
+    #[test]
+    fn truncated_answer_keeps_the_complete_records() {
+        //TC bit, 2 A records announced, the second one is cut
+        let mut packet = answer_head(0x8380, 2, 0, EXAMPLE_COM, DNS_TYPE_A);
+        packet.extend_from_slice(&[0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x04, 192, 0, 2, 1]);
+        packet.extend_from_slice(&[0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00]);
+
+        let answer = parse_answer(&packet).unwrap();
+        assert!(answer.truncated);
+        assert_eq!(ips(&answer), vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]);
+
+        //The same answer without the TC bit is not valid
+        packet[2] = 0x81;
+        assert!(parse_answer(&packet).is_none());
+    }
+
+    #[test]
+    fn truncated_answer_without_address_is_a_failure() {
+        let answers = DnsAnswers { rcode: 0, truncated: true, records: Vec::new() };
+        assert!(matches!(answers.result(), Err((_, reason)) if reason == "truncated answer without address"));
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn query_and_short_message_are_not_answers() {
+        assert!(parse_answer(&dns_query(1, "example.com", DNS_TYPE_A)).is_none());
+        assert!(parse_answer(&[0x1a, 0x2b, 0x81, 0x80]).is_none());
+    }
+
+    #[test]
+    fn nameservers_of_resolv_conf() {
+        let content = "# comment\nsearch example.com\nnameserver 10.255.255.53\nnameserver fe80::1%eth0\n nameserver 2001:db8::53\nnameserver 192.0.2.53\nnameserver 192.0.2.54\n";
+        let expected: Vec<IpAddr> = ["10.255.255.53", "2001:db8::53", "192.0.2.53"].iter().map(|x| x.parse().unwrap()).collect();
+        assert_eq!(parse_nameservers(content), expected);
+        assert!(parse_nameservers("options ndots:2\n").is_empty());
+    }
+}
@@ -0,0 +1,344 @@
+use std::ffi::CStr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::os::fd::AsRawFd;
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Socket, Type};
+use tokio::time::timeout_at;
+
+use crate::error::{Error, Result};
+use crate::icmp::{ICMP_HEADER_SIZE, IPV4_HEADER_SIZE, IPV6_HEADER_SIZE, IcmpError, IcmpSocket, Privileges, SocketKind, echo_payload, next_ident};
+use crate::socket::source_ip;
+use crate::tool::{as_ms, resolve_target};
+
+//Smallest MTU of a link => every IPv4 router forwards 68 bytes without fragmentation, every IPv6 link carries 1280 bytes
+pub const IPV4_MIN_MTU: usize = 68;
+pub const IPV6_MIN_MTU: usize = 1280;
+
+//MTU used when the MTU of the local interface cannot be read
+const DEFAULT_LOCAL_MTU: usize = 1500;
+
+//Largest IP packet => the MTU of the loopback is bigger
+const MAX_PACKET_SIZE: usize = 65_535;
+
+//Number of echo requests of a size before considering it is silently dropped => a single loss is not a black hole
+pub const PMTU_TRIES: u8 = 3;
+
+//Result of the echo requests of one size
+#[derive(Debug, Clone, PartialEq)]
+pub enum PmtuResult {
+    Reply(Duration), //The target answered => the size goes through the path
+    TooBig(IcmpError), //A router answered fragmentation needed or packet too big with the MTU of its next link
+    LocalTooBig, //Bigger than the MTU of the local interface => not sent
+    Timeout, //No answer to any try => dropped without ICMP error
+    Error(IcmpError), //Another ICMP error => the target is unreachable
+}
+
+//Echo requests of one size
+#[derive(Debug, Clone, PartialEq)]
+pub struct PmtuProbe {
+    pub size: usize, //Size of the IP packet
+    pub tries: u8, //Number of echo requests sent
+    pub result: PmtuResult,
+}
+
+impl fmt::Display for PmtuProbe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.result {
+            PmtuResult::Reply(latency) => write!(f, "{} bytes: reply in {:.3} ms", self.size, as_ms(*latency)),
+            PmtuResult::TooBig(error) | PmtuResult::Error(error) => write!(f, "{} bytes: {}", self.size, error),
+            PmtuResult::LocalTooBig => write!(f, "{} bytes: bigger than the MTU of the local interface", self.size),
+            PmtuResult::Timeout => write!(f, "{} bytes: no reply to {} tries, dropped without ICMP error", self.size, self.tries),
+        }
+    }
+}
+
+//Result of the path MTU discovery
+#[derive(Debug, Clone, PartialEq)]
+pub struct PmtuReport {
+    pub target_ip: IpAddr,
+    pub local_mtu: usize, //MTU of the interface toward the target
+    pub mtu: Option<usize>, //Largest packet answered by the target, None if it never answered
+    pub too_big: Vec<IcmpError>, //Fragmentation needed and packet too big received => router and MTU of its next link
+    pub black_hole: bool, //Packets bigger than mtu are dropped without fragmentation needed => large transfers hang
+}
+
+impl fmt::Display for PmtuReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "\n--- Path MTU ---\n")?;
+        let Some(mtu) = self.mtu else {
+            return writeln!(f, "{} does not answer, the path MTU cannot be discovered", self.target_ip);
+        };
+        writeln!(f, "Path MTU to {}: {} bytes (local MTU {} bytes)", self.target_ip, mtu, self.local_mtu)?;
+
+        for error in &self.too_big {
+            writeln!(f, "Router {} limits the packets to {} bytes", error.from, error.mtu.unwrap_or_default())?;
+        }
+        if self.black_hole {
+            writeln!(
+                f,
+                "MTU black hole: packets bigger than {} bytes are dropped without fragmentation needed, large transfers can hang",
+                mtu
+            )?;
+        }
+        Ok(())
+    }
+}
+
+//Path MTU discovery tool => binary search of the largest echo request with the Don't Fragment bit answered by the target
+//The MTU given by fragmentation needed and packet too big is tried first
+pub struct PmtuTool {
+    pub target: String, //IP or host to test
+    timeout: Duration, //Time to wait for the answer of an echo request
+    target_ip: IpAddr,
+    ident: u16,
+    seq: u16,
+    socket: Option<IcmpSocket>, //Opened by prepare
+    local_mtu: usize,
+    low: Option<usize>, //Largest size answered
+    high: usize, //Largest size that can still be answered
+    hint: Option<usize>, //MTU given by the last ICMP error
+    stopped: bool, //An ICMP error stopped the search
+    probes: Vec<PmtuProbe>,
+}
+
+impl PmtuTool {
+    //Init attributes of the object
+    pub fn new(target: &str, timeout: Duration) -> Self {
+        Self {
+            target: target.to_string(),
+            timeout,
+            target_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            ident: next_ident(),
+            seq: 0,
+            socket: None,
+            local_mtu: DEFAULT_LOCAL_MTU,
+            low: None,
+            high: DEFAULT_LOCAL_MTU,
+            hint: None,
+            stopped: false,
+            probes: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        "pmtu"
+    }
+
+    //Resolve the target, open the ICMP socket with the Don't Fragment bit and read the MTU of the local interface
+    pub async fn prepare(&mut self) -> Result<String> {
+        self.target_ip = resolve_target(&self.target)?;
+        let socket = IcmpSocket::open(self.target_ip).map_err(|e| match e.kind() {
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied(Privileges::detect().explain(self.name())),
+            _ => Error::from_io(self.name(), e),
+        })?;
+        socket.set_dont_fragment().map_err(|e| Error::from_io(self.name(), e))?;
+
+        self.local_mtu = local_mtu(self.target_ip).unwrap_or(DEFAULT_LOCAL_MTU).min(MAX_PACKET_SIZE);
+        self.high = self.local_mtu;
+        let mode = match socket.kind() {
+            SocketKind::Raw => "",
+            SocketKind::Datagram => " (unprivileged ICMP datagram socket)",
+        };
+        self.socket = Some(socket);
+
+        Ok(format!(
+            "PMTU {} ({}), local MTU {} bytes, Don't Fragment echo requests{}",
+            self.target, self.target_ip, self.local_mtu, mode
+        ))
+    }
+
+    //Smallest MTU of the IP version of the target
+    fn min_mtu(&self) -> usize {
+        if self.target_ip.is_ipv6() { IPV6_MIN_MTU } else { IPV4_MIN_MTU }
+    }
+
+    //Try the next size, None when the search is finished
+    pub async fn next_probe(&mut self) -> Result<Option<&PmtuProbe>> {
+        if self.stopped || self.high < self.min_mtu() || self.low.is_some_and(|low| low >= self.high) {
+            return Ok(None);
+        }
+
+        //The local MTU first => nothing to search on most paths, then the MTU given by a router, else the smallest size to check
+        //that the target answers, then the middle of the sizes not yet tested
+        let size = match (self.low, self.hint.take()) {
+            (None, _) if self.probes.is_empty() => self.high,
+            (low, Some(hint)) if hint > low.unwrap_or(0) && hint <= self.high => hint,
+            (None, _) => self.min_mtu(),
+            (Some(low), _) => (low + self.high).div_ceil(2),
+        };
+
+        let probe = self.probe_size(size).await.map_err(|e| Error::from_io(self.name(), e))?;
+        match &probe.result {
+            PmtuResult::Reply(_) => self.low = Some(size),
+            PmtuResult::TooBig(error) => {
+                self.high = size - 1;
+                if let Some(mtu) = error.mtu.map(|x| x as usize).filter(|x| *x >= self.min_mtu()) {
+                    self.high = self.high.min(mtu);
+                    self.hint = Some(mtu);
+                }
+            }
+            PmtuResult::LocalTooBig | PmtuResult::Timeout => self.high = size - 1,
+            PmtuResult::Error(_) => self.stopped = true,
+        }
+
+        //The smallest size is not answered => the target cannot be reached, the search stops
+        if size == self.min_mtu() && self.low.is_none() {
+            self.stopped = true;
+        }
+
+        self.probes.push(probe);
+        Ok(self.probes.last())
+    }
+
+    //Run the whole discovery without front-end
+    pub async fn run(&mut self) -> Result<PmtuReport> {
+        self.prepare().await?;
+        while self.next_probe().await?.is_some() {}
+        Ok(self.report())
+    }
+
+    //Result of the discovery with the probes done
+    pub fn report(&self) -> PmtuReport {
+        let mut too_big: Vec<IcmpError> = Vec::new();
+        for probe in &self.probes {
+            if let PmtuResult::TooBig(error) = &probe.result
+                && !too_big.contains(error)
+            {
+                too_big.push(error.clone());
+            }
+        }
+
+        PmtuReport {
+            target_ip: self.target_ip,
+            local_mtu: self.local_mtu,
+            mtu: self.low,
+            black_hole: self.low.is_some_and(|mtu| {
+                self.probes.iter().any(|x| x.result == PmtuResult::Timeout && x.size > mtu)
+            }),
+            too_big,
+        }
+    }
+
+    //Send echo requests of an IP packet of `size` bytes until an answer or PMTU_TRIES timeouts
+    async fn probe_size(&mut self, size: usize) -> io::Result<PmtuProbe> {
+        let header = if self.target_ip.is_ipv6() { IPV6_HEADER_SIZE } else { IPV4_HEADER_SIZE } + ICMP_HEADER_SIZE;
+        let payload = echo_payload(size.saturating_sub(header), &[]);
+
+        let mut tries = 0;
+        while tries < PMTU_TRIES {
+            tries += 1;
+            let result = self.echo(&payload).await?;
+            if result != PmtuResult::Timeout {
+                return Ok(PmtuProbe { size, tries, result });
+            }
+        }
+        Ok(PmtuProbe { size, tries, result: PmtuResult::Timeout })
+    }
+
+    //Send one echo request and wait its reply or an ICMP error about it
+    async fn echo(&mut self, payload: &[u8]) -> io::Result<PmtuResult> {
+        let Some(socket) = self.socket.as_ref() else {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "socket not opened"));
+        };
+        self.seq = self.seq.wrapping_add(1);
+
+        let start = Instant::now();
+        match socket.send_echo(self.target_ip, self.ident, self.seq, payload) {
+            Err(e) if e.raw_os_error() == Some(libc::EMSGSIZE) => return Ok(PmtuResult::LocalTooBig),
+            result => result?,
+        }
+
+        let deadline = tokio::time::Instant::from_std(start + self.timeout);
+        loop {
+            let Ok(message) = timeout_at(deadline, socket.recv()).await else {
+                return Ok(PmtuResult::Timeout);
+            };
+            let message = message?;
+
+            if socket.own_echo_error(&message, self.ident) == Some(self.seq)
+                && let Some(error) = message.error
+            {
+                return Ok(if error.mtu.is_some() { PmtuResult::TooBig(error) } else { PmtuResult::Error(error) });
+            }
+            if socket.is_own_echo(&message, self.ident) && message.seq == self.seq && message.from == self.target_ip {
+                return Ok(PmtuResult::Reply(message.received.saturating_duration_since(start)));
+            }
+        }
+    }
+}
+
+//MTU of the interface of the route toward the target => the interface with the source address of the route
+//The MTU of the route is only used when the interface is not found, it includes the path MTU already learned by the kernel
+pub fn local_mtu(target_ip: IpAddr) -> io::Result<usize> {
+    let src_ip = source_ip(target_ip, None)?;
+    if let Some(mtu) = interface_name(src_ip)
+        .and_then(|name| fs::read_to_string(format!("/sys/class/net/{}/mtu", name)).ok())
+        .and_then(|mtu| mtu.trim().parse().ok())
+    {
+        return Ok(mtu);
+    }
+
+    let (domain, level, name) = match target_ip {
+        IpAddr::V4(_) => (Domain::IPV4, libc::IPPROTO_IP, libc::IP_MTU),
+        IpAddr::V6(_) => (Domain::IPV6, libc::IPPROTO_IPV6, libc::IPV6_MTU),
+    };
+    let socket = Socket::new(domain, Type::DGRAM, None)?;
+    socket.connect(&SocketAddr::new(target_ip, 9).into())?;
+
+    let mut mtu: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &mut mtu as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(mtu as usize)
+}
+
+//Name of the interface that has the address, None if no interface has it
+fn interface_name(ip: IpAddr) -> Option<String> {
+    let mut addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut addrs) } < 0 {
+        return None;
+    }
+
+    let mut name = None;
+    let mut current = addrs;
+    while !current.is_null() {
+        let ifaddr = unsafe { &*current };
+        current = ifaddr.ifa_next;
+        if ifaddr.ifa_addr.is_null() {
+            continue;
+        }
+
+        let address = match unsafe { (*ifaddr.ifa_addr).sa_family } as libc::c_int {
+            libc::AF_INET => {
+                let addr = unsafe { &*(ifaddr.ifa_addr as *const libc::sockaddr_in) };
+                IpAddr::V4(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)))
+            }
+            libc::AF_INET6 => {
+                let addr = unsafe { &*(ifaddr.ifa_addr as *const libc::sockaddr_in6) };
+                IpAddr::V6(Ipv6Addr::from(addr.sin6_addr.s6_addr))
+            }
+            _ => continue,
+        };
+        if address == ip {
+            name = Some(unsafe { CStr::from_ptr(ifaddr.ifa_name) }.to_string_lossy().into_owned());
+            break;
+        }
+    }
+
+    unsafe { libc::freeifaddrs(addrs) };
+    name
+}
@@ -0,0 +1,522 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::future::pending;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr};
+use std::os::fd::BorrowedFd;
+use std::time::{Duration, Instant};
+
+use csv::Writer;
+use pnet::packet::tcp::{TcpFlags, TcpPacket};
+use pnet::transport::TransportSender;
+use serde_json::json;
+use socket2::{SockRef, Socket};
+use tokio::io::unix::{AsyncFd, AsyncFdReadyGuard};
+use tokio::time::timeout_at;
+
+use crate::error::{Error, Result};
+use crate::icmp::{IPPROTO_TCP, IcmpError, IcmpMessage, IcmpSocket, SocketKind};
+use crate::socket::SocketOptions;
+use crate::tcp_ping::{
+    DEFAULT_TTL, Ipv4Fields, TcpSegment, open_ipv4_channel, open_ipv6_raw, recv_tcp_segment, reserve_port, send_segment_v4, send_segment_v6,
+};
+use crate::tool::{as_ms, resolve_target};
+
+//Default number of SYN sent per second
+pub const DEFAULT_SCAN_RATE: u32 = 100;
+
+//Highest number of SYN sent per second => a faster scan floods the links and the firewalls
+pub const MAX_SCAN_RATE: u32 = 10_000;
+
+//Columns of the csv export of the port scan
+pub const SCAN_CSV_COLUMNS: [&str; 4] = ["Port", "State", "Reason", "RTT"];
+
+//State of a port deduced from the answer to its SYN
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PortState {
+    Open, //SYN|ACK => a service listens on the port
+    Closed, //RST => the host is reached but nothing listens
+    Filtered, //No answer or an ICMP error => a firewall drops or rejects the SYN
+}
+
+impl PortState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PortState::Open => "open",
+            PortState::Closed => "closed",
+            PortState::Filtered => "filtered",
+        }
+    }
+}
+
+//Result of the SYN of one port
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortResult {
+    pub port: u16,
+    pub state: PortState,
+    pub rtt: Option<Duration>, //None without answer
+    pub error: Option<IcmpError>, //ICMP error about the SYN
+}
+
+impl PortResult {
+    //Why the port has its state => used in the report and the exports
+    pub fn reason(&self) -> String {
+        match (&self.error, self.state) {
+            (Some(error), _) => format!("{} from {}", error.describe().1, error.from),
+            (None, PortState::Open) => String::from("syn-ack"),
+            (None, PortState::Closed) => String::from("rst"),
+            (None, PortState::Filtered) => String::from("no response"),
+        }
+    }
+}
+
+impl fmt::Display for PortResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<11} {:<9} {}", format!("{}/tcp", self.port), self.state.name(), self.reason())?;
+        if let Some(rtt) = self.rtt {
+            write!(f, " in {:.3} ms", as_ms(rtt))?;
+        }
+        Ok(())
+    }
+}
+
+//Result of the whole scan => the ports of the most common state and reason are only counted, like nmap
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanReport {
+    pub target_ip: IpAddr,
+    pub results: Vec<PortResult>, //Sorted by port
+    pub elapsed: Duration,
+}
+
+impl ScanReport {
+    //Number of ports of a state
+    pub fn count(&self, state: PortState) -> usize {
+        self.results.iter().filter(|x| x.state == state).count()
+    }
+}
+
+impl fmt::Display for ScanReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "\n--- Port scan of {} ---\n", self.target_ip)?;
+
+        //The closed or filtered ports of the most common reason are only counted
+        let mut groups: HashMap<(PortState, String), usize> = HashMap::new();
+        for result in self.results.iter().filter(|x| x.state != PortState::Open) {
+            *groups.entry((result.state, result.reason())).or_default() += 1;
+        }
+        let hidden = groups.into_iter().filter(|(_, count)| *count > 1).max_by_key(|(_, count)| *count);
+        let is_hidden = |x: &PortResult| hidden.as_ref().is_some_and(|((state, reason), _)| x.state == *state && x.reason() == *reason);
+
+        let shown: Vec<&PortResult> = self.results.iter().filter(|x| !is_hidden(x)).collect();
+        if !shown.is_empty() {
+            writeln!(f, "{:<11} {:<9} REASON", "PORT", "STATE")?;
+        }
+        for result in shown {
+            writeln!(f, "{}", result)?;
+        }
+        if let Some(((state, reason), count)) = &hidden {
+            writeln!(f, "Not shown: {} {} ports ({})", count, state.name(), reason)?;
+        }
+
+        writeln!(
+            f,
+            "\n{} ports scanned in {:.1} s: {} open, {} closed, {} filtered",
+            self.results.len(),
+            self.elapsed.as_secs_f64(),
+            self.count(PortState::Open),
+            self.count(PortState::Closed),
+            self.count(PortState::Filtered),
+        )
+    }
+}
+
+//Parse a list of ports and ranges separated by commas => "22,80,443,8000-8100", None if a port or a range is not valid
+//A port given twice is scanned once, the order of the list is kept
+pub fn parse_ports(ports: &str) -> Option<Vec<u16>> {
+    let mut list = Vec::new();
+    let mut seen = HashSet::new();
+
+    for part in ports.split(',').map(str::trim) {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (first.trim().parse::<u16>().ok()?, last.trim().parse::<u16>().ok()?),
+            None => {
+                let port = part.parse::<u16>().ok()?;
+                (port, port)
+            }
+        };
+        if first == 0 || first > last {
+            return None;
+        }
+        list.extend((first..=last).filter(|x| seen.insert(*x)));
+    }
+    Some(list)
+}
+
+//SYN waiting for its answer
+struct PendingProbe {
+    segment: TcpSegment,
+    sent: Instant,
+}
+
+//TCP port scan => one SYN per port at a fixed rate, the answers are classified while the next SYN are sent
+//All the SYN leave from one reserved source port, the answers are matched by the port of the target and the sequence numbers
+pub struct ScanTool {
+    pub target: String, //IP or host to scan
+    ports: Vec<u16>,
+    rate: u32, //SYN per second
+    timeout: Duration, //Time to wait for the answer of a SYN
+    output: String, //Output filename => json if it ends with .json, else csv
+    socket_options: SocketOptions, //TOS, TTL, source and interface of the SYN
+    target_ip: IpAddr,
+    src_ip: IpAddr,
+    src_port: u16,
+    reservation: Option<Socket>, //Keeps the source port of the host for the scan
+    ipv4_channel: Option<(TransportSender, AsyncFd<Socket>)>, //Layer3 raw socket, its receiving half is read through the tokio reactor
+    ipv6_socket: Option<AsyncFd<Socket>>,
+    icmp: Option<IcmpSocket>,
+    next: usize, //Index of the next port to probe
+    next_send: Instant,
+    pending: HashMap<u16, PendingProbe>,
+    answered: VecDeque<PortResult>, //Classified, not yet returned by next_port
+    results: Vec<PortResult>,
+    start: Instant,
+}
+
+impl ScanTool {
+    //Init attributes of the object
+    pub fn new(target: &str, ports: Vec<u16>, rate: u32, timeout: Duration, output: &str, socket_options: SocketOptions) -> Self {
+        Self {
+            target: target.to_string(),
+            ports,
+            rate: rate.max(1),
+            timeout,
+            output: output.to_string(),
+            socket_options,
+            target_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            src_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            src_port: 0,
+            reservation: None,
+            ipv4_channel: None,
+            ipv6_socket: None,
+            icmp: None,
+            next: 0,
+            next_send: Instant::now(),
+            pending: HashMap::new(),
+            answered: VecDeque::new(),
+            results: Vec::new(),
+            start: Instant::now(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        "scan"
+    }
+
+    pub fn results(&self) -> &[PortResult] {
+        &self.results
+    }
+
+    //Resolve the target, reserve the source port and open the raw sockets, return the description of the scan
+    pub async fn prepare(&mut self) -> Result<String> {
+        self.target_ip = resolve_target(&self.target)?;
+        self.socket_options.check(self.target_ip)?;
+        let io_error = |e: io::Error| match e.kind() {
+            io::ErrorKind::PermissionDenied => {
+                Error::PermissionDenied(String::from("scan failed: operation not permitted, raw sockets need root or CAP_NET_RAW"))
+            }
+            _ => Error::from_io("scan", e),
+        };
+
+        self.src_ip = self.socket_options.source_ip(self.target_ip).map_err(io_error)?;
+        let (reservation, src_port) = reserve_port(self.src_ip).map_err(io_error)?;
+        self.reservation = Some(reservation);
+        self.src_port = src_port;
+
+        match self.target_ip {
+            //The IPv4 header is ours => only the interface is a socket option, TOS and TTL are set in each packet
+            IpAddr::V4(_) => {
+                let channel = open_ipv4_channel().map_err(io_error)?;
+                //SAFETY: the descriptor is owned by the channel, alive while it is borrowed
+                let fd = unsafe { BorrowedFd::borrow_raw(channel.0.socket.fd) };
+                self.socket_options.apply(&SockRef::from(&fd), self.target_ip).map_err(io_error)?;
+                self.ipv4_channel = Some(channel);
+            }
+            //The kernel builds the IPv6 header => traffic class, hop limit, interface and source are socket options
+            IpAddr::V6(_) => {
+                let socket = open_ipv6_raw().map_err(io_error)?;
+                self.socket_options.apply(socket.get_ref(), self.target_ip).map_err(io_error)?;
+                self.socket_options.bind(socket.get_ref()).map_err(io_error)?;
+                self.ipv6_socket = Some(socket);
+            }
+        }
+
+        //ICMP errors only tell why a port is filtered => the scan still works if the socket cannot be opened
+        self.icmp = IcmpSocket::open_kind(self.target_ip, SocketKind::Raw).ok();
+
+        self.start = Instant::now();
+        self.next_send = self.start;
+        let marking = SocketOptions { source: None, ..self.socket_options.clone() };
+        let marking = if marking.is_default() { String::new() } else { format!(" {}", marking) };
+        Ok(format!(
+            "SCAN {} ({}), {} ports from {}:{}, {} SYN/s{}",
+            self.target,
+            self.target_ip,
+            self.ports.len(),
+            self.src_ip,
+            self.src_port,
+            self.rate,
+            marking
+        ))
+    }
+
+    //Classify the next port, None when every port is classified
+    //The ports are returned in the order of their answer
+    pub async fn next_port(&mut self) -> Result<Option<&PortResult>> {
+        loop {
+            if let Some(result) = self.answered.pop_front() {
+                self.results.push(result);
+                return Ok(self.results.last());
+            }
+
+            //No answer before the timeout => dropped by a firewall
+            let now = Instant::now();
+            let expired: Vec<u16> = self.pending.iter().filter(|(_, x)| now - x.sent >= self.timeout).map(|(port, _)| *port).collect();
+            for port in expired {
+                self.pending.remove(&port);
+                self.answered.push_back(PortResult { port, state: PortState::Filtered, rtt: None, error: None });
+            }
+            if !self.answered.is_empty() {
+                continue;
+            }
+
+            let sending = self.next < self.ports.len();
+            if !sending && self.pending.is_empty() {
+                return Ok(None);
+            }
+            if sending && now >= self.next_send {
+                self.send_next().map_err(|e| Error::from_io(self.name(), e))?;
+            }
+
+            //Wait the answers until the next SYN to send or the next SYN without answer => at a high rate the answers already received are only read
+            let expiry = self.pending.values().map(|x| x.sent + self.timeout).min();
+            let until = match (self.next < self.ports.len(), expiry) {
+                (true, Some(expiry)) => self.next_send.min(expiry),
+                (true, None) => self.next_send,
+                (false, Some(expiry)) => expiry,
+                (false, None) => continue,
+            };
+            self.receive(until).await.map_err(|e| Error::from_io(self.name(), e))?;
+        }
+    }
+
+    //Run the whole scan without front-end
+    pub async fn run(&mut self) -> Result<ScanReport> {
+        self.prepare().await?;
+        while self.next_port().await?.is_some() {}
+        Ok(self.report())
+    }
+
+    //Result of the scan with the ports already classified
+    pub fn report(&self) -> ScanReport {
+        let mut results = self.results.clone();
+        results.sort_by_key(|x| x.port);
+        ScanReport { target_ip: self.target_ip, results, elapsed: self.start.elapsed() }
+    }
+
+    //Send the SYN of the next port
+    fn send_next(&mut self) -> io::Result<()> {
+        let port = self.ports[self.next];
+        self.next += 1;
+        self.next_send += Duration::from_secs(1) / self.rate;
+
+        let segment = TcpSegment::new(self.src_port, port, TcpFlags::SYN);
+        self.send(&segment)?;
+        self.pending.insert(port, PendingProbe { segment, sent: Instant::now() });
+        Ok(())
+    }
+
+    //Send a segment to the target with the socket of its IP version
+    fn send(&mut self, segment: &TcpSegment) -> io::Result<()> {
+        match (self.target_ip, self.src_ip, self.ipv4_channel.as_mut(), self.ipv6_socket.as_ref()) {
+            (IpAddr::V4(dst), IpAddr::V4(src), Some((sender, _)), _) => {
+                let fields = Ipv4Fields {
+                    ttl: self.socket_options.ttl.unwrap_or(DEFAULT_TTL),
+                    tos: self.socket_options.tos.unwrap_or(0),
+                };
+                send_segment_v4(sender, src, dst, segment, fields)
+            }
+            (IpAddr::V6(dst), IpAddr::V6(src), _, Some(socket)) => send_segment_v6(socket.get_ref(), src, dst, segment),
+            _ => Err(io::Error::new(io::ErrorKind::NotConnected, "socket not opened")),
+        }
+    }
+
+    //Wait the segments of the target and the ICMP errors until `until`, without blocking a worker of tokio
+    //Every segment and error already received is read => the buffer of the raw socket does not overflow at a high rate
+    async fn receive(&mut self, until: Instant) -> io::Result<()> {
+        let receiver = match (self.target_ip, self.ipv4_channel.as_ref(), self.ipv6_socket.as_ref()) {
+            (IpAddr::V4(_), Some((_, receiver)), _) => receiver,
+            (IpAddr::V6(_), _, Some(socket)) => socket,
+            _ => return Err(io::Error::new(io::ErrorKind::NotConnected, "socket not opened")),
+        };
+        let (target_ip, icmp) = (self.target_ip, self.icmp.as_ref());
+
+        let mut segments: Vec<Vec<u8>> = Vec::new();
+        let mut messages: Vec<IcmpMessage> = Vec::new();
+        let wait = async {
+            tokio::select! {
+                guard = receiver.readable() => read_segments(&mut guard?, target_ip, &mut segments),
+                message = recv_icmp(icmp) => {
+                    messages.push(message);
+                    Ok(())
+                }
+            }
+        };
+        if let Ok(received) = timeout_at(tokio::time::Instant::from_std(until), wait).await {
+            received?;
+        }
+        if let Some(icmp) = icmp {
+            while let Ok(Some(message)) = icmp.try_recv() {
+                messages.push(message);
+            }
+        }
+
+        for segment in segments {
+            if let Some(tcp) = TcpPacket::new(&segment) {
+                self.classify(&tcp);
+            }
+        }
+        for message in messages {
+            self.classify_icmp(message);
+        }
+        Ok(())
+    }
+
+    //Classify the port of a segment that answers its SYN => open for SYN|ACK, closed for RST
+    //The half-open connexion of an open port is closed by our RST
+    fn classify(&mut self, tcp: &TcpPacket) {
+        let (port, flags) = (tcp.get_source(), tcp.get_flags());
+        if tcp.get_destination() != self.src_port || !self.pending.get(&port).is_some_and(|x| x.segment.is_answered_by(tcp)) {
+            return;
+        }
+
+        let state = if flags & TcpFlags::RST != 0 {
+            PortState::Closed
+        } else if flags & (TcpFlags::SYN | TcpFlags::ACK) == TcpFlags::SYN | TcpFlags::ACK {
+            PortState::Open
+        } else {
+            return;
+        };
+        let Some(probe) = self.pending.remove(&port) else {
+            return;
+        };
+        if let Some(reset) = probe.segment.reset_for(flags) {
+            let _ = self.send(&reset);
+        }
+        self.answered.push_back(PortResult { port, state, rtt: Some(probe.sent.elapsed()), error: None });
+    }
+
+    //Classify the port of the SYN quoted by an ICMP error => filtered with the error
+    fn classify_icmp(&mut self, message: IcmpMessage) {
+        let (Some(error), Some(quoted)) = (message.error, message.quoted) else {
+            return;
+        };
+        let Some((src_port, port)) = quoted.ports() else {
+            return;
+        };
+        if quoted.protocol != IPPROTO_TCP || quoted.dst != self.target_ip || src_port != self.src_port {
+            return;
+        }
+        let own = self.pending.get(&port).is_some_and(|x| quoted.header.get(4..8).is_none_or(|seq| seq == x.segment.seq.to_be_bytes()));
+        if let Some(probe) = self.pending.remove(&port).filter(|_| own) {
+            let rtt = message.received.saturating_duration_since(probe.sent);
+            self.answered.push_back(PortResult { port, state: PortState::Filtered, rtt: Some(rtt), error: Some(error) });
+        }
+    }
+
+    //Export the ports in json if the output ends with .json, else in csv
+    pub fn export(&self) -> Result<()> {
+        if self.output.ends_with(".json") {
+            self.export_json()
+        } else {
+            self.export_csv()
+        }
+    }
+
+    //Export the ports in csv => one row per port sorted by port, the RTT is empty without answer
+    pub fn export_csv(&self) -> Result<()> {
+        let export_error = |source: io::Error| Error::Export { path: self.output.clone(), source };
+        let file = File::create(&self.output).map_err(export_error)?;
+        let mut writer = Writer::from_writer(file);
+
+        writer.write_record(SCAN_CSV_COLUMNS).map_err(|e| export_error(e.into()))?;
+        for result in self.report().results {
+            let record = [
+                result.port.to_string(),
+                result.state.name().to_string(),
+                result.reason(),
+                result.rtt.map_or(String::new(), |x| format!("{:.3}", as_ms(x))),
+            ];
+            writer.write_record(&record).map_err(|e| export_error(e.into()))?;
+        }
+        writer.flush().map_err(export_error)?;
+        Ok(())
+    }
+
+    //Export the scan in json => target and the ports sorted by port with their state, reason and RTT
+    pub fn export_json(&self) -> Result<()> {
+        let export_error = |source: io::Error| Error::Export { path: self.output.clone(), source };
+        let report = self.report();
+
+        let ports: Vec<serde_json::Value> = report
+            .results
+            .iter()
+            .map(|result| {
+                json!({
+                    "port": result.port,
+                    "state": result.state.name(),
+                    "reason": result.reason(),
+                    "rtt_ms": result.rtt.map(as_ms),
+                    "icmp_type": result.error.as_ref().map(|e| e.icmp_type),
+                    "icmp_code": result.error.as_ref().map(|e| e.code),
+                })
+            })
+            .collect();
+
+        let scan = json!({
+            "target": self.target,
+            "target_ip": report.target_ip.to_string(),
+            "open": report.count(PortState::Open),
+            "closed": report.count(PortState::Closed),
+            "filtered": report.count(PortState::Filtered),
+            "ports": ports,
+        });
+
+        let mut file = File::create(&self.output).map_err(export_error)?;
+        serde_json::to_writer_pretty(&mut file, &scan).map_err(|e| export_error(e.into()))?;
+        file.write_all(b"\n").map_err(export_error)
+    }
+}
+
+//Read every segment of the target queued on the raw socket, the readiness is cleared once the queue is empty
+fn read_segments(guard: &mut AsyncFdReadyGuard<'_, Socket>, target_ip: IpAddr, segments: &mut Vec<Vec<u8>>) -> io::Result<()> {
+    let mut buf = [0u8; 1024];
+    loop {
+        match guard.try_io(|fd| recv_tcp_segment(fd.get_ref(), &mut buf, target_ip)) {
+            Ok(Ok(Some((range, _ttl)))) => segments.push(buf[range].to_vec()),
+            Ok(Ok(None)) => {}
+            Ok(Err(e)) => return Err(e),
+            Err(_would_block) => return Ok(()),
+        }
+    }
+}
+
+//Wait the next ICMP message, never returns without the socket or if it fails => the errors only tell why a port is filtered
+async fn recv_icmp(icmp: Option<&IcmpSocket>) -> IcmpMessage {
+    if let Some(icmp) = icmp
+        && let Ok(message) = icmp.recv().await
+    {
+        return message;
+    }
+    pending().await
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+use socket2::{Domain, SockRef, Socket, Type};
+use tokio::net::{TcpSocket, TcpStream};
+
+use crate::error::{Error, Result};
+
+//Options of the sockets of a tool => QoS marking, TTL and source of the packets
+//None => the value chosen by the system
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    pub tos: Option<u8>, //TOS byte of IPv4 or traffic class of IPv6 => DSCP in the 6 high bits, ECN in the 2 low bits
+    pub ttl: Option<u8>, //TTL of IPv4 or hop limit of IPv6
+    pub source: Option<IpAddr>, //Source address of the packets, must be an address of the host
+    pub interface: Option<String>, //Interface used to send the packets whatever the routes => SO_BINDTODEVICE
+}
+
+impl SocketOptions {
+    //Return true if no option is given => the sockets are left as the system opens them
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    //Check that the source address can reach the target => same IP version
+    pub fn check(&self, target: IpAddr) -> Result<()> {
+        match self.source {
+            Some(source) if source.is_ipv4() != target.is_ipv4() => {
+                Err(Error::InvalidAddress(format!("source {} cannot be used to reach {}", source, target)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    //Set the TOS, the TTL and the interface of a socket of the IP version of the target
+    //The source address is set apart by bind => a listening socket keeps its own address
+    pub fn apply(&self, socket: &Socket, target: IpAddr) -> io::Result<()> {
+        if let Some(tos) = self.tos {
+            match target {
+                IpAddr::V4(_) => socket.set_tos_v4(tos as u32)?,
+                IpAddr::V6(_) => socket.set_tclass_v6(tos as u32)?,
+            }
+        }
+        if let Some(ttl) = self.ttl {
+            match target {
+                IpAddr::V4(_) => socket.set_ttl_v4(ttl as u32)?,
+                IpAddr::V6(_) => socket.set_unicast_hops_v6(ttl as u32)?,
+            }
+        }
+        if let Some(interface) = &self.interface {
+            socket.bind_device(Some(interface.as_bytes()))?;
+        }
+        Ok(())
+    }
+
+    //Bind the socket to the source address with a port chosen by the kernel, nothing without source
+    pub fn bind(&self, socket: &Socket) -> io::Result<()> {
+        if let Some(source) = self.source {
+            socket.bind(&SocketAddr::new(source, 0).into())?;
+        }
+        Ok(())
+    }
+
+    //Connect a TCP socket with the TOS, TTL, interface and source of the options, Nagle is disabled for the small requests of the probes
+    pub(crate) async fn connect(&self, addr: SocketAddr) -> io::Result<TcpStream> {
+        let socket = match addr.ip() {
+            IpAddr::V4(_) => TcpSocket::new_v4()?,
+            IpAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+        let sock_ref = SockRef::from(&socket);
+        self.apply(&sock_ref, addr.ip())?;
+        self.bind(&sock_ref)?;
+        socket.set_nodelay(true)?;
+        socket.connect(addr).await
+    }
+
+    //Source address of the packets sent to the target => the given source, else the one of the route through the interface
+    pub fn source_ip(&self, target: IpAddr) -> io::Result<IpAddr> {
+        match self.source {
+            Some(source) => Ok(source),
+            None => source_ip(target, self.interface.as_deref()),
+        }
+    }
+}
+
+//Options given by the user in the description of a run => "from 10.0.0.2 dev eth1 tos 0xb8 ttl 10"
+impl fmt::Display for SocketOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(source) = self.source {
+            parts.push(format!("from {}", source));
+        }
+        if let Some(interface) = &self.interface {
+            parts.push(format!("dev {}", interface));
+        }
+        if let Some(tos) = self.tos {
+            parts.push(format!("tos 0x{:02x} (dscp {})", tos, tos >> 2));
+        }
+        if let Some(ttl) = self.ttl {
+            parts.push(format!("ttl {}", ttl));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+//Local source IP used to reach the target: connect a UDP socket of the address family of the target and let the kernel choose the route
+//With an interface the route is looked up through this interface only
+pub(crate) fn source_ip(target_ip: IpAddr, interface: Option<&str>) -> io::Result<IpAddr> {
+    let socket = Socket::new(Domain::for_address(SocketAddr::new(target_ip, 0)), Type::DGRAM, None)?;
+    if let Some(interface) = interface {
+        socket.bind_device(Some(interface.as_bytes()))?;
+    }
+    socket.connect(&SocketAddr::new(target_ip, 80).into())?;
+
+    socket
+        .local_addr()?
+        .as_socket()
+        .map(|x| x.ip())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::AddrNotAvailable, "no source address"))
+}
@@ -1,8 +1,9 @@
+use crate::error::Result;
+use crate::socket::SocketOptions;
 use crate::tool::ConnectTool;
-use std::net::IpAddr;
-use std::net::Ipv4Addr;
-use tokio::net::TcpListener;
-use tokio::net::TcpStream;
+use socket2::SockRef;
+use std::net::{IpAddr, SocketAddr};
+use tokio::net::TcpSocket;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::time::{sleep, Instant, Duration};
 
@@ -14,6 +15,7 @@ pub struct SpeedTest{
     tst_duration: u64, //Duration of the speedtest
     mbps: u64, //Bandwidth limit for the speedtest
     mode: String, //full => upload + Download, upload => upload only, download => download only
+    socket_options: SocketOptions, //TOS, TTL, interface of the connexions + source of the client
 }
 
 //Methods definition for methods inerhited by the trait
@@ -30,9 +32,9 @@ impl ConnectTool for SpeedTest{
 
     #[allow(unused_variables)]
     //Main method
-    async fn run(&mut self) -> std::io::Result<()> {
+    async fn run(&mut self) -> Result<()> {
         //Server mode
-        if self.is_srv == true {
+        if self.is_srv {
             self.start_server().await?;
             return Ok(());
         }
@@ -43,24 +45,19 @@ impl ConnectTool for SpeedTest{
         Ok(())
     }
 
-    #[allow(unused_assignments)]
     //Start server and handle connexion
-    async fn start_server(&mut self) -> std::io::Result<()> {
+    async fn start_server(&mut self) -> Result<()> {
         let duration = self.tst_duration;
 
-        //Need init the IpAddr object before use it, so init with 0.0.0.0 and will be modified later
-        let mut target_ip: IpAddr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
-
         //Resolve if the user given a hostname
-        if self.srv_addr.parse::<IpAddr>().is_ok(){
-            target_ip = self.srv_addr.parse().expect("Invalid IP address");
-
-        }
-        else {
-            target_ip = self.resolve();
-        }
-
-        let listener = TcpListener::bind((target_ip, self.srv_port)).await?;
+        let target_ip = self.resolve()?;
+
+        //The accepted connexions inherit the TOS, TTL and interface of the listening socket
+        let socket = tcp_socket(target_ip)?;
+        self.socket_options.apply(&SockRef::from(&socket), target_ip)?;
+        socket.set_reuseaddr(true)?;
+        socket.bind(SocketAddr::new(target_ip, self.srv_port))?;
+        let listener = socket.listen(1024)?;
         println!("Server listening on port {}", &self.srv_port);
 
         loop {
@@ -104,24 +101,18 @@ impl ConnectTool for SpeedTest{
         }
     }
 
-    #[allow(unused_assignments)]
     //Handle the client side
-    async fn client(&mut self) -> std::io::Result<()> {
-        //Need init the IpAddr object before use it, so init with 0.0.0.0 and will be modified later
-        let mut target_ip: IpAddr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
-
+    async fn client(&mut self) -> Result<()> {
         //Resolve if the user given a hostname
-        if self.srv_addr.parse::<IpAddr>().is_ok(){
-            target_ip = self.srv_addr.parse().expect("Invalid IP address");
-        }
-        else {
-            target_ip = self.resolve();
-        }
-        let target_string = target_ip.to_string();
-
-        //Build string with addr of server + the dst port to have this format => 1.2.3.4:80
-        let addr_port = target_string + ":" + self.srv_port.to_string().as_str();
-        let mut socket = TcpStream::connect(addr_port).await?;
+        let target_ip = self.resolve()?;
+        self.socket_options.check(target_ip)?;
+
+        //Connect from the source and interface given by the user with their TOS and TTL
+        let socket = tcp_socket(target_ip)?;
+        let sock_ref = SockRef::from(&socket);
+        self.socket_options.apply(&sock_ref, target_ip)?;
+        self.socket_options.bind(&sock_ref)?;
+        let mut socket = socket.connect(SocketAddr::new(target_ip, self.srv_port)).await?;
         println!("Connected to server {}:{}", &self.srv_addr, &self.srv_port);
 
         //Run upload if the mode is full or upload
@@ -155,7 +146,7 @@ impl ConnectTool for SpeedTest{
                         Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
                             tokio::task::yield_now().await;
                         }
-                        Err(e) => return Err(e),
+                        Err(e) => return Err(e.into()),
                     }
                 }
                 
@@ -214,18 +205,27 @@ impl SpeedTest{
     }
 
     //Init object method
-    pub fn new(srv_addr: &str, srv_port: u16, mode: &str, is_srv: bool, tst_duration: u64, mbps: u64) -> Self {
+    pub fn new(srv_addr: &str, srv_port: u16, mode: &str, is_srv: bool, tst_duration: u64, mbps: u64, socket_options: SocketOptions) -> Self {
         Self {
             srv_addr: srv_addr.to_string(),
-            srv_port: srv_port,
-            is_srv: is_srv,
-            tst_duration: tst_duration,
-            mbps: mbps,
+            srv_port,
+            is_srv,
+            tst_duration,
+            mbps,
             mode: mode.to_string(),
+            socket_options,
         }
     }
 }
 
+//TCP socket of the IP version of the address
+fn tcp_socket(ip: IpAddr) -> std::io::Result<TcpSocket> {
+    match ip {
+        IpAddr::V4(_) => TcpSocket::new_v4(),
+        IpAddr::V6(_) => TcpSocket::new_v6(),
+    }
+}
+
 //Handle upload method for server part
 async fn handle_upload(socket: &mut tokio::net::TcpStream, limit_bytes_per_sec: usize, duration_secs: u64) -> std::io::Result<()> {
     let mut buffer = vec![0u8; 64 * 1024];
@@ -0,0 +1,480 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::tool::{PHASES, ProbeResult, ProbeTime, ReplyDetail, SampledSeries, Statistics, Timings, percentile, sampled_series};
+
+//Number of latencies kept sorted for exact percentiles and mean deviation => beyond, they are approximated with the histogram
+const EXACT_SAMPLE: usize = 1_024;
+
+//Resolutions of the buckets => width of a bucket and number of buckets kept
+//1 s buckets during 1 hour, 1 min buckets during 1 day, 1 h buckets during 90 days
+pub const RESOLUTIONS: [(Duration, usize); 3] = [
+    (Duration::from_secs(1), 3_600),
+    (Duration::from_secs(60), 1_440),
+    (Duration::from_secs(3_600), 2_160),
+];
+
+//Relative precision of the latency histogram used for the percentiles of long runs => 1%
+const HISTOGRAM_GROWTH: f64 = 1.01;
+
+//Probes aggregated over a time window => min/avg/max, loss, p95, jitter, ...
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bucket {
+    pub start: Duration, //Monotonic begin of the window since the begin of the run
+    pub wall_ms: u64, //Wall-clock timestamp in ms of the first probe of the window
+    pub stats: Statistics,
+}
+
+//Window still receiving probes => running totals like the ones of the whole run, its statistics are taken when it is closed
+struct OpenBucket {
+    index: u64,
+    wall_ms: u64,
+    totals: Totals,
+}
+
+//Buckets of one resolution
+struct Series {
+    width: Duration,
+    capacity: usize,
+    buckets: VecDeque<Bucket>,
+    open: Option<OpenBucket>,
+    evicted: bool, //True when the oldest buckets have been dropped
+}
+
+impl Series {
+    fn new(width: Duration, capacity: usize) -> Self {
+        Self { width, capacity, buckets: VecDeque::new(), open: None, evicted: false }
+    }
+
+    fn push(&mut self, time: ProbeTime, result: &ProbeResult) {
+        let index = (time.mono.as_nanos() / self.width.as_nanos()) as u64;
+
+        //The probe is in a new window => close the current one
+        if let Some(open) = self.open.take_if(|open| open.index != index) {
+            let (next, wall_ms) = (open.index + 1, open.wall_ms);
+            let bucket = self.close(&open);
+            self.store(bucket);
+
+            //Windows without probe until the new one => empty buckets, the points of the graph stay evenly spaced
+            //Only the last `capacity` ones can be kept, their wall-clock time is the one of the closed window + the elapsed windows
+            let first = next.max(index.saturating_sub(self.capacity as u64));
+            self.evicted |= first > next;
+            for empty in first..index {
+                let wall_ms = wall_ms + (empty - next + 1) * self.width.as_millis() as u64;
+                self.store(Bucket { start: self.width * empty as u32, wall_ms, stats: Statistics::default() });
+            }
+        }
+
+        self.open
+            .get_or_insert_with(|| OpenBucket { index, wall_ms: time.wall_ms, totals: Totals::default() })
+            .totals
+            .push(result);
+    }
+
+    //Keep a closed bucket => the oldest one is dropped beyond the capacity
+    fn store(&mut self, bucket: Bucket) {
+        self.buckets.push_back(bucket);
+        if self.buckets.len() > self.capacity {
+            self.buckets.pop_front();
+            self.evicted = true;
+        }
+    }
+
+    fn close(&self, open: &OpenBucket) -> Bucket {
+        Bucket {
+            start: self.width * open.index as u32,
+            wall_ms: open.wall_ms,
+            stats: open.totals.statistics(),
+        }
+    }
+
+    //Closed buckets + the current window
+    fn buckets(&self) -> Vec<Bucket> {
+        let mut buckets: Vec<Bucket> = self.buckets.iter().cloned().collect();
+        if let Some(open) = &self.open {
+            buckets.push(self.close(open));
+        }
+        buckets
+    }
+
+    //Statistics of the last `count` buckets => only them are copied for the graph
+    fn last_statistics(&self, count: usize) -> Vec<Statistics> {
+        let closed = count.saturating_sub(self.open.is_some() as usize).min(self.buckets.len());
+        let mut stats: Vec<Statistics> = self.buckets.range(self.buckets.len() - closed..).map(|x| x.stats.clone()).collect();
+        if let Some(open) = &self.open {
+            stats.push(open.totals.statistics());
+        }
+        stats
+    }
+
+    fn len(&self) -> usize {
+        self.buckets.len() + self.open.is_some() as usize
+    }
+}
+
+//Running totals of the whole run => constant memory statistics
+#[derive(Default)]
+struct Totals {
+    transmitted: u32,
+    received: u32,
+    min: Option<Duration>,
+    max: Option<Duration>,
+    sum: Duration,
+    sum_sq_us: f64,
+    jitter_us: f64, //RFC 3550 jitter
+    last: Option<Duration>, //Latency of the last reply => used for the jitter
+    histogram: Vec<u64>, //Number of replies per latency bin, bins grow by HISTOGRAM_GROWTH
+    sorted: Vec<Duration>, //Latencies of the replies in order while there are at most EXACT_SAMPLE, then emptied
+    duplicates: u32,
+    out_of_order: u32,
+    icmp_errors: u32,
+    route_changes: u32,
+    phases: [(f64, u32); PHASES], //Sum in µs and number of each phase of Timings::phases() in the replies
+    timed: bool, //True when a reply has timings
+    cert_expiry: Option<u64>, //Certificate expiry of the last TLS reply
+}
+
+impl Totals {
+    fn push(&mut self, result: &ProbeResult) {
+        self.transmitted += 1;
+        if result.is_icmp_error() {
+            self.icmp_errors += 1;
+        }
+        let Some(latency) = result.latency() else {
+            return;
+        };
+        self.received += 1;
+
+        let us = latency.as_micros() as f64;
+        self.min = Some(self.min.map_or(latency, |x| x.min(latency)));
+        self.max = Some(self.max.map_or(latency, |x| x.max(latency)));
+        self.sum += latency;
+        self.sum_sq_us += us * us;
+
+        if let Some(last) = self.last {
+            let d = (us - last.as_micros() as f64).abs();
+            self.jitter_us += (d - self.jitter_us) / 16.0;
+        }
+        self.last = Some(latency);
+
+        let bin = histogram_bin(us);
+        if self.histogram.len() <= bin {
+            self.histogram.resize(bin + 1, 0);
+        }
+        self.histogram[bin] += 1;
+
+        if self.received as usize <= EXACT_SAMPLE {
+            let index = self.sorted.partition_point(|x| *x <= latency);
+            self.sorted.insert(index, latency);
+        } else if !self.sorted.is_empty() {
+            self.sorted = Vec::new();
+        }
+    }
+
+    //Add the phases and the certificate of a reply => average time of each phase and latest expiry
+    fn push_reply(&mut self, reply: &ReplyDetail) {
+        if let Some(timings) = &reply.timings {
+            self.timed = true;
+            for (sum, (_, phase)) in self.phases.iter_mut().zip(timings.phases()) {
+                if let Some(phase) = phase {
+                    sum.0 += phase.as_micros() as f64;
+                    sum.1 += 1;
+                }
+            }
+        }
+        if reply.cert_expiry.is_some() {
+            self.cert_expiry = reply.cert_expiry;
+        }
+    }
+
+    //Average time of each phase of the replies, None if no reply has timings
+    fn timings(&self) -> Option<Timings> {
+        self.timed
+            .then(|| Timings::from_phases(self.phases.map(|(sum, count)| (count > 0).then(|| from_us(sum / count as f64)))))
+    }
+
+    //Statistics of the whole run => percentiles and mean deviation are approximated with the histogram beyond EXACT_SAMPLE replies
+    fn statistics(&self) -> Statistics {
+        let mut stats = Statistics {
+            transmitted: self.transmitted,
+            received: self.received,
+            icmp_errors: self.icmp_errors,
+            ..Default::default()
+        };
+        if self.transmitted == 0 {
+            return stats;
+        }
+        stats.loss_percent = ((self.transmitted - self.received) as f64 / self.transmitted as f64) * 100.0;
+        if self.received == 0 {
+            return stats;
+        }
+
+        let n = self.received as f64;
+        let avg = self.sum / self.received;
+        let avg_us = avg.as_micros() as f64;
+        let variance = (self.sum_sq_us / n - avg_us * avg_us).max(0.0);
+        let mdev = if self.sorted.is_empty() {
+            self.histogram
+                .iter()
+                .enumerate()
+                .map(|(bin, &count)| count as f64 * (histogram_value(bin) - avg_us).abs())
+                .sum::<f64>()
+        } else {
+            self.sorted.iter().map(|x| (x.as_micros() as f64 - avg_us).abs()).sum::<f64>()
+        } / n;
+
+        stats.min = self.min;
+        stats.max = self.max;
+        stats.avg = Some(avg);
+        stats.mdev = Some(from_us(mdev));
+        stats.stddev = Some(from_us(variance.sqrt()));
+        stats.p50 = Some(self.percentile(50.0));
+        stats.p90 = Some(self.percentile(90.0));
+        stats.p95 = Some(self.percentile(95.0));
+        stats.p99 = Some(self.percentile(99.0));
+        stats.jitter = Some(from_us(self.jitter_us));
+        stats
+    }
+
+    //Nearest-rank percentile => exact on the sorted latencies, else on the histogram bounded by the real min and max
+    fn percentile(&self, p: f64) -> Duration {
+        if !self.sorted.is_empty() {
+            return percentile(&self.sorted, p);
+        }
+        let rank = (((p / 100.0) * self.received as f64).ceil() as u64).max(1);
+        let mut cumul = 0;
+        let mut value = 0.0;
+        for (bin, &count) in self.histogram.iter().enumerate() {
+            cumul += count;
+            if cumul >= rank {
+                value = histogram_value(bin);
+                break;
+            }
+        }
+        from_us(value).clamp(self.min.unwrap_or_default(), self.max.unwrap_or_default())
+    }
+}
+
+//Store of the probes of a run with a constant memory
+//The probes are kept in time buckets of 1 s, 1 min and 1 h for the graph and in running totals, the csv export is written as they are done
+pub struct ProbeStore {
+    series: Vec<Series>,
+    totals: Totals,
+}
+
+impl Default for ProbeStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProbeStore {
+    pub fn new() -> Self {
+        Self {
+            series: RESOLUTIONS.iter().map(|&(width, capacity)| Series::new(width, capacity)).collect(),
+            totals: Totals::default(),
+        }
+    }
+
+    //Store the result of a probe with the detail of its reply => phases and certificate of the probe type
+    pub fn push(&mut self, time: ProbeTime, result: ProbeResult, reply: &ReplyDetail) {
+        self.totals.push(&result);
+        if result.is_reply() {
+            self.totals.push_reply(reply);
+        }
+        for series in &mut self.series {
+            series.push(time, &result);
+        }
+    }
+
+    //Number of probes since the begin of the run
+    pub fn len(&self) -> u32 {
+        self.totals.transmitted
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.totals.transmitted == 0
+    }
+
+    //Buckets of a resolution of RESOLUTIONS, from the oldest to the current one
+    pub fn buckets(&self, resolution: usize) -> Vec<Bucket> {
+        self.series[resolution].buckets()
+    }
+
+    //Count the replies that do not answer the current probe => duplicates and replies received after their timeout
+    pub fn count_unexpected(&mut self, duplicates: u32, out_of_order: u32) {
+        self.totals.duplicates += duplicates;
+        self.totals.out_of_order += out_of_order;
+    }
+
+    //Count a change of the hop count of the replies => the route toward the target changed
+    pub fn count_route_change(&mut self) {
+        self.totals.route_changes += 1;
+    }
+
+    //Statistics of the whole run from the running totals => exact percentiles and mean deviation while the replies fit in EXACT_SAMPLE
+    pub fn statistics(&self) -> Statistics {
+        let mut stats = self.totals.statistics();
+        stats.duplicates = self.totals.duplicates;
+        stats.out_of_order = self.totals.out_of_order;
+        stats.route_changes = self.totals.route_changes;
+        stats.timings = self.totals.timings();
+        stats.cert_expiry = self.totals.cert_expiry;
+        stats
+    }
+
+    //Series for the graph => the finest resolution that shows the whole run in max_points, else the last max_points of 1 h buckets
+    pub fn sampled_series(&self, max_points: usize) -> SampledSeries {
+        let max_points = max_points.max(1);
+        let series = self
+            .series
+            .iter()
+            .find(|x| !x.evicted && x.len() <= max_points)
+            .unwrap_or(&self.series[self.series.len() - 1]);
+
+        let stats = series.last_statistics(max_points);
+
+        SampledSeries {
+            min: sampled_series(&stats, |x| x.min),
+            moy: sampled_series(&stats, |x| x.avg),
+            max: sampled_series(&stats, |x| x.max),
+            p95: sampled_series(&stats, |x| x.p95),
+            jitter: sampled_series(&stats, |x| x.jitter),
+        }
+    }
+}
+
+//Bin of the latency histogram of a latency in µs
+fn histogram_bin(us: f64) -> usize {
+    ((us + 1.0).ln() / HISTOGRAM_GROWTH.ln()) as usize
+}
+
+//Latency in µs represented by a bin => middle of the bin
+fn histogram_value(bin: usize) -> f64 {
+    let low = HISTOGRAM_GROWTH.powi(bin as i32) - 1.0;
+    let high = HISTOGRAM_GROWTH.powi(bin as i32 + 1) - 1.0;
+    (low + high) / 2.0
+}
+
+fn from_us(us: f64) -> Duration {
+    Duration::from_micros(us.round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::icmp::IcmpError;
+    use crate::tool::ProbeError;
+
+    fn time(ms: u64) -> ProbeTime {
+        ProbeTime { wall_ms: 1_000_000 + ms, mono: Duration::from_millis(ms) }
+    }
+
+    #[test]
+    fn windows_without_probe_are_empty_buckets() {
+        let mut store = ProbeStore::new();
+        let reply = |ms| ProbeResult::Reply(Duration::from_millis(ms));
+        store.push(time(0), reply(10), &ReplyDetail::default());
+        store.push(time(500), reply(30), &ReplyDetail::default());
+        store.push(time(3_200), ProbeResult::Timeout, &ReplyDetail::default());
+
+        let buckets = store.buckets(0);
+        assert_eq!(buckets.len(), 4);
+        assert_eq!(buckets.iter().map(|x| x.start.as_secs()).collect::<Vec<_>>(), [0, 1, 2, 3]);
+        assert_eq!(buckets.iter().map(|x| x.wall_ms - 1_000_000).collect::<Vec<_>>(), [0, 1_000, 2_000, 3_200]);
+        assert_eq!(buckets.iter().map(|x| x.stats.transmitted).collect::<Vec<_>>(), [2, 0, 0, 1]);
+        assert_eq!(buckets[0].stats.avg, Some(Duration::from_millis(20)));
+        assert_eq!(buckets[3].stats.loss_percent, 100.0);
+
+        //An empty window is a 0 point of the graph like a window without reply
+        assert_eq!(store.sampled_series(10).moy, [20_000, 0, 0, 0]);
+    }
+
+    #[test]
+    fn long_gap_keeps_the_last_buckets() {
+        let mut store = ProbeStore::new();
+        store.push(time(0), ProbeResult::Timeout, &ReplyDetail::default());
+        store.push(time(10_000_000), ProbeResult::Timeout, &ReplyDetail::default());
+
+        let (_, capacity) = RESOLUTIONS[0];
+        let buckets = store.buckets(0);
+        assert_eq!(buckets.len(), capacity + 1);
+        assert_eq!(buckets[0].start.as_secs(), 10_000 - capacity as u64);
+        assert_eq!(buckets[capacity].stats.transmitted, 1);
+    }
+
+    //Results of a run => replies between 1 and 60 ms with spikes, timeouts and ICMP errors, from a fixed pseudo-random sequence
+    fn results(count: usize) -> Vec<ProbeResult> {
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let error = IcmpError { from: "192.0.2.1".parse().unwrap(), icmp_type: 3, code: 1, mtu: None };
+        (0..count)
+            .map(|_| {
+                state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+                let random = state >> 33;
+                match random % 100 {
+                    0..=4 => ProbeResult::Timeout,
+                    5 => ProbeResult::Error(ProbeError::Icmp(error.clone())),
+                    6 => ProbeResult::Reply(Duration::from_micros(200_000 + random % 100_000)),
+                    _ => ProbeResult::Reply(Duration::from_micros(1_000 + random % 59_000)),
+                }
+            })
+            .collect()
+    }
+
+    fn store_of(results: &[ProbeResult]) -> ProbeStore {
+        let mut store = ProbeStore::new();
+        for (index, result) in results.iter().enumerate() {
+            store.push(time(index as u64 * 500), result.clone(), &ReplyDetail::default());
+        }
+        store
+    }
+
+    //Difference in µs of two latencies
+    fn gap(a: Option<Duration>, b: Option<Duration>) -> u128 {
+        a.unwrap().as_micros().abs_diff(b.unwrap().as_micros())
+    }
+
+    #[test]
+    fn totals_match_the_statistics_of_the_results() {
+        let results = results(EXACT_SAMPLE);
+        let exact = Statistics::from_results(&results);
+        let totals = store_of(&results).statistics();
+        assert!(exact.received > 0 && exact.icmp_errors > 0 && exact.lost() > exact.icmp_errors);
+
+        //The variance of the totals comes from the sum of the squares => rounding of 1 µs at most
+        assert!(gap(totals.stddev, exact.stddev) <= 1);
+        assert_eq!(Statistics { stddev: exact.stddev, ..totals }, exact);
+    }
+
+    #[test]
+    fn totals_without_reply() {
+        let results = vec![ProbeResult::Timeout; 2_000];
+        let totals = store_of(&results).statistics();
+        assert_eq!(totals, Statistics::from_results(&results));
+        assert_eq!((totals.transmitted, totals.received, totals.loss_percent), (2_000, 0, 100.0));
+        assert_eq!(totals.avg, None);
+
+        assert_eq!(ProbeStore::new().statistics(), Statistics::from_results(&[]));
+    }
+
+    #[test]
+    fn totals_beyond_the_exact_sample() {
+        let results = results(5 * EXACT_SAMPLE);
+        let exact = Statistics::from_results(&results);
+        let totals = store_of(&results).statistics();
+        assert!(exact.received as usize > EXACT_SAMPLE);
+
+        //Counts, extremes, average and jitter are still exact
+        assert_eq!((totals.transmitted, totals.received, totals.icmp_errors), (exact.transmitted, exact.received, exact.icmp_errors));
+        assert_eq!(totals.loss_percent, exact.loss_percent);
+        assert_eq!((totals.min, totals.avg, totals.max, totals.jitter), (exact.min, exact.avg, exact.max, exact.jitter));
+        assert!(gap(totals.stddev, exact.stddev) <= 1);
+
+        //Percentiles and mean deviation come from the histogram => within the width of a bin
+        let width = |x: Option<Duration>| (x.unwrap().as_micros() as f64 * (HISTOGRAM_GROWTH - 1.0)).ceil() as u128 + 1;
+        for (approx, exact) in [(totals.p50, exact.p50), (totals.p90, exact.p90), (totals.p95, exact.p95), (totals.p99, exact.p99)] {
+            assert!(gap(approx, exact) <= width(exact), "{:?} {:?}", approx, exact);
+        }
+        assert!(gap(totals.mdev, exact.mdev) <= width(exact.avg), "{:?} {:?}", totals.mdev, exact.mdev);
+    }
+}
@@ -0,0 +1,76 @@
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::error::{Error, Result};
+
+//Maximum number of targets given by the user => limits the size of a CIDR range
+pub const MAX_TARGETS: usize = 65_536;
+
+//Parse a list of targets given by the user => IPs, hostnames or CIDR ranges separated by commas or spaces
+pub fn parse_targets(spec: &str) -> Result<Vec<String>> {
+    let mut targets = Vec::new();
+
+    for item in spec.split(|c: char| c == ',' || c.is_whitespace()).filter(|x| !x.is_empty()) {
+        if item.contains('/') {
+            targets.extend(expand_cidr(item)?);
+        } else {
+            targets.push(item.to_string());
+        }
+
+        if targets.len() > MAX_TARGETS {
+            return Err(Error::InvalidAddress(format!("more than {} targets", MAX_TARGETS)));
+        }
+    }
+    Ok(targets)
+}
+
+//Read the targets of a file => one list of targets per line, empty lines and lines starting with # are ignored
+pub fn read_targets(path: &str) -> Result<Vec<String>> {
+    let content = fs::read_to_string(path).map_err(|source| Error::TargetFile { path: path.to_string(), source })?;
+
+    let mut targets = Vec::new();
+    for line in content.lines().map(str::trim).filter(|x| !x.is_empty() && !x.starts_with('#')) {
+        targets.extend(parse_targets(line)?);
+        if targets.len() > MAX_TARGETS {
+            return Err(Error::InvalidAddress(format!("more than {} targets", MAX_TARGETS)));
+        }
+    }
+    Ok(targets)
+}
+
+//Expand a CIDR range in the IPs of its hosts => the network and broadcast addresses of an IPv4 range are skipped
+fn expand_cidr(cidr: &str) -> Result<Vec<String>> {
+    let invalid = || Error::InvalidAddress(cidr.to_string());
+
+    let (addr, prefix) = cidr.split_once('/').ok_or_else(invalid)?;
+    let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+    let prefix: u32 = prefix.parse().map_err(|_| invalid())?;
+
+    let bits = match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    };
+    if prefix > bits {
+        return Err(invalid());
+    }
+    //Number of addresses of the range, bounded to not allocate huge ranges
+    let host_bits = bits - prefix;
+    if host_bits > MAX_TARGETS.ilog2() {
+        return Err(Error::InvalidAddress(format!("{} has more than {} addresses", cidr, MAX_TARGETS)));
+    }
+    let size = 1u128 << host_bits;
+
+    let targets = match addr {
+        IpAddr::V4(ip) => {
+            let network = u32::from(ip) & !((size - 1) as u32);
+            //A /31 or a /32 has no network and broadcast address
+            let hosts = if size > 2 { 1..size - 1 } else { 0..size };
+            hosts.map(|i| Ipv4Addr::from(network + i as u32).to_string()).collect()
+        }
+        IpAddr::V6(ip) => {
+            let network = u128::from(ip) & !(size - 1);
+            (0..size).map(|i| Ipv6Addr::from(network + i).to_string()).collect()
+        }
+    };
+    Ok(targets)
+}
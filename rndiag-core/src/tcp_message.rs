@@ -1,10 +1,12 @@
 use std::io::{self, BufRead, BufReader, Write};
-use std::net::{TcpListener, TcpStream};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc;
 use std::thread;
-use std::net::IpAddr;
-use std::net::Ipv4Addr;
+use socket2::{Domain, Protocol, Socket, Type};
 use tokio;
 
+use crate::error::Result;
+use crate::socket::SocketOptions;
 use crate::tool::ConnectTool;
 
 //TCPMessage object definition
@@ -12,68 +14,98 @@ pub struct TCPMessage{
     srv_addr: String, //addr to listen in server mode, or addr to contact in client mode
     srv_port: u16, //port to listen in server mode or port to contact in port mode
     is_srv: bool, //true => run as server, false => run as client
+    socket_options: SocketOptions, //TOS, TTL, interface of the connexion + source of the client
 }
 //Init object method
 impl TCPMessage {
-    pub fn new(srv_addr: String, srv_port: u16, is_srv: bool) -> Self {
+    pub fn new(srv_addr: String, srv_port: u16, is_srv: bool, socket_options: SocketOptions) -> Self {
         Self {
             srv_addr,
             srv_port,
             is_srv,
+            socket_options,
         }
     }
 
+    //TCP socket of the IP version of the address with the options of the user
+    fn socket(ip: IpAddr, options: &SocketOptions) -> io::Result<Socket> {
+        let socket = Socket::new(Domain::for_address(SocketAddr::new(ip, 0)), Type::STREAM, Some(Protocol::TCP))?;
+        options.apply(&socket, ip)?;
+        Ok(socket)
+    }
+
     //Handle bi-dir connexion (read + write)
+    //Returns when the user closes the input (Ctrl+D) or when the remote peer closes the connection
     fn handle_connection(stream: TcpStream) -> io::Result<()> {
         //Clone the stream for the read thread
         let read_stream = stream.try_clone()?;
         let mut write_stream = stream;
 
+        //The remote messages and the user input are sent to the main thread
+        let (events_tx, events_rx) = mpsc::channel();
+
         //Thread to receive messages
-        let receive_thread = thread::spawn(move || {
+        let remote_events = events_tx.clone();
+        thread::spawn(move || {
             let mut reader = BufReader::new(read_stream);
             let mut line = String::new();
 
-            
             loop {
                 line.clear();
-                match reader.read_line(&mut line) {
-                    Ok(0) => {
-                        //Connexion closed
-                        println!("\n[Connection closed by remote peer]");
-                        std::process::exit(0);
-                    }
-                    Ok(_) => {
-                        //Show the received message
-                        print!("\rRemote: {}", line);
-                        print!("You: ");
-                        io::stdout().flush().unwrap();
-                    }
-                    Err(e) => {
-                        eprintln!("\n[Error reading from connection: {}]", e);
-                        std::process::exit(1);
-                    }
+                let event = match reader.read_line(&mut line) {
+                    //Connexion closed
+                    Ok(0) => ChatEvent::RemoteClosed,
+                    Ok(_) => ChatEvent::Remote(line.clone()),
+                    Err(e) => ChatEvent::RemoteError(e),
+                };
+                let last = !matches!(event, ChatEvent::Remote(_));
+                if remote_events.send(event).is_err() || last {
+                    return;
                 }
             }
         });
 
-        //Main thread to send messages
-        let stdin = io::stdin();
-        let mut input = String::new();
-
-        loop {
-            print!("You: ");
-            io::stdout().flush()?;
+        //Thread to read the user input => it stays blocked on stdin until the next line, so it is not joined
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            let mut input = String::new();
 
-            input.clear();
-
-            match stdin.read_line(&mut input) {
-                Ok(0) => {
+            loop {
+                input.clear();
+                let event = match stdin.read_line(&mut input) {
                     // EOF (Ctrl+D)
-                    println!("[Closing connection...]");
+                    Ok(0) => ChatEvent::InputClosed,
+                    Ok(_) => ChatEvent::Input(input.clone()),
+                    Err(e) => ChatEvent::InputError(e),
+                };
+                let last = !matches!(event, ChatEvent::Input(_));
+                if events_tx.send(event).is_err() || last {
+                    return;
+                }
+            }
+        });
+
+        //Main thread to send messages
+        print!("You: ");
+        io::stdout().flush()?;
+
+        for event in events_rx {
+            match event {
+                ChatEvent::Remote(line) => {
+                    //Show the received message
+                    print!("\rRemote: {}", line);
+                    print!("You: ");
+                    io::stdout().flush()?;
+                }
+                ChatEvent::RemoteClosed => {
+                    println!("\n[Connection closed by remote peer]");
                     break;
                 }
-                Ok(_) => {
+                ChatEvent::RemoteError(e) => {
+                    eprintln!("\n[Error reading from connection: {}]", e);
+                    return Err(e);
+                }
+                ChatEvent::Input(input) => {
                     //Send the message
                     if let Err(e) = write_stream.write_all(input.as_bytes()) {
                         eprintln!("[Error sending message: {}]", e);
@@ -83,21 +115,37 @@ impl TCPMessage {
                         eprintln!("[Error flushing stream: {}]", e);
                         break;
                     }
+                    print!("You: ");
+                    io::stdout().flush()?;
+                }
+                ChatEvent::InputClosed => {
+                    println!("[Closing connection...]");
+                    break;
                 }
-                Err(e) => {
+                ChatEvent::InputError(e) => {
                     eprintln!("[Error reading input: {}]", e);
                     break;
                 }
             }
         }
 
-        //Wait the end of execution of the reception thread
-        let _ = receive_thread.join();
+        //Close the connection => the reception thread ends
+        let _ = write_stream.shutdown(Shutdown::Both);
 
         Ok(())
     }
 }
 
+//Events handled by the main thread of a connexion
+enum ChatEvent {
+    Remote(String), //Message received from the remote peer
+    RemoteClosed,
+    RemoteError(io::Error),
+    Input(String), //Message typed by the user
+    InputClosed,
+    InputError(io::Error),
+}
+
 //Methods definition that inerithed of the trait
 impl ConnectTool for TCPMessage {
     //Return the tool name
@@ -111,7 +159,7 @@ impl ConnectTool for TCPMessage {
     }
 
     //Main method
-    async fn run(&mut self) -> std::io::Result<()> {
+    async fn run(&mut self) -> Result<()> {
         // Mode serveur
         if self.is_srv {
             self.start_server().await?;
@@ -122,19 +170,10 @@ impl ConnectTool for TCPMessage {
         Ok(())
     }
 
-    #[allow(unused_assignments)]
     //Start server method => handle the server
-    async fn start_server(&mut self) -> std::io::Result<()> {
-        let mut target_ip: IpAddr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
-
+    async fn start_server(&mut self) -> Result<()> {
         //Resolve if the user given a hostname
-        if self.srv_addr.parse::<IpAddr>().is_ok(){
-            target_ip = self.srv_addr.parse().expect("Invalid IP address");
-
-        }
-        else {
-            target_ip = self.resolve();
-        }
+        let target_ip = self.resolve()?;
 
         let addr = format!("{}:{}", target_ip, self.srv_port);
         println!("[{}] Server listening on {}...", self.name(), addr);
@@ -142,48 +181,48 @@ impl ConnectTool for TCPMessage {
 
         //Use tokio::task::spawn_blocking for the blocking code
         let srv_port = self.srv_port;
+        let options = self.socket_options.clone();
         tokio::task::spawn_blocking(move || {
-            let listener = TcpListener::bind(format!("{}:{}", target_ip, srv_port))?;
+            //The accepted connexion inherits the TOS, TTL and interface of the listening socket
+            let socket = TCPMessage::socket(target_ip, &options)?;
+            socket.set_reuse_address(true)?;
+            socket.bind(&SocketAddr::new(target_ip, srv_port).into())?;
+            socket.listen(128)?;
+            let listener = TcpListener::from(socket);
             let (stream, addr) = listener.accept()?;
             println!("Client connected from: {}", addr);
             println!("Type messages and press Enter to send. Ctrl+C to quit.\n");
 
             TCPMessage::handle_connection(stream)
         })
-        .await
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+        .await??;
 
         Ok(())
     }
 
-    #[allow(unused_assignments)]
     //client method => Handle client part
-    async fn client(&mut self) -> std::io::Result<()> {
-        let mut target_ip: IpAddr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
-
+    async fn client(&mut self) -> Result<()> {
         //Resolve if the user given a hostname
-        if self.srv_addr.parse::<IpAddr>().is_ok(){
-            target_ip = self.srv_addr.parse().expect("Invalid IP address");
-
-        }
-        else {
-            target_ip = self.resolve();
-        }
+        let target_ip = self.resolve()?;
+        self.socket_options.check(target_ip)?;
 
         let addr = format!("{}:{}", target_ip, self.srv_port);
         println!("[{}] Connecting to {}...", self.name(), addr);
 
         //Use tokio::task::spawn_blocking for the blocking code
-        let addr_clone = format!("{}:{}", target_ip, self.srv_port);
+        let srv_addr = SocketAddr::new(target_ip, self.srv_port);
+        let options = self.socket_options.clone();
         tokio::task::spawn_blocking(move || {
-            let stream = TcpStream::connect(&addr_clone)?;
+            let socket = TCPMessage::socket(target_ip, &options)?;
+            options.bind(&socket)?;
+            socket.connect(&srv_addr.into())?;
+            let stream = TcpStream::from(socket);
             println!("Connected to server!");
             println!("Type messages and press Enter to send. Ctrl+C to quit.\n");
 
             TCPMessage::handle_connection(stream)
         })
-        .await
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+        .await??;
 
         Ok(())
     }
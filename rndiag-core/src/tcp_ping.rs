@@ -1,385 +1,474 @@
-use std::net::{Ipv4Addr, Ipv6Addr, IpAddr};
-use std::time::{Duration, Instant};
-use tokio::time::sleep;
-use crossterm::event::KeyModifiers;
-use rndiag_graph::graph::graph_display;
-use crossterm::event::{self, Event, KeyCode};
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, IpAddr, SocketAddr};
+use std::ops::Range;
+use std::future::pending;
+use std::os::fd::BorrowedFd;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::unix::AsyncFd;
+use tokio::net::TcpSocket;
+use tokio::time::timeout_at;
 
 use pnet::packet::ip::IpNextHeaderProtocols;
-use pnet::packet::ipv4::{MutableIpv4Packet, checksum as ipv4_checksum};
-use pnet::packet::tcp::MutableTcpPacket;
+use pnet::packet::ipv4::{Ipv4Packet, MutableIpv4Packet, checksum as ipv4_checksum};
+use pnet::packet::tcp::{MutableTcpPacket, TcpFlags, TcpOptionNumbers, TcpPacket};
 use pnet::packet::Packet;
-use pnet::transport::{
-    transport_channel, TransportChannelType, ipv4_packet_iter,
-};
-use socket2::{Socket, Domain, Type, Protocol};
+use pnet::transport::{transport_channel, TransportChannelType, TransportSender};
+use socket2::{Socket, SockAddr, SockRef, Domain, Type, Protocol};
 
-use crate::tool::LatencyTool;
+use crate::error::{Error, Result};
+use crate::icmp::{IPPROTO_TCP, IcmpError, IcmpSocket, SocketKind, recv_message};
+use crate::socket::SocketOptions;
+use crate::tool::{Probe, ProbeError, ProbeOutcome, ProbeResult, ReplyDetail, as_ms, resolve_target};
 
 // ─────────────────────────────────────────────────────────────────────────────
 // TCPPingTool struct definition
 // ─────────────────────────────────────────────────────────────────────────────
 
+// One TCP segment with the given flags per probe, run by ProbeRunner
+// Each probe has its own source port and sequence numbers => concurrent runs and stray segments are not mistaken for replies
 pub struct TCPPingTool {
     pub target: String,
     port: u16,
-    data: Vec<u32>,
-    begin_time: u64,
-    elapsed_time: u64,
-    sys_time: Vec<u64>,
-    latency_time: Vec<u64>,
-    latency_min: Vec<u32>,
-    latency_moy: Vec<u32>,
-    latency_max: Vec<u32>,
-    latency_min_sampled: Vec<u64>,
-    latency_moy_sampled: Vec<u64>,
-    latency_max_sampled: Vec<u64>,
-    output: String,
-    nb_ping: u32,
-    flag: u8,
+    mode: TcpPingMode,
+    target_ip: IpAddr,
+    src_ip: IpAddr,
+    // TOS, TTL, source and interface of the segments
+    socket_options: SocketOptions,
+    // IPv4 only: Layer3 raw socket opened once by prepare(), its receiving half is read through the tokio reactor
+    ipv4_channel: Option<(TransportSender, AsyncFd<Socket>)>,
+    // IPv6 only: raw TCP socket opened once by prepare(), the kernel adds the IPv6 header
+    ipv6_socket: Option<AsyncFd<Socket>>,
+    // Raw ICMP/ICMPv6 socket opened by prepare() => errors of routers about our segments
+    icmp: Option<IcmpSocket>,
+}
+
+// How a probe measures the latency
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpPingMode {
+    // One segment with these flags and TCP options on a raw socket => root or CAP_NET_RAW needed
+    Raw(u8, TcpOptions),
+    // Three-way handshake of connect() on a normal socket => no privilege needed, the kernel sends the segments
+    Connect,
+}
+
+// TCP options of the probes, none by default => a bare 20 bytes header
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TcpOptions {
+    pub mss: Option<u16>, // Maximum segment size
+    pub wscale: Option<u8>, // Window scale shift count, 14 at most
+    pub sack_permitted: bool, // SACK permitted
+    pub timestamp: bool, // Timestamps => TSval is our clock in ms, TSecr is 0
+}
+
+// Answer to a probe: a TCP segment of the target (latency + flags + its decoded options + TTL or hop limit of the packet)
+// or an ICMP error about our segment (latency + error)
+pub(crate) enum TcpReply {
+    Segment(Duration, u8, String, Option<u8>),
+    Icmp(Duration, IcmpError),
+}
+
+// Ports, sequence numbers and flags of a probe => the reply must answer all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TcpSegment {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: u8,
+    pub options: TcpOptions,
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
-// LatencyTool trait implementation
+// Probe trait implementation
 // ─────────────────────────────────────────────────────────────────────────────
 
-impl LatencyTool for TCPPingTool {
+impl Probe for TCPPingTool {
     fn name(&self) -> &'static str {
         "tping"
     }
 
-    fn data(&self) -> &Vec<u32> {
-        &self.data
+    fn target(&self) -> &str {
+        &self.target
     }
 
-    fn nb_ping(&self) -> &u32 {
-        &self.nb_ping
-    }
+    async fn prepare(&mut self) -> Result<Option<String>> {
+        // Resolve hostname or parse IP
+        self.target_ip = resolve_target(&self.target)?;
+        self.socket_options.check(self.target_ip)?;
 
-    fn sys_time(&self) -> &Vec<u64> {
-        &self.sys_time
-    }
+        // The source address is written in the IPv4 header and in the checksum of the segments => known before the first probe
+        self.src_ip = self.socket_options.source_ip(self.target_ip).map_err(|e| Error::from_io(self.name(), e))?;
+
+        let marking = SocketOptions { source: None, ..self.socket_options.clone() };
+        let marking = if marking.is_default() { String::new() } else { format!(" {}", marking) };
+
+        // connect() needs no raw socket, the source address is only displayed
+        let (flag, options) = match self.mode {
+            TcpPingMode::Raw(flag, options) => (flag, options),
+            TcpPingMode::Connect => {
+                return Ok(Some(format!("TCP-CONNECT {}:{} from {}{}", self.target_ip, self.port, self.src_ip, marking)));
+            }
+        };
+
+        // IPv4: Layer3 raw socket for full IP+TCP control
+        // IPv6: socket2 raw socket — handles arbitrary TCP flags correctly
+        if self.target_ip.is_ipv4() {
+            let channel = open_ipv4_channel().map_err(|e| Error::from_io(self.name(), e))?;
+
+            // The IPv4 header is ours => only the interface is a socket option, TOS and TTL are set in each packet
+            // SAFETY: the descriptor is owned by the channel, alive while it is borrowed
+            let fd = unsafe { BorrowedFd::borrow_raw(channel.0.socket.fd) };
+            self.socket_options.apply(&SockRef::from(&fd), self.target_ip).map_err(|e| Error::from_io(self.name(), e))?;
+            self.ipv4_channel = Some(channel);
+        } else {
+            // One socket for the run as for IPv4, opened here to report a missing privilege before the first probe
+            // The segments queued since the last probe are read and skipped as they do not answer its sequence numbers
+            self.ipv6_socket = Some(self.open_ipv6_socket().map_err(|e| Error::from_io(self.name(), e))?);
+        }
 
-    fn elapsed_time(&mut self) -> &mut u64 {
-        &mut self.elapsed_time
+        // ICMP errors are only a detail => the probes still work if the socket cannot be opened
+        self.icmp = IcmpSocket::open_kind(self.target_ip, SocketKind::Raw).ok();
+
+        let options = if options == TcpOptions::default() { String::new() } else { format!(" options {}", options) };
+        Ok(Some(format!(
+            "TCP-PING {}:{} from {} flags=0x{:02x} ({}){}{}",
+            self.target_ip, self.port, self.src_ip, flag, decode_tcp_flags(flag), options, marking
+        )))
     }
 
-    fn begin_time(&self) -> &u64 {
-        &self.begin_time
+    async fn probe(&mut self, _seq: u32, timeout: Duration) -> ProbeOutcome {
+        let (flag, options) = match self.mode {
+            TcpPingMode::Raw(flag, options) => (flag, options),
+            TcpPingMode::Connect => return self.probe_connect(timeout).await,
+        };
+
+        // Reply flags and options are the detail of a reply for the front-end
+        match self.exchange(flag, options, timeout).await {
+            Ok(Some(TcpReply::Segment(latency, reply_flags, reply_options, ttl))) => {
+                let mut info = format!("flags=0x{:02x} ({})", reply_flags, decode_tcp_flags(reply_flags));
+                if !reply_options.is_empty() {
+                    info = format!("{} options {}", info, reply_options);
+                }
+                let mut outcome = ProbeOutcome::new(ProbeResult::Reply(latency), Some(info));
+                outcome.reply = ReplyDetail { ttl, from: Some(self.target_ip), ..Default::default() };
+                outcome
+            }
+            Ok(Some(TcpReply::Icmp(_, error))) => ProbeResult::Error(ProbeError::Icmp(error)).into(),
+            Ok(None) => ProbeResult::Timeout.into(),
+            Err(e) => ProbeResult::Error(ProbeError::from(&e)).into(),
+        }
     }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// TCPPingTool-specific methods
+// ─────────────────────────────────────────────────────────────────────────────
 
-    fn latency_time(&mut self) -> &mut Vec<u64> {
-        &mut self.latency_time
+impl TCPPingTool {
+    pub fn new(target: &str, port: u16, mode: TcpPingMode, socket_options: SocketOptions) -> Self {
+        Self {
+            target: target.to_string(),
+            port,
+            mode,
+            target_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            src_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            socket_options,
+            ipv4_channel: None,
+            ipv6_socket: None,
+            icmp: None,
+        }
     }
 
-    fn latency_min(&mut self) -> &mut Vec<u32> {
-        &mut self.latency_min
+    // Send one segment with these flags and options from a new source port and wait the answer of the target or of a router
+    // Ok(None) => no reply before the timeout, Err => the segment cannot be sent
+    pub(crate) async fn exchange(&mut self, flag: u8, options: TcpOptions, timeout: Duration) -> io::Result<Option<TcpReply>> {
+        // The source port is reserved until the reply is handled => no other run or application sends from it
+        let (_reservation, src_port) = reserve_port(self.src_ip)?;
+        let segment = TcpSegment { options, ..TcpSegment::new(src_port, self.port, flag) };
+
+        let start = Instant::now();
+        self.send(&segment)?;
+        let reply = self.wait_reply(&segment, start, timeout).await;
+
+        // A SYN answered by SYN|ACK left a half-open connexion on the target => closed by our RST
+        // The RST of the kernel is not suppressed: no socket is connected to the port, so the kernel also answers the SYN|ACK with a RST
+        // Suppressing it needs a firewall rule of the host, the target gets two RST closing the same connexion
+        // Ours still closes it if a firewall of the host drops the kernel one
+        if let Ok(Some(TcpReply::Segment(_, reply_flags, _, _))) = reply
+            && let Some(reset) = segment.reset_for(reply_flags)
+        {
+            let _ = self.send(&reset);
+        }
+        reply
     }
 
-    fn latency_moy(&mut self) -> &mut Vec<u32> {
-        &mut self.latency_moy
+    // Connect mode: latency of the three-way handshake, from the SYN to the SYN|ACK received by the kernel
+    // A RST instead of the SYN|ACK => refused, a RST right after the handshake (middlebox, full backlog) => reset
+    async fn probe_connect(&self, timeout: Duration) -> ProbeOutcome {
+        let socket = match self.open_connect_socket() {
+            Ok(socket) => socket,
+            Err(e) => return ProbeResult::Error(ProbeError::from(&e)).into(),
+        };
+
+        let start = Instant::now();
+        let connect = tokio::time::timeout(timeout, socket.connect(SocketAddr::new(self.target_ip, self.port))).await;
+        let latency = start.elapsed();
+
+        match connect {
+            Ok(Ok(stream)) => {
+                let reset = matches!(stream.take_error(), Ok(Some(e)) if e.kind() == io::ErrorKind::ConnectionReset);
+                // Closed by a RST => no FIN exchange with the target and no TIME_WAIT left on the host
+                let _ = SockRef::from(&stream).set_linger(Some(Duration::ZERO));
+                if reset {
+                    ProbeOutcome::new(ProbeResult::Error(ProbeError::Reset), Some(format!("reset after {:.3} ms", as_ms(latency))))
+                } else {
+                    ProbeOutcome::new(ProbeResult::Reply(latency), Some(String::from("connected")))
+                }
+            }
+            Ok(Err(e)) => match ProbeError::from(&e) {
+                // Answered by the target => the time of its RST is a detail
+                error @ (ProbeError::Refused | ProbeError::Reset) => {
+                    ProbeOutcome::new(ProbeResult::Error(error.clone()), Some(format!("{} after {:.3} ms", error, as_ms(latency))))
+                }
+                error => ProbeResult::Error(error).into(),
+            },
+            Err(_) => ProbeResult::Timeout.into(),
+        }
     }
 
-    fn latency_max(&mut self) -> &mut Vec<u32> {
-        &mut self.latency_max
+    // Normal TCP socket of the connect mode with the TOS, TTL, interface and source of the options
+    fn open_connect_socket(&self) -> io::Result<TcpSocket> {
+        let socket = match self.target_ip {
+            IpAddr::V4(_) => TcpSocket::new_v4()?,
+            IpAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+        let sock_ref = SockRef::from(&socket);
+        self.socket_options.apply(&sock_ref, self.target_ip)?;
+        self.socket_options.bind(&sock_ref)?;
+        Ok(socket)
+    }
+
+    // Raw IPv6 TCP socket: the kernel builds the IPv6 header => traffic class, hop limit, interface and source are socket options
+    // The hop limit of the replies is given as ancillary data
+    fn open_ipv6_socket(&self) -> io::Result<AsyncFd<Socket>> {
+        let socket = open_ipv6_raw()?;
+        self.socket_options.apply(socket.get_ref(), self.target_ip)?;
+        self.socket_options.bind(socket.get_ref())?;
+        Ok(socket)
+    }
+
+    // Send a segment to the target with the socket of its IP version
+    fn send(&mut self, segment: &TcpSegment) -> io::Result<()> {
+        match (self.target_ip, self.src_ip, self.ipv4_channel.as_mut(), self.ipv6_socket.as_ref()) {
+            (IpAddr::V4(dst), IpAddr::V4(src), Some((sender, _)), _) => {
+                let fields = Ipv4Fields {
+                    ttl: self.socket_options.ttl.unwrap_or(DEFAULT_TTL),
+                    tos: self.socket_options.tos.unwrap_or(0),
+                };
+                send_segment_v4(sender, src, dst, segment, fields)
+            }
+            (IpAddr::V6(dst), IpAddr::V6(src), _, Some(socket)) => send_segment_v6(socket.get_ref(), src, dst, segment),
+            _ => Err(io::Error::new(io::ErrorKind::NotConnected, "socket not opened")),
+        }
     }
 
-    fn latency_min_sampled(&mut self) -> &mut Vec<u64> {
-        &mut self.latency_min_sampled
+    // Wait the answer of the target or of a router to a segment
+    async fn wait_reply(&self, segment: &TcpSegment, start: Instant, timeout: Duration) -> io::Result<Option<TcpReply>> {
+        let receiver = match (self.target_ip, self.ipv4_channel.as_ref(), self.ipv6_socket.as_ref()) {
+            (IpAddr::V4(_), Some((_, receiver)), _) => receiver,
+            (IpAddr::V6(_), _, Some(socket)) => socket,
+            _ => return Err(io::Error::new(io::ErrorKind::NotConnected, "socket not opened")),
+        };
+        wait_tcp_reply(receiver, self.icmp.as_ref(), self.target_ip, segment, start, timeout).await
     }
+}
 
-    fn latency_moy_sampled(&mut self) -> &mut Vec<u64> {
-        &mut self.latency_moy_sampled
+// ─────────────────────────────────────────────────────────────────────────────
+// Segment numbering and matching
+// ─────────────────────────────────────────────────────────────────────────────
+
+impl TcpSegment {
+    // Segment with random sequence and acknowledgement numbers and without TCP option
+    pub(crate) fn new(src_port: u16, dst_port: u16, flags: u8) -> Self {
+        Self { src_port, dst_port, seq: random_u32(), ack: random_u32(), flags, options: TcpOptions::default() }
     }
 
-    fn latency_max_sampled(&mut self) -> &mut Vec<u64> {
-        &mut self.latency_max_sampled
+    // Length of the TCP header with the options
+    fn header_len(&self) -> usize {
+        20 + self.options.len()
     }
 
-    fn output(&self) -> &str {
-        &self.output
+    // Sequence space used by the segment => SYN and FIN count for one, there is no data
+    fn len(&self) -> u32 {
+        (self.flags & TcpFlags::SYN != 0) as u32 + (self.flags & TcpFlags::FIN != 0) as u32
     }
 
-    fn target(&self) -> &str {
-        &self.target
+    // True if a segment of the target answers this one, its ports are already checked (RFC 9293 3.10.7.1):
+    // with ACK it acknowledges our sequence space, a RST without ACK takes the acknowledgement number of our ACK as sequence
+    pub(crate) fn is_answered_by(&self, reply: &TcpPacket) -> bool {
+        if reply.get_flags() & TcpFlags::ACK != 0 {
+            return reply.get_acknowledgement() == self.seq.wrapping_add(self.len());
+        }
+        self.flags & TcpFlags::ACK != 0 && reply.get_flags() & TcpFlags::RST != 0 && reply.get_sequence() == self.ack
     }
 
-    async fn run(&mut self) -> std::io::Result<()> {
-        self.tcp_ping(self.flag, 500).await?;
-        Ok(())
+    // RST closing the half-open connexion of a SYN answered by SYN|ACK, None for any other exchange
+    // Cleanup only => the RST of the kernel for the same SYN|ACK is still sent
+    pub(crate) fn reset_for(&self, reply_flags: u8) -> Option<TcpSegment> {
+        let syn_ack = TcpFlags::SYN | TcpFlags::ACK;
+        let half_open = self.flags & (TcpFlags::SYN | TcpFlags::ACK | TcpFlags::RST) == TcpFlags::SYN && reply_flags & syn_ack == syn_ack;
+        half_open.then(|| TcpSegment { seq: self.seq.wrapping_add(1), ack: 0, flags: TcpFlags::RST, options: TcpOptions::default(), ..*self })
     }
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
-// TCPPingTool-specific methods
+// TCP options
 // ─────────────────────────────────────────────────────────────────────────────
 
-impl TCPPingTool {
-    #[allow(unused_assignments)]
-    async fn tcp_ping(&mut self, flags: u8, interval_ms: u64) -> std::io::Result<()> {
-        let mut i: u32 = 0;
-        let mut j: u16 = 0;
-
-        // Resolve hostname or parse IP
-        let mut target_ip: IpAddr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
+impl TcpOptions {
+    // Length of the options padded to 32 bits => multiple of 4, 20 bytes at most
+    fn len(&self) -> usize {
+        let len = self.mss.map_or(0, |_| 4) + self.sack_permitted as usize * 2 + self.timestamp as usize * 10 + self.wscale.map_or(0, |_| 3);
+        len.div_ceil(4) * 4
+    }
 
-        if self.target.parse::<IpAddr>().is_ok() {
-            target_ip = self.target.parse().expect("Invalid IP address");
-        } else {
-            target_ip = self.resolve();
+    // Write the options in the order of Linux, the buffer is zeroed => the padding is End of Option List
+    fn write(&self, buffer: &mut [u8]) {
+        let mut offset = 0;
+        if let Some(mss) = self.mss {
+            buffer[offset..offset + 4].copy_from_slice(&[TcpOptionNumbers::MSS.0, 4, (mss >> 8) as u8, mss as u8]);
+            offset += 4;
         }
+        if self.sack_permitted {
+            buffer[offset..offset + 2].copy_from_slice(&[TcpOptionNumbers::SACK_PERMITTED.0, 2]);
+            offset += 2;
+        }
+        if self.timestamp {
+            buffer[offset..offset + 2].copy_from_slice(&[TcpOptionNumbers::TIMESTAMPS.0, 10]);
+            buffer[offset + 2..offset + 6].copy_from_slice(&timestamp_ms().to_be_bytes());
+            offset += 10;
+        }
+        if let Some(wscale) = self.wscale {
+            buffer[offset..offset + 3].copy_from_slice(&[TcpOptionNumbers::WSCALE.0, 3, wscale]);
+        }
+    }
+}
 
-        // Bind to appropriate address family to discover the local source IP
-        let bind_addr = match target_ip {
-            IpAddr::V4(_) => "0.0.0.0:0",
-            IpAddr::V6(_) => "[::]:0",
-        };
-
-        let socket = std::net::UdpSocket::bind(bind_addr)?;
-        socket.connect(format!("{}:80", target_ip))?;
-        let src_ip = socket.local_addr()?.ip();
-
-        let src_port: u16 = 54321;
-
-        // IPv4 only: open a Layer3 raw socket for full IP+TCP control
-        // IPv6: uses socket2 raw socket — handles arbitrary TCP flags correctly
-        let mut ipv4_channel = match target_ip {
-            IpAddr::V4(_) => {
-                let protocol = TransportChannelType::Layer3(IpNextHeaderProtocols::Tcp);
-                Some(
-                    transport_channel(4096, protocol)
-                        .expect("failed to open IPv4 raw socket (need root/admin privileges)"),
-                )
-            }
-            IpAddr::V6(_) => None,
-        };
-
-        let flags_str = decode_tcp_flags(flags);
-        println!(
-            "TCP-PING {}:{} from {}:{} flags=0x{:02x} ({}) count={}",
-            target_ip, self.port, src_ip, src_port, flags, flags_str, self.nb_ping
-        );
-
-        let mut k: usize = 0;
-        let mut scale: u16 = 5;
-        let opt_graph = true;
-
-        self.begin_time = self.get_time();
-
-        // Main loop: runs until nb_ping is reached, or forever if nb_ping == 0
-        while i < self.nb_ping || self.nb_ping == 0 {
-            use crossterm::terminal::{enable_raw_mode, disable_raw_mode};
-
-            enable_raw_mode()?;
-
-            if event::poll(Duration::from_millis(20))? {
-                if let Event::Key(key_event) = event::read().unwrap() {
-                    match (key_event.code, key_event.modifiers) {
-                        // 'g' => compute latest sampling then show the graph (blocking)
-                        (KeyCode::Char('g'), KeyModifiers::NONE) => {
-                            k = self.sampling(k, scale);
-                            graph_display(
-                                &self.latency_min_sampled,
-                                &self.latency_moy_sampled,
-                                &self.latency_max_sampled,
-                            )
-                            .unwrap_or_else(|e| {
-                                eprintln!("Error during graph building: {}", e);
-                            });
-                        }
-                        // Ctrl-C => print stats then exit cleanly
-                        (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
-                            disable_raw_mode()?;
-                            self.latency_data();
-                            return Ok(());
-                        }
-                        _ => {}
-                    }
-                }
-            }
+// Options of the probes in the description of a run => "mss=1460 sack ts wscale=7"
+impl fmt::Display for TcpOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(mss) = self.mss {
+            parts.push(format!("mss={}", mss));
+        }
+        if self.sack_permitted {
+            parts.push(String::from("sack"));
+        }
+        if self.timestamp {
+            parts.push(String::from("ts"));
+        }
+        if let Some(wscale) = self.wscale {
+            parts.push(format!("wscale={}", wscale));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
 
-            let start = Instant::now();
-
-            // Dispatch per IP version:
-            // IPv4 => pnet Layer3 raw socket, full IP+TCP control, real TCP flags in reply
-            // IPv6 => socket2 raw socket, kernel adds IPv6 header, arbitrary TCP flags supported
-            let reply: Option<(Duration, u8)> = match target_ip {
-                IpAddr::V4(dst) => {
-                    let src = match src_ip {
-                        IpAddr::V4(a) => a,
-                        _ => unreachable!(),
-                    };
-                    let (sender, receiver) = ipv4_channel.as_mut().unwrap();
-                    let mut buffer = [0u8; 40]; // IPv4 (20) + TCP (20)
-                    build_ipv4_packet(&mut buffer, src, dst, src_port, self.port, flags);
-
-                    sender
-                        .send_to(
-                            pnet::packet::ipv4::Ipv4Packet::new(&buffer).unwrap(),
-                            std::net::IpAddr::V4(dst),
-                        )
-                        .expect("send_to failed");
-
-                    wait_reply_ipv4(receiver, dst, self.port, start).await
-                }
-                IpAddr::V6(dst) => {
-                    let src = match src_ip {
-                        IpAddr::V6(a) => a,
-                        _ => unreachable!(),
-                    };
-
-                    // Raw IPv6 socket: kernel adds the IPv6 header, we only provide TCP segment
-                    disable_raw_mode()?;
-                    let send_sock = Socket::new(
-                        Domain::IPV6,
-                        Type::RAW,
-                        Some(Protocol::from(6)), // IPPROTO_TCP = 6
-                    )
-                    .expect("failed to create IPv6 raw send socket (need root)");
-                    enable_raw_mode()?;
-
-                    let mut tcp_buffer = [0u8; 20];
-                    build_tcp_packet_v6(&mut tcp_buffer, src, dst, src_port, self.port, flags);
-
-                    let dst_addr = std::net::SocketAddrV6::new(dst, 0, 0, 0); // port = 0 on raw sockets
-                    send_sock
-                        .send_to(
-                            &tcp_buffer,
-                            &dst_addr.into(),
-                        )
-                        .expect("IPv6 raw send failed");
-
-                    wait_reply_ipv6_raw(dst, self.port, start).await
-                }
-            };
-
-            match reply {
-                Some((latency, reply_flags)) => {
-                    let flags_str = decode_tcp_flags(reply_flags);
-
-                    use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
-                    disable_raw_mode()?;
-                    println!(
-                        "[{}] Reply in {:.3} ms - flags=0x{:02x} ({})",
-                        i + 1,
-                        latency.as_secs_f64() * 1000.0,
-                        reply_flags,
-                        flags_str
-                    );
-                    enable_raw_mode()?;
-
-                    // Treat anything >= 5 s as a timeout
-                    if latency.as_millis() as u16 >= 5000 {
-                        self.data.push(5000);
-                    } else {
-                        self.data.push(latency.as_millis() as u32);
-                    }
-                    self.sys_time.push(self.get_time());
-
-                    // Sampling window: every `scale` pings we emit one graph data point
-                    if j == scale && opt_graph {
-                        j = 0;
-                        let scale_before = scale;
-
-                        scale = match self.elapsed_time {
-                            t if t <= 300     =>    5,
-                            t if t <= 1800    =>   15,
-                            t if t <= 3600    =>   30,
-                            t if t <= 7200    =>   60,
-                            t if t <= 14400   =>  120,
-                            t if t <= 28800   =>  240,
-                            t if t <= 57600   =>  480,
-                            t if t <= 115200  =>  960,
-                            t if t <= 230400  => 1920,
-                            t if t <= 460800  => 3840,
-                            t if t <= 921600  => 7680,
-                            _                 => 15360,
-                        };
-
-                        // If the scale changed, resample all accumulated data at the new resolution
-                        if scale_before != scale {
-                            k = 0;
-                            self.latency_max_sampled.clear();
-                            self.latency_moy_sampled.clear();
-                            self.latency_min_sampled.clear();
-                            k = self.sampling(k, 0);
-                        }
-
-                        k = self.sampling(k, scale);
-                    } else if opt_graph {
-                        j += 1;
-                    }
-
-                    i += 1;
-                }
-                None => {
-                    panic!("Inconsistent data in packet response");
-                }
-            }
+// Decode the options of a reply => "mss=1460 sack ts=3501/1207 wscale=7", an unknown option is shown by its kind
+fn decode_tcp_options(tcp: &TcpPacket) -> String {
+    let mut result = Vec::new();
 
-            // Interval between pings (~2 pings/sec by default)
-            if self.nb_ping != 0 {
-                if interval_ms > 0 && i < self.nb_ping - 1 {
-                    sleep(Duration::from_millis(interval_ms)).await;
-                }
-            } else if interval_ms > 0 {
-                sleep(Duration::from_millis(interval_ms)).await;
-            }
+    for option in tcp.get_options_iter() {
+        let number = option.get_number();
+        let data = option.payload();
+        match number {
+            TcpOptionNumbers::NOP | TcpOptionNumbers::EOL => {}
+            TcpOptionNumbers::MSS if data.len() == 2 => result.push(format!("mss={}", u16::from_be_bytes([data[0], data[1]]))),
+            TcpOptionNumbers::WSCALE if data.len() == 1 => result.push(format!("wscale={}", data[0])),
+            TcpOptionNumbers::SACK_PERMITTED => result.push(String::from("sack")),
+            TcpOptionNumbers::TIMESTAMPS if data.len() == 8 => result.push(format!(
+                "ts={}/{}",
+                u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+                u32::from_be_bytes([data[4], data[5], data[6], data[7]])
+            )),
+            _ => result.push(format!("kind{}", number.0)),
         }
-
-        use crossterm::terminal::disable_raw_mode;
-        disable_raw_mode()?;
-        self.latency_data();
-        Ok(())
     }
+    result.join(" ")
+}
 
-    // Override output filename and ping count after construction.
-    #[allow(dead_code)]
-    fn setting(&mut self, output: &str, nb_ping: u32) {
-        self.output = output.to_string();
-        self.nb_ping = nb_ping;
-    }
+// TSval of the timestamps option: a clock in ms, it only has to grow
+fn timestamp_ms() -> u32 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |x| x.as_millis() as u32)
+}
 
-    // Construct a new TCPPingTool with all vectors initialised to empty.
-    pub fn new(target: &str, output: &str, nb_ping: u32, port: u16, flag: u8) -> Self {
-        Self {
-            target: target.to_string(),
-            output: output.to_string(),
-            port,
-            nb_ping,
-            flag,
-            data: Vec::new(),
-            sys_time: Vec::new(),
-            begin_time: 0,
-            elapsed_time: 0,
-            latency_time: Vec::new(),
-            latency_min: Vec::new(),
-            latency_moy: Vec::new(),
-            latency_max: Vec::new(),
-            latency_min_sampled: Vec::new(),
-            latency_moy_sampled: Vec::new(),
-            latency_max_sampled: Vec::new(),
-        }
+// Reserve a free source port of the host with a bound TCP socket, the kernel picks it at random in the ephemeral range
+// The port stays reserved while the socket is open => the segments received on it are only ours
+pub(crate) fn reserve_port(src_ip: IpAddr) -> io::Result<(Socket, u16)> {
+    let socket = Socket::new(Domain::for_address(SocketAddr::new(src_ip, 0)), Type::STREAM, Some(Protocol::TCP))?;
+    socket.bind(&SocketAddr::new(src_ip, 0).into())?;
+    let port = socket.local_addr()?.as_socket().map_or(0, |x| x.port());
+    Ok((socket, port))
+}
+
+// Random number of the kernel => sequence numbers cannot be guessed by a middlebox
+fn random_u32() -> u32 {
+    let mut value = [0u8; 4];
+    let read = unsafe { libc::getrandom(value.as_mut_ptr() as *mut libc::c_void, value.len(), 0) };
+    if read == value.len() as isize {
+        return u32::from_ne_bytes(value);
     }
+    // No entropy available => the clock still gives a different number to each probe
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |x| x.subsec_nanos() ^ x.as_secs() as u32)
+}
+
+// Write the TCP header of a segment with its options, the checksum is left to 0
+fn write_tcp_header(buffer: &mut [u8], segment: &TcpSegment) {
+    let mut tcp = MutableTcpPacket::new(buffer).unwrap();
+    tcp.set_source(segment.src_port);
+    tcp.set_destination(segment.dst_port);
+    tcp.set_sequence(segment.seq);
+    // The acknowledgement number is only meaningful with ACK, a RST answering it takes it as sequence
+    tcp.set_acknowledgement(if segment.flags & TcpFlags::ACK != 0 { segment.ack } else { 0 });
+    tcp.set_data_offset((segment.header_len() / 4) as u8);
+    tcp.set_flags(segment.flags);
+    tcp.set_window(64240);
+    tcp.set_urgent_ptr(0);
+    tcp.set_checksum(0);
+    segment.options.write(&mut buffer[20..segment.header_len()]);
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
 // IPv4 helpers
 // ─────────────────────────────────────────────────────────────────────────────
 
-fn build_ipv4_packet(
+// TTL of the IPv4 packets of tcp ping and of the scan without --ttl
+pub(crate) const DEFAULT_TTL: u8 = 64;
+
+// TTL and TOS of the IPv4 header of a probe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Ipv4Fields {
+    pub ttl: u8,
+    pub tos: u8,
+}
+
+// Build an IPv4 + TCP packet of 40 bytes plus the TCP options, the TTL and TOS are chosen by the caller (traceroute sends TTL-limited packets)
+pub(crate) fn build_ipv4_packet(
     buffer: &mut [u8],
     src_ip: Ipv4Addr,
     dst_ip: Ipv4Addr,
-    src_port: u16,
-    dst_port: u16,
-    flags: u8,
+    segment: &TcpSegment,
+    fields: Ipv4Fields,
 ) {
     let mut ip_packet = MutableIpv4Packet::new(buffer).unwrap();
     ip_packet.set_version(4);
     ip_packet.set_header_length(5);
-    ip_packet.set_total_length(40);
-    ip_packet.set_ttl(64);
+    let len = 20 + segment.header_len();
+    ip_packet.set_total_length(len as u16);
+    ip_packet.set_dscp(fields.tos >> 2);
+    ip_packet.set_ecn(fields.tos & 0x03);
+    ip_packet.set_ttl(fields.ttl);
     ip_packet.set_next_level_protocol(IpNextHeaderProtocols::Tcp);
     ip_packet.set_source(src_ip);
     ip_packet.set_destination(dst_ip);
@@ -388,24 +477,29 @@ fn build_ipv4_packet(
     let checksum = ipv4_checksum(&ip_packet.to_immutable());
     ip_packet.set_checksum(checksum);
 
-    {
-        let mut tcp = MutableTcpPacket::new(&mut buffer[20..40]).unwrap();
-        tcp.set_source(src_port);
-        tcp.set_destination(dst_port);
-        tcp.set_sequence(12345);
-        tcp.set_acknowledgement(0);
-        tcp.set_data_offset(5);
-        tcp.set_flags(flags);
-        tcp.set_window(64240);
-        tcp.set_urgent_ptr(0);
-        tcp.set_checksum(0);
-    }
+    write_tcp_header(&mut buffer[20..len], segment);
 
-    let cksum = tcp_checksum_ipv4(src_ip, dst_ip, &buffer[20..40]);
-    let mut tcp = MutableTcpPacket::new(&mut buffer[20..40]).unwrap();
+    let cksum = tcp_checksum_ipv4(src_ip, dst_ip, &buffer[20..len]);
+    let mut tcp = MutableTcpPacket::new(&mut buffer[20..len]).unwrap();
     tcp.set_checksum(cksum);
 }
 
+// Build and send an IPv4 + TCP packet on the Layer3 channel
+pub(crate) fn send_segment_v4(
+    sender: &mut TransportSender,
+    src_ip: Ipv4Addr,
+    dst_ip: Ipv4Addr,
+    segment: &TcpSegment,
+    fields: Ipv4Fields,
+) -> io::Result<()> {
+    let mut buffer = [0u8; 60]; // IPv4 (20) + TCP (20) + TCP options (20 at most)
+    let len = 20 + segment.header_len();
+    build_ipv4_packet(&mut buffer[..len], src_ip, dst_ip, segment, fields);
+    let packet = Ipv4Packet::new(&buffer[..len]).ok_or_else(|| io::Error::other("invalid IPv4 packet"))?;
+    sender.send_to(packet, IpAddr::V4(dst_ip))?;
+    Ok(())
+}
+
 fn tcp_checksum_ipv4(src_ip: Ipv4Addr, dst_ip: Ipv4Addr, tcp_packet: &[u8]) -> u16 {
     let mut sum = 0u32;
 
@@ -433,46 +527,15 @@ fn tcp_checksum_ipv4(src_ip: Ipv4Addr, dst_ip: Ipv4Addr, tcp_packet: &[u8]) -> u
     !(sum as u16)
 }
 
-// Layer3 channel: ipv4_packet_iter gives raw IPv4 packets; TCP must be extracted from payload.
-async fn wait_reply_ipv4(
-    receiver: &mut pnet::transport::TransportReceiver,
-    target_ip: Ipv4Addr,
-    target_port: u16,
-    start: Instant,
-) -> Option<(Duration, u8)> {
-    let timeout = Duration::from_millis(5000);
-
-    loop {
-        if start.elapsed() > timeout {
-            return Some((timeout, 0));
-        }
-
-        let mut iter = ipv4_packet_iter(receiver);
-
-        match iter.next() {
-            Ok((packet, addr)) => {
-                if let std::net::IpAddr::V4(src_ip) = addr {
-                    if src_ip != target_ip {
-                        continue;
-                    }
-                } else {
-                    continue;
-                }
-
-                if packet.get_next_level_protocol() == IpNextHeaderProtocols::Tcp {
-                    if let Some(tcp) = pnet::packet::tcp::TcpPacket::new(packet.payload()) {
-                        if tcp.get_source() == target_port {
-                            return Some((start.elapsed(), tcp.get_flags()));
-                        }
-                    }
-                }
-            }
-            Err(_) => {
-                sleep(Duration::from_millis(10)).await;
-                continue;
-            }
-        }
-    }
+// Layer3 raw socket: IPv4 packets with our header are sent, every TCP packet received by the host is read with its IPv4 header
+// The receiving half is a duplicate of the descriptor registered in the tokio reactor => a probe waits its reply without blocking a worker
+// The channel stays in blocking mode for pnet, the duplicate is only read with MSG_DONTWAIT
+pub(crate) fn open_ipv4_channel() -> io::Result<(TransportSender, AsyncFd<Socket>)> {
+    let protocol = TransportChannelType::Layer3(IpNextHeaderProtocols::Tcp);
+    let (sender, receiver) = transport_channel(4096, protocol)?;
+    // SAFETY: the descriptor is owned by the channel, alive while it is borrowed
+    let fd = unsafe { BorrowedFd::borrow_raw(receiver.socket.fd) }.try_clone_to_owned()?;
+    Ok((sender, AsyncFd::new(Socket::from(fd))?))
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
@@ -482,32 +545,23 @@ async fn wait_reply_ipv4(
 // Build a TCP-only segment for IPv6 raw socket.
 // The kernel adds the IPv6 header automatically on SOCK_RAW with IPPROTO_TCP.
 // We must compute the TCP checksum manually using the IPv6 pseudo-header.
-fn build_tcp_packet_v6(
-    buffer: &mut [u8],
-    src_ip: Ipv6Addr,
-    dst_ip: Ipv6Addr,
-    src_port: u16,
-    dst_port: u16,
-    flags: u8,
-) {
-    {
-        let mut tcp = MutableTcpPacket::new(buffer).unwrap();
-        tcp.set_source(src_port);
-        tcp.set_destination(dst_port);
-        tcp.set_sequence(12345);
-        tcp.set_acknowledgement(0);
-        tcp.set_data_offset(5);
-        tcp.set_flags(flags);
-        tcp.set_window(64240);
-        tcp.set_urgent_ptr(0);
-        tcp.set_checksum(0);
-    }
+pub(crate) fn build_tcp_packet_v6(buffer: &mut [u8], src_ip: Ipv6Addr, dst_ip: Ipv6Addr, segment: &TcpSegment) {
+    write_tcp_header(buffer, segment);
     // Checksum must be computed after all fields are set
     let cksum = tcp_checksum_ipv6(src_ip, dst_ip, buffer);
     let mut tcp = MutableTcpPacket::new(buffer).unwrap();
     tcp.set_checksum(cksum);
 }
 
+// Build and send a TCP segment on a raw IPv6 socket
+pub(crate) fn send_segment_v6(socket: &Socket, src_ip: Ipv6Addr, dst_ip: Ipv6Addr, segment: &TcpSegment) -> io::Result<()> {
+    let mut buffer = [0u8; 40]; // TCP (20) + TCP options (20 at most)
+    let buffer = &mut buffer[..segment.header_len()];
+    build_tcp_packet_v6(buffer, src_ip, dst_ip, segment);
+    socket.send_to(buffer, &SockAddr::from(SocketAddr::new(IpAddr::V6(dst_ip), 0)))?; // port = 0 on raw sockets
+    Ok(())
+}
+
 fn tcp_checksum_ipv6(src_ip: Ipv6Addr, dst_ip: Ipv6Addr, tcp_packet: &[u8]) -> u16 {
     let mut sum = 0u32;
 
@@ -519,8 +573,8 @@ fn tcp_checksum_ipv6(src_ip: Ipv6Addr, dst_ip: Ipv6Addr, tcp_packet: &[u8]) -> u
         sum += u16::from_be_bytes([byte[0], byte[1]]) as u32;
     }
     let tcp_len = tcp_packet.len() as u32;
-    sum += (tcp_len >> 16) as u32;
-    sum += (tcp_len & 0xFFFF) as u32;
+    sum += tcp_len >> 16;
+    sum += tcp_len & 0xFFFF;
     sum += IpNextHeaderProtocols::Tcp.0 as u32;
 
     // TCP segment
@@ -539,65 +593,109 @@ fn tcp_checksum_ipv6(src_ip: Ipv6Addr, dst_ip: Ipv6Addr, tcp_packet: &[u8]) -> u
     !(sum as u16)
 }
 
-// Receive the TCP reply on a raw IPv6 socket.
-// On Linux, raw IPv6 sockets with IPPROTO_TCP receive TCP segments directly
-// (the IPv6 header is stripped by the kernel before delivery).
-async fn wait_reply_ipv6_raw(
-    target_ip: Ipv6Addr,
-    target_port: u16,
-    start: Instant,
-) -> Option<(Duration, u8)> {
-    let recv_sock = Socket::new(
-        Domain::IPV6,
-        Type::RAW,
-        Some(Protocol::from(6)), // IPPROTO_TCP = 6
-    )
-    .expect("failed to create IPv6 raw receive socket (need root)");
+// Raw IPv6 TCP socket registered in the tokio reactor, the hop limit of the replies is given as ancillary data
+pub(crate) fn open_ipv6_raw() -> io::Result<AsyncFd<Socket>> {
+    let socket = Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::TCP))?;
+    socket.set_recv_hoplimit_v6(true)?;
+    socket.set_nonblocking(true)?;
+    AsyncFd::new(socket)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Common helpers
+// ─────────────────────────────────────────────────────────────────────────────
 
-    // Non-blocking poll with 100ms timeout so we can check the overall deadline
-    recv_sock.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+// Wait the answer of the target to a segment or the ICMP error of a router about it, None at the timeout
+// receiver is the raw socket of the IP version of the target: IPv4 packets with their header, bare TCP segments for IPv6
+// It is opened before the segment is sent => the reply is queued on it even on a fast link
+pub(crate) async fn wait_tcp_reply(
+    receiver: &AsyncFd<Socket>,
+    icmp: Option<&IcmpSocket>,
+    target_ip: IpAddr,
+    segment: &TcpSegment,
+    start: Instant,
+    timeout: Duration,
+) -> io::Result<Option<TcpReply>> {
+    let deadline = tokio::time::Instant::from_std(start + timeout);
+    let reply = timeout_at(deadline, async {
+        tokio::select! {
+            reply = wait_tcp_segment(receiver, target_ip, segment, start) => reply,
+            (latency, error) = wait_icmp_error(icmp, target_ip, segment, start) => Ok(TcpReply::Icmp(latency, error)),
+        }
+    });
+    match reply.await {
+        Ok(reply) => reply.map(Some),
+        Err(_elapsed) => Ok(None),
+    }
+}
 
-    let timeout = Duration::from_millis(5000);
-    let mut buf = vec![std::mem::MaybeUninit::<u8>::uninit(); 1024];
+// Wait a segment of the target to our port that answers our sequence numbers
+async fn wait_tcp_segment(receiver: &AsyncFd<Socket>, target_ip: IpAddr, segment: &TcpSegment, start: Instant) -> io::Result<TcpReply> {
+    let mut buf = [0u8; 1024];
 
     loop {
-        if start.elapsed() > timeout {
-            return Some((Duration::from_millis(5000), 0x00));
-        }
+        let mut guard = receiver.readable().await?;
+        let (range, ttl) = match guard.try_io(|fd| recv_tcp_segment(fd.get_ref(), &mut buf, target_ip)) {
+            Ok(received) => match received? {
+                Some(received) => received,
+                None => continue,
+            },
+            Err(_would_block) => continue,
+        };
 
-        match recv_sock.recv_from(&mut buf) {
-            Ok((n, addr)) => {
-                // Filter by source address
-                if let Some(std::net::IpAddr::V6(src_ip)) = addr.as_socket_ipv6().map(|a| IpAddr::V6(a.ip().clone())) {
-                    if src_ip != target_ip {
-                        continue;
-                    }
-                } else {
-                    continue;
-                }
+        if let Some(tcp) = TcpPacket::new(&buf[range])
+            && tcp.get_source() == segment.dst_port
+            && tcp.get_destination() == segment.src_port
+            && segment.is_answered_by(&tcp)
+        {
+            return Ok(TcpReply::Segment(start.elapsed(), tcp.get_flags(), decode_tcp_options(&tcp), ttl));
+        }
+    }
+}
 
-                // Reconstruct a byte slice from MaybeUninit
-                let received: Vec<u8> = buf[..n]
-                    .iter()
-                    .map(|b| unsafe { b.assume_init() })
-                    .collect();
+// Read one packet queued on the raw socket of the IP version of the target without waiting
+// Returns where the TCP segment of the target is in buf with its TTL, None for a packet of another host or protocol
+// IPv4: the TCP segment is the payload of the packet, the TTL is read in its header
+// IPv6: the kernel strips the header, the hop limit is given as ancillary data
+pub(crate) fn recv_tcp_segment(socket: &Socket, buf: &mut [u8], target_ip: IpAddr) -> io::Result<Option<(Range<usize>, Option<u8>)>> {
+    let received = recv_message(socket, buf, 0)?;
+    if received.from != target_ip {
+        return Ok(None);
+    }
+
+    match target_ip {
+        IpAddr::V4(_) => match Ipv4Packet::new(&buf[..received.len]) {
+            Some(packet) if packet.get_next_level_protocol() == IpNextHeaderProtocols::Tcp => {
+                let end = (packet.get_total_length() as usize).min(received.len);
+                let offset = (packet.get_header_length() as usize * 4).min(end);
+                Ok(Some((offset..end, Some(packet.get_ttl()))))
+            }
+            _ => Ok(None),
+        },
+        IpAddr::V6(_) => Ok(Some((0..received.len, received.ttl))),
+    }
+}
 
-                if let Some(tcp) = pnet::packet::tcp::TcpPacket::new(&received) {
-                    if tcp.get_source() == target_port {
-                        return Some((start.elapsed(), tcp.get_flags()));
-                    }
-                }
+// Wait the first ICMP error about our segment and return it with its latency:
+// it quotes a TCP packet sent to the target from our source port to the target port with our sequence number
+// ICMP errors are only a detail => never returns without the socket or if it fails
+async fn wait_icmp_error(icmp: Option<&IcmpSocket>, target_ip: IpAddr, segment: &TcpSegment, start: Instant) -> (Duration, IcmpError) {
+    if let Some(icmp) = icmp {
+        while let Ok(message) = icmp.recv().await {
+            if let (Some(error), Some(quoted)) = (message.error, message.quoted)
+                && quoted.protocol == IPPROTO_TCP
+                && quoted.dst == target_ip
+                && quoted.ports() == Some((segment.src_port, segment.dst_port))
+                && quoted.header.get(4..8).is_none_or(|x| x == segment.seq.to_be_bytes())
+            {
+                return (message.received.saturating_duration_since(start), error);
             }
-            Err(_) => continue, // timeout on this recv, loop and check overall deadline
         }
     }
+    pending().await
 }
 
-// ─────────────────────────────────────────────────────────────────────────────
-// Common helpers
-// ─────────────────────────────────────────────────────────────────────────────
-
-fn decode_tcp_flags(flags: u8) -> String {
+pub(crate) fn decode_tcp_flags(flags: u8) -> String {
     let mut result = Vec::new();
 
     if flags & 0x01 != 0 { result.push("FIN"); }
@@ -606,10 +704,134 @@ fn decode_tcp_flags(flags: u8) -> String {
     if flags & 0x08 != 0 { result.push("PSH"); }
     if flags & 0x10 != 0 { result.push("ACK"); }
     if flags & 0x20 != 0 { result.push("URG"); }
+    if flags & 0x40 != 0 { result.push("ECE"); }
+    if flags & 0x80 != 0 { result.push("CWR"); }
 
     if result.is_empty() {
         "NONE".to_string()
     } else {
         result.join("|")
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Segment of the target with these flags, sequence and acknowledgement numbers
+    fn reply(flags: u8, seq: u32, ack: u32) -> Vec<u8> {
+        let mut buffer = vec![0u8; 20];
+        let mut tcp = MutableTcpPacket::new(&mut buffer).unwrap();
+        tcp.set_source(443);
+        tcp.set_destination(40000);
+        tcp.set_sequence(seq);
+        tcp.set_acknowledgement(ack);
+        tcp.set_data_offset(5);
+        tcp.set_flags(flags);
+        buffer
+    }
+
+    fn segment(flags: u8, seq: u32, ack: u32) -> TcpSegment {
+        TcpSegment { seq, ack, ..TcpSegment::new(40000, 443, flags) }
+    }
+
+    fn answers(probe: &TcpSegment, flags: u8, seq: u32, ack: u32) -> bool {
+        probe.is_answered_by(&TcpPacket::new(&reply(flags, seq, ack)).unwrap())
+    }
+
+    #[test]
+    fn options_length_is_padded_to_32_bits() {
+        let none = TcpOptions::default();
+        assert_eq!(none.len(), 0);
+        assert_eq!(TcpOptions { mss: Some(1460), ..none }.len(), 4);
+        assert_eq!(TcpOptions { sack_permitted: true, ..none }.len(), 4);
+        assert_eq!(TcpOptions { wscale: Some(7), ..none }.len(), 4);
+        assert_eq!(TcpOptions { timestamp: true, ..none }.len(), 12);
+        assert_eq!(TcpOptions { mss: Some(1460), wscale: Some(7), ..none }.len(), 8);
+        let all = TcpOptions { mss: Some(1460), wscale: Some(7), sack_permitted: true, timestamp: true };
+        assert_eq!(all.len(), 20);
+        assert_eq!(segment(TcpFlags::SYN, 1, 0).header_len(), 20);
+        assert_eq!(TcpSegment { options: all, ..segment(TcpFlags::SYN, 1, 0) }.header_len(), 40);
+    }
+
+    #[test]
+    fn options_layout_of_linux() {
+        let options = TcpOptions { mss: Some(1460), sack_permitted: true, ..TcpOptions::default() };
+        let mut buffer = [0u8; 8];
+        options.write(&mut buffer);
+        assert_eq!(buffer, [2, 4, 0x05, 0xb4, 4, 2, 0, 0]);
+
+        let options = TcpOptions { wscale: Some(14), ..TcpOptions::default() };
+        let mut buffer = [0u8; 4];
+        options.write(&mut buffer);
+        assert_eq!(buffer, [3, 3, 14, 0]);
+    }
+
+    #[test]
+    fn all_options_fill_the_40_bytes_header() {
+        let options = TcpOptions { mss: Some(536), wscale: Some(7), sack_permitted: true, timestamp: true };
+        let probe = TcpSegment { options, ..segment(TcpFlags::SYN, 0x01020304, 0) };
+        let before = timestamp_ms();
+        let mut buffer = [0u8; 40];
+        write_tcp_header(&mut buffer, &probe);
+        let after = timestamp_ms();
+
+        assert_eq!(buffer[12] >> 4, 10); // Data offset in 32 bits words
+        assert_eq!(&buffer[4..8], &[1, 2, 3, 4]);
+        assert_eq!(&buffer[8..12], &[0, 0, 0, 0]); // No acknowledgement number without ACK
+        assert_eq!(&buffer[20..28], &[2, 4, 0x02, 0x18, 4, 2, 8, 10]); // MSS, SACK permitted, timestamps
+        let tsval = u32::from_be_bytes(buffer[28..32].try_into().unwrap());
+        assert!(before <= tsval && tsval <= after);
+        assert_eq!(&buffer[32..40], &[0, 0, 0, 0, 3, 3, 7, 0]); // TSecr 0, window scale, End of Option List
+
+        let tcp = TcpPacket::new(&buffer).unwrap();
+        assert_eq!(decode_tcp_options(&tcp), format!("mss=536 sack ts={}/0 wscale=7", tsval));
+    }
+
+    #[test]
+    fn syn_is_answered_by_its_acknowledgement() {
+        let syn = segment(TcpFlags::SYN, 1000, 0);
+        assert!(answers(&syn, TcpFlags::SYN | TcpFlags::ACK, 5000, 1001));
+        assert!(answers(&syn, TcpFlags::RST | TcpFlags::ACK, 0, 1001));
+        assert!(!answers(&syn, TcpFlags::SYN | TcpFlags::ACK, 5000, 1000));
+        assert!(!answers(&syn, TcpFlags::SYN | TcpFlags::ACK, 5000, 1002));
+        // A RST without ACK answers only an ACK
+        assert!(!answers(&syn, TcpFlags::RST, 0, 0));
+
+        let wrapping = segment(TcpFlags::SYN, u32::MAX, 0);
+        assert!(answers(&wrapping, TcpFlags::SYN | TcpFlags::ACK, 5000, 0));
+    }
+
+    #[test]
+    fn fin_counts_in_the_sequence_space() {
+        let fin = segment(TcpFlags::FIN | TcpFlags::PSH | TcpFlags::URG, 2000, 0);
+        assert!(answers(&fin, TcpFlags::RST | TcpFlags::ACK, 0, 2001));
+        assert!(!answers(&fin, TcpFlags::RST | TcpFlags::ACK, 0, 2000));
+
+        let null = segment(0, 3000, 0);
+        assert!(answers(&null, TcpFlags::RST | TcpFlags::ACK, 0, 3000));
+        assert!(!answers(&null, TcpFlags::RST | TcpFlags::ACK, 0, 3001));
+    }
+
+    #[test]
+    fn ack_is_answered_by_a_rst_taking_its_acknowledgement() {
+        let ack = segment(TcpFlags::ACK, 4000, 777);
+        assert!(answers(&ack, TcpFlags::RST, 777, 0));
+        assert!(!answers(&ack, TcpFlags::RST, 778, 0));
+        assert!(!answers(&ack, TcpFlags::SYN, 777, 0));
+        // With ACK the reply must acknowledge our sequence number, an ACK has no sequence space
+        assert!(answers(&ack, TcpFlags::RST | TcpFlags::ACK, 0, 4000));
+    }
+
+    #[test]
+    fn reset_closes_only_the_half_open_connexion() {
+        let syn = segment(TcpFlags::SYN, 1000, 0);
+        let reset = syn.reset_for(TcpFlags::SYN | TcpFlags::ACK).unwrap();
+        assert_eq!((reset.src_port, reset.dst_port, reset.seq, reset.flags), (40000, 443, 1001, TcpFlags::RST));
+        assert_eq!(reset.options, TcpOptions::default());
+
+        assert!(syn.reset_for(TcpFlags::RST | TcpFlags::ACK).is_none());
+        assert!(segment(TcpFlags::SYN | TcpFlags::ACK, 1000, 1).reset_for(TcpFlags::SYN | TcpFlags::ACK).is_none());
+        assert!(segment(TcpFlags::ACK, 1000, 1).reset_for(TcpFlags::RST).is_none());
+    }
+}
@@ -0,0 +1,437 @@
+use crate::error::{Error, Result};
+use crate::socket::SocketOptions;
+use crate::tool::{Probe, ProbeError, ProbeOutcome, ProbeResult, ReplyDetail, Timings, as_ms, resolve_target};
+use rustls::client::Resumption;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{WebPkiSupportedAlgorithms, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, ProtocolVersion, RootCertStore, SignatureScheme};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::time::timeout_at;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::client::TlsStream;
+
+//Default port of the TLS probe => HTTPS
+pub const TLS_PORT: u16 = 443;
+
+//Columns added to the csv export by the TLS probe => phases in ms since the start of the probe and negotiated session
+pub const TLS_CSV_COLUMNS: [&str; 6] = ["Connect", "TLS", "TLS Version", "Cipher", "ALPN", "Cert Expiry"];
+
+//Options of the TLS handshake
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsOptions {
+    pub sni: Option<String>, //Server name sent in the handshake and checked in the certificate, the target if it is a name by default
+    pub alpn: Vec<String>, //Protocols offered with ALPN in order of preference, ex: h2, http/1.1
+    pub insecure: bool, //Accept any certificate => expired, self-signed or of another name, the signature of the handshake is still checked
+}
+
+//TLS object definition => one TCP connection + TLS handshake per probe, run by ProbeRunner
+//The latency is the time until the handshake is done, the connect and tls phases are kept apart like curl -w
+pub struct TlsTool {
+    pub target: String, //Name or IP of the server
+    port: u16,
+    options: TlsOptions,
+    target_ip: IpAddr, //Resolved by prepare
+    server_name: Option<ServerName<'static>>, //Name checked in the certificate, built by prepare
+    connector: Option<TlsConnector>, //Client configuration shared by the probes, built by prepare
+    socket_options: SocketOptions, //TOS, TTL, source and interface of the connections
+}
+
+//Session negotiated by a TLS handshake
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsSession {
+    pub version: String, //TLSv1.2 or TLSv1.3
+    pub cipher: String, //Cipher suite, ex: TLS13_AES_128_GCM_SHA256
+    pub alpn: Option<String>, //Protocol chosen by the server among the ALPN ones
+    pub cert_expiry: Option<u64>, //End of validity of the certificate of the server in s since UNIX_EPOCH
+}
+
+//Failed handshake => the reason of the failed probe and its detail for the front-end
+type HandshakeError = (ProbeError, Option<String>);
+
+impl Probe for TlsTool {
+    //Return only the name of the object
+    fn name(&self) -> &'static str {
+        "tls"
+    }
+
+    //Return the target attribute
+    fn target(&self) -> &str {
+        &self.target
+    }
+
+    //Resolve the target and build the client configuration shared by all the handshakes
+    async fn prepare(&mut self) -> Result<Option<String>> {
+        self.target_ip = resolve_target(&self.target)?;
+        self.socket_options.check(self.target_ip)?;
+        self.server_name = Some(server_name(&self.target, &self.options)?);
+        self.connector = Some(TlsConnector::from(client_config(&self.options)));
+
+        let mut description = format!("TLS {}", SocketAddr::new(self.target_ip, self.port));
+        if let Some(ServerName::DnsName(name)) = &self.server_name {
+            description = format!("{} sni={}", description, name.as_ref());
+        }
+        if !self.options.alpn.is_empty() {
+            description = format!("{} alpn={}", description, self.options.alpn.join(","));
+        }
+        if self.options.insecure {
+            description = format!("{} insecure", description);
+        }
+        if !self.socket_options.is_default() {
+            description = format!("{} {}", description, self.socket_options);
+        }
+        Ok(Some(description))
+    }
+
+    //Do one connection + handshake => the connect phase is kept even if the handshake fails or times out
+    async fn probe(&mut self, _seq: u32, timeout: Duration) -> ProbeOutcome {
+        let (Some(connector), Some(server_name)) = (self.connector.clone(), self.server_name.clone()) else {
+            return ProbeResult::Error(ProbeError::Other(String::from("tls not prepared"))).into();
+        };
+
+        //Start to count the time
+        let start = Instant::now();
+        let deadline = tokio::time::Instant::from_std(start + timeout);
+        let mut timings = Timings::default();
+
+        let addr = SocketAddr::new(self.target_ip, self.port);
+        let handshake = timeout_at(deadline, async {
+            let stream = self.socket_options.connect(addr).await.map_err(|e| (ProbeError::from(&e), None))?;
+            timings.connect = Some(start.elapsed());
+            let stream = handshake(&connector, server_name, stream).await?;
+            timings.tls = Some(start.elapsed());
+            Ok::<_, HandshakeError>(stream)
+        })
+        .await;
+
+        let mut session = None;
+        let (result, info) = match handshake {
+            Ok(Ok(stream)) => {
+                let negotiated = TlsSession::from_connection(stream.get_ref().1);
+                let info = negotiated.to_string();
+                session = Some(negotiated);
+                (ProbeResult::Reply(timings.tls.unwrap_or_default()), Some(info))
+            }
+            Ok(Err((error, info))) => (ProbeResult::Error(error), info),
+            //Timeout elapsed
+            Err(_) => (ProbeResult::Timeout, None),
+        };
+
+        let session = session.unwrap_or_default();
+        let phase = |x: Option<Duration>| x.map_or(String::new(), |x| format!("{:.3}", as_ms(x)));
+        let mut outcome = ProbeOutcome::new(result, info);
+        outcome.reply = ReplyDetail {
+            from: Some(self.target_ip),
+            timings: Some(timings),
+            cert_expiry: session.cert_expiry,
+            columns: vec![
+                phase(timings.connect),
+                phase(timings.tls),
+                session.version,
+                session.cipher,
+                session.alpn.unwrap_or_default(),
+                session.cert_expiry.map(format_date).unwrap_or_default(),
+            ],
+            ..Default::default()
+        };
+        outcome
+    }
+
+    //Phases and negotiated session of each handshake
+    fn csv_columns(&self) -> &'static [&'static str] {
+        &TLS_CSV_COLUMNS
+    }
+}
+
+//Specific methods of TlsTool that not match with the Probe Trait general definition
+impl TlsTool {
+    //Init attributes of the object, port 0 => TLS_PORT
+    pub fn new(target: &str, port: u16, options: TlsOptions, socket_options: SocketOptions) -> Self {
+        Self {
+            target: target.to_string(),
+            port: if port == 0 { TLS_PORT } else { port },
+            options,
+            target_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            server_name: None,
+            connector: None,
+            socket_options,
+        }
+    }
+}
+
+impl TlsSession {
+    //Session of a connection whose handshake is done
+    pub(crate) fn from_connection(connection: &ClientConnection) -> Self {
+        let version = match connection.protocol_version() {
+            Some(ProtocolVersion::TLSv1_2) => String::from("TLSv1.2"),
+            Some(ProtocolVersion::TLSv1_3) => String::from("TLSv1.3"),
+            Some(version) => format!("{:?}", version),
+            None => String::new(),
+        };
+        let cipher = connection.negotiated_cipher_suite().map_or(String::new(), |x| format!("{:?}", x.suite()));
+        let alpn = connection.alpn_protocol().map(|x| String::from_utf8_lossy(x).to_string());
+
+        //The first certificate is the one of the server, the next ones are the chain
+        let cert_expiry = connection.peer_certificates().and_then(|x| x.first()).and_then(|x| certificate_expiry(x));
+        Self { version, cipher, alpn, cert_expiry }
+    }
+}
+
+//Session displayed for the front-end => "TLSv1.3 TLS13_AES_128_GCM_SHA256 alpn=h2 cert expires 2027-01-15 (89 days)"
+impl fmt::Display for TlsSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.version, self.cipher)?;
+        if let Some(alpn) = &self.alpn {
+            write!(f, " alpn={}", alpn)?;
+        }
+        if let Some(expiry) = self.cert_expiry {
+            let now = UnixTime::now().as_secs();
+            if expiry >= now {
+                write!(f, " cert expires {} ({} days)", format_date(expiry), (expiry - now) / 86_400)?;
+            } else {
+                write!(f, " cert expired {} ({} days ago)", format_date(expiry), (now - expiry) / 86_400)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//Name of the server for the handshake => the SNI option, else the target
+//An IP target without SNI sends no server name, the certificate is then checked against the IP
+pub(crate) fn server_name(target: &str, options: &TlsOptions) -> Result<ServerName<'static>> {
+    let name = options.sni.as_deref().unwrap_or(target);
+    ServerName::try_from(name.to_string()).map_err(|_| Error::InvalidAddress(name.to_string()))
+}
+
+//Client configuration of the handshakes => TLS 1.2 and 1.3 with the Mozilla root certificates
+//Session resumption is disabled => each probe measures a full handshake
+pub(crate) fn client_config(options: &TlsOptions) -> Arc<ClientConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    //The ring provider supports the default protocol versions
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .expect("ring supports TLS 1.2 and 1.3");
+
+    let mut config = if options.insecure {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AnyCertificate(provider.signature_verification_algorithms)))
+            .with_no_client_auth()
+    } else {
+        let roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+        builder.with_root_certificates(roots).with_no_client_auth()
+    };
+    config.alpn_protocols = options.alpn.iter().map(|x| x.as_bytes().to_vec()).collect();
+    config.resumption = Resumption::disabled();
+    Arc::new(config)
+}
+
+//Do the TLS handshake on a connected stream
+pub(crate) async fn handshake(
+    connector: &TlsConnector,
+    server_name: ServerName<'static>,
+    stream: TcpStream,
+) -> std::result::Result<TlsStream<TcpStream>, HandshakeError> {
+    connector.connect(server_name, stream).await.map_err(|e| {
+        //The alerts and the certificate errors are rustls errors inside the io error
+        let reason = match e.get_ref().and_then(|x| x.downcast_ref::<rustls::Error>()) {
+            Some(tls) => tls.to_string(),
+            None if e.kind() == io::ErrorKind::UnexpectedEof => String::from("connection closed by the server"),
+            None => return (ProbeError::from(&e), None),
+        };
+        (ProbeError::Other(String::from("handshake failed")), Some(format!("handshake failed: {}", reason)))
+    })
+}
+
+//Certificate verifier of the insecure option => any certificate is accepted
+//The signature of the handshake is still checked => the server has the key of the certificate it sent
+#[derive(Debug)]
+struct AnyCertificate(WebPkiSupportedAlgorithms);
+
+impl ServerCertVerifier for AnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.0)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.0)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_schemes()
+    }
+}
+
+//End of validity of a DER certificate in s since UNIX_EPOCH => notAfter of tbsCertificate, None if the certificate cannot be read
+//Certificate ::= SEQUENCE { tbsCertificate SEQUENCE { [0] version OPTIONAL, serialNumber, signature, issuer, validity, ... }, ... }
+fn certificate_expiry(der: &[u8]) -> Option<u64> {
+    let (certificate, _) = der_element(der, 0x30)?;
+    let (tbs, _) = der_element(certificate, 0x30)?;
+
+    //Skip the version, the serial number, the signature algorithm and the issuer
+    let mut fields = tbs;
+    if fields.first() == Some(&0xa0) {
+        fields = der_element(fields, 0xa0)?.1;
+    }
+    for _ in 0..3 {
+        fields = der_element(fields, *fields.first()?)?.1;
+    }
+
+    //Validity ::= SEQUENCE { notBefore Time, notAfter Time }
+    let (validity, _) = der_element(fields, 0x30)?;
+    let not_after = der_element(validity, *validity.first()?)?.1;
+    let tag = *not_after.first()?;
+    let (time, _) = der_element(not_after, tag)?;
+    der_time(tag, std::str::from_utf8(time).ok()?)
+}
+
+//Content of the DER element of this tag at the start of data and the data after it
+fn der_element(data: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    if *data.first()? != tag {
+        return None;
+    }
+    let first = *data.get(1)? as usize;
+
+    //Short form => the length is the byte, long form => the byte is the number of bytes of the length
+    let (length, header) = if first < 0x80 {
+        (first, 2)
+    } else {
+        let size = first & 0x7f;
+        if size == 0 || size > 4 {
+            return None;
+        }
+        let length = data.get(2..2 + size)?.iter().fold(0usize, |length, x| (length << 8) | *x as usize);
+        (length, 2 + size)
+    };
+    let end = header.checked_add(length)?;
+    Some((data.get(header..end)?, &data[end..]))
+}
+
+//Time of a certificate in s since UNIX_EPOCH => UTCTime YYMMDDHHMMSSZ (tag 0x17) or GeneralizedTime YYYYMMDDHHMMSSZ (tag 0x18)
+fn der_time(tag: u8, time: &str) -> Option<u64> {
+    let time = time.strip_suffix('Z')?;
+    let (year, rest) = match (tag, time.len()) {
+        //RFC 5280 => two digit years are 1950 to 2049
+        (0x17, 12) => {
+            let year: u64 = time.get(..2)?.parse().ok()?;
+            (if year >= 50 { 1900 + year } else { 2000 + year }, &time[2..])
+        }
+        (0x18, 14) => (time.get(..4)?.parse().ok()?, &time[4..]),
+        _ => return None,
+    };
+    if !rest.bytes().all(|x| x.is_ascii_digit()) {
+        return None;
+    }
+
+    let field = |i: usize| rest[i..i + 2].parse::<u64>().ok();
+    let (month, day, hour, minute, second) = (field(0)?, field(2)?, field(4)?, field(6)?, field(8)?);
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86_400 + hour * 3_600 + minute * 60 + second)
+}
+
+//Number of days since 1970-01-01 of a date of the Gregorian calendar, from 1970
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    //The year starts in March => the leap day is the last day of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+//Date of a time in s since UNIX_EPOCH => YYYY-MM-DD in UTC
+fn format_date(time: u64) -> String {
+    let days = time / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Self-signed certificate of rndiag.test made by openssl => notAfter=Oct 15 12:04:07 2036 GMT
+    const CERTIFICATE: [&str; 9] = [
+        "3082019f30820145a00302010202144725d27fecbedf013af35853337286ad81427319300a06082a8648ce3d04030230",
+        "163114301206035504030c0b726e646961672e74657374301e170d3236313031383132303430375a170d333631303135",
+        "3132303430375a30163114301206035504030c0b726e646961672e746573743059301306072a8648ce3d020106082a86",
+        "48ce3d03010703420004cc41a9950f257fd6c812f2ec81bc1c7149df9ac3def076fd2d70eac44dd3770fe74c8d90a242",
+        "5ea5a4b787c8d312f78868908c425515b05d4f5d9076a962033ca371306f301d0603551d0e041604146ed4cd0bd582c8",
+        "c276607ea91b3dd0fddc483ef4301f0603551d230418301680146ed4cd0bd582c8c276607ea91b3dd0fddc483ef4300f",
+        "0603551d130101ff040530030101ff301c0603551d1104153013820b726e646961672e7465737487040a630001300a06",
+        "082a8648ce3d0403020348003045022100ca5d3e9d52b55d0d9cc7a3c27932757965987c02af9e5ce0fdffc5aac92650",
+        "c60220142e4a9d0741f7fb10b6286956aa8f4540e842414eb66c58c0cbb34547b256fe",
+    ];
+
+    fn certificate() -> Vec<u8> {
+        let hex = CERTIFICATE.concat();
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn expiry_is_the_not_after_of_the_certificate() {
+        assert_eq!(certificate_expiry(&certificate()), Some(2_107_685_047));
+        assert_eq!(format_date(2_107_685_047), "2036-10-15");
+    }
+
+    #[test]
+    fn truncated_certificate_has_no_expiry() {
+        let der = certificate();
+        assert_eq!(certificate_expiry(&der[..100]), None);
+        assert_eq!(certificate_expiry(&[]), None);
+    }
+
+    #[test]
+    fn utc_and_generalized_times() {
+        assert_eq!(der_time(0x17, "700101000000Z"), Some(0));
+        assert_eq!(der_time(0x17, "491231235959Z"), Some(2_524_607_999));
+        assert_eq!(der_time(0x18, "20360229120000Z"), Some(2_087_899_200));
+        assert_eq!(der_time(0x17, "20360229120000Z"), None);
+        assert_eq!(der_time(0x18, "20361301000000Z"), None);
+        assert_eq!(der_time(0x17, "7001010000+0Z"), None);
+    }
+
+    #[test]
+    fn dates_around_leap_days() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(2_087_899_200), "2036-02-29");
+        assert_eq!(format_date(days_from_civil(2100, 3, 1) * 86_400), "2100-03-01");
+        assert_eq!(days_from_civil(2000, 3, 1) - days_from_civil(2000, 2, 28), 2);
+    }
+}
@@ -1,851 +1,744 @@
-use anyhow::Result;
 use csv::Writer;
 use dns_lookup::lookup_host;
+use std::fmt;
 use std::fs::File;
 use std::io::{self};
 use std::net::IpAddr;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::error::{Error, Result};
+use crate::icmp::IcmpError;
+use crate::store::ProbeStore;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::sleep_until;
+
+//Columns of the csv export of a latency tool
+pub const CSV_COLUMNS: [&str; 8] = ["Date", "Elapsed", "Ping Number", "Latency", "Status", "TTL", "Initial TTL", "Hops"];
+
+//Default time between the start of two probes
+pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(500);
+
+//Default time to wait for a reply before considering the probe as a timeout
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(5000);
+
+//Timing options of a latency tool => interval between probes, timeout of each probe and total deadline of the run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeOptions {
+    pub interval: Duration, //Time between the start of two probes
+    pub timeout: Duration, //Time to wait for a reply before recording the probe as a timeout
+    pub deadline: Option<Duration>, //Total run time, the tool stops when it is exceeded even if nb_ping is not reached
+}
 
-//NetworkTool trait => The trait for all networktool (ping, DNS resolving, telnet connection, ...)
-#[allow(async_fn_in_trait)]
-pub trait LatencyTool {
-    fn name(&self) -> &'static str;
-    async fn run(&mut self) -> Result<(), io::Error>;
-    fn data(&self) -> &Vec<u32>; //Get the var content of the current object in the trait
-    fn nb_ping(&self) -> &u32; //Get the var content of the current object in the trait
-    fn sys_time(&self) -> &Vec<u64>; //Get the var content of the current object in the trait
-    fn begin_time(&self) -> &u64; //Get the var content of the current object in the trait
-    fn elapsed_time(&mut self) -> &mut u64; //Get the var content of the current object in the trait
-    fn latency_time(&mut self) -> &mut Vec<u64>; //Get the var content of the current object in the trait
-    fn latency_min(&mut self) -> &mut Vec<u32>; //Get the var content of the current object in the trait
-    fn latency_moy(&mut self) -> &mut Vec<u32>; //Get the var content of the current object in the trait
-    fn latency_max(&mut self) -> &mut Vec<u32>; //Get the var content of the current object in the trait
-    fn latency_min_sampled(&mut self) -> &mut Vec<u64>; //Get the var content of the current object in the trait
-    fn latency_moy_sampled(&mut self) -> &mut Vec<u64>; //Get the var content of the current object in the trait
-    fn latency_max_sampled(&mut self) -> &mut Vec<u64>; //Get the var content of the current object in the trait
-    fn output(&self) -> &str;
-    fn target(&self) -> &str;
-
-    //Function to calculate stats about the ping, dns resolver, ... => min/avg/max + % of packet loss when we quit the program
-    fn latency_data(&mut self) {
-        //Get the vecsize to know the number of ping and number of related lantencies values
-        let vec_size = self.sys_time().len();
-
-        //Calculate the min and max latency on all executed ping
-        let max_latency = *self.data().iter().max().unwrap();
-        let min_latency = *self.data().iter().min().unwrap();
-
-        //Get the sum to calcul the moy latency on all executed ping
-        let sum_latency: u64 = self.data().iter().map(|x| *x as u64).sum();
-        let moy_latency = sum_latency as u64 / vec_size as u64;
-
-        //Calculate the number of no received packets
-        //5000ms for a packet is considered as timeout and a no received packet
-        let zero: u32 = self.data().iter().filter(|&&x| x == 5000).count() as u32;
-        let received = vec_size as u32 - zero;
-
-        //Calculate the % of packet loss
-        let packet_loss_percent: f64 =
-            ((vec_size as f64 - received as f64) / vec_size as f64) * 100.0;
-
-        //Display results
-        println!("--- Statistics ---\n");
-        println!(
-            "{} packet transmitted, {} packet received, {:.2}% packet loss\n",
-            vec_size, received, packet_loss_percent
-        );
-        println!(
-            "round-trip min/avg/max = {}/{}/{} ms\n",
-            min_latency, moy_latency, max_latency
-        );
-    }
-
-    //Function for sampling data depending of the elapsed_time and the scale sampling
-    fn sampling(&mut self, mut j: usize, scale: u16) -> usize {
-        //Get the number of ping by the size of sys_time. To remind, each ping have a timestamps related value. So sys_time number values ping number values
-        let mut vec_size = self.sys_time().len();
-
-        //used to determine the number of ping that are in the interval time defined by the sampling scale
-        let mut i = 0;
-
-        //To have the number of elements of Max,moy,min after the for boucle
-        let mut l: usize = 0;
-
-        //Index used to put the system timestamp each time we do the pre-sampling
-        let mut n: usize = 0;
-
-        //Get and update the elapsed_time
-        *self.elapsed_time() = self.sys_time()[vec_size - 1] - self.sys_time()[0];
-
-        //Create temporary vec for pre-sampling
-        let mut vec_max_temp = Vec::new();
-        let mut vec_moy_temp = Vec::new();
-        let mut vec_min_temp = Vec::new();
-        let mut vec_time_temp: Vec<u64> = Vec::new();
-
-        //Index used for the pre-sampling. Pre-sampling => max,min,moy,... latency calculation for each 5 pings. k is the index to determine when we have 5 ping and do pre-sampling
-        let mut k = 0;
-
-        //Temporary vec that contain pings latency values result for pre-sampling
-        let mut vec_data_temp: Vec<u32> = Vec::new();
-
-        //Each time we change the scale, the scale value = 0 and fix the vec_size to 0 to pre-sampling again all values for the new scale.
-        if scale == 0 {
-            vec_size = 0;
+impl Default for ProbeOptions {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+            deadline: None,
         }
+    }
+}
 
-        if let Some(start) = vec_size.checked_sub(scale as usize) {
-            //Loop for pre-sampling data. Pre-sampling => max,min,moy,... latency calculation for each 5 pings
-            for elem in &self.data()[start..] {
-                //Push elem for pre-sampling while k <= 4 in vec_data_temp
-                if k <= 4 {
-                    vec_data_temp.push(*elem);
-                    k += 1;
-                }
-                //When k == 4 its mean we have 5 pings and need to pre-sample it.
-                if k == 4 {
-                    //Get max,moy,min latency for each batch of 5 pings latency result => The pre-sampling
-                    let max_latency: u32 = *vec_data_temp.iter().max().unwrap();
-                    let min_latency = *vec_data_temp.iter().min().unwrap();
-                    let sum_latency: u32 = vec_data_temp.iter().sum();
-                    let moy_latency: u32 = sum_latency / k;
-
-                    //Push pre-sampled (so max,moy,min latency value of 5 pings) values in temporary vecs
-                    vec_max_temp.push(max_latency);
-                    vec_min_temp.push(min_latency);
-                    vec_moy_temp.push(moy_latency);
-                    vec_time_temp.push(self.sys_time()[n]);
-                    //Clear vec for push next 5 pings latencies values for next loop trips and next pre-sampling calculation.
-                    vec_data_temp.clear();
-
-                    k = 0;
-
-                    //Increase the l value to know the number of elem in temporary max/moy/min vec
-                    l += 1;
-                }
-                n += 1;
-            }
+//Timestamps of a probe => wall-clock time in ms since UNIX_EPOCH + monotonic time elapsed since the first probe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeTime {
+    pub wall_ms: u64, //Wall-clock timestamp in ms => used for the date in CSV
+    pub mono: Duration, //Monotonic elapsed time since the begin of the run => used for sampling windows
+}
 
-            //Index of below while loop
-            let mut m: usize = 0;
+//Reason of a failed probe
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProbeError {
+    Unreachable, //Network or host unreachable
+    Refused, //Connection refused by the target
+    Reset, //Connection reset by the target or a middlebox
+    DnsFailure, //Name resolution failed
+    PermissionDenied, //Not enough privileges to send the probe
+    Icmp(IcmpError), //ICMP or ICMPv6 error sent by a router or the target about the probe
+    Other(String), //Any other error with its description
+}
 
-            //Push stored pre-sampled values in temporary vectors to current object attribute vec
-            while m < l {
-                self.latency_max().push(vec_max_temp[m]);
-                self.latency_min().push(vec_min_temp[m]);
-                self.latency_moy().push(vec_moy_temp[m]);
-                self.latency_time().push(vec_time_temp[m]);
-                m += 1;
-            }
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeError::Unreachable => write!(f, "unreachable"),
+            ProbeError::Refused => write!(f, "refused"),
+            ProbeError::Reset => write!(f, "reset"),
+            ProbeError::DnsFailure => write!(f, "dns failure"),
+            ProbeError::PermissionDenied => write!(f, "permission denied"),
+            ProbeError::Icmp(e) => write!(f, "{}", e),
+            ProbeError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
 
-            //Clear pre-sampled vec to free memory
-            vec_max_temp.clear();
-            vec_moy_temp.clear();
-            vec_min_temp.clear();
-            vec_time_temp.clear();
-
-            //Sampling part => We sampling pre-sampled latencies values
-            //Depending of elapsed time (5min, 1h, 2h, ...) we pass in a condition or in other one
-            if *self.elapsed_time() <= 300 {
-                //Main loop for sampling
-                while i < l {
-                    //The starting timestamp ping for the sampling window.
-                    //Take the j value in latency-time +5 => the scale value.
-                    let start_sampling_time = self.latency_time()[j] + 5;
-
-                    //Boucle for to determine with i index the number of pings that can be in the window of the current scale for sampling with timestamp
-                    for elem in self.latency_time() {
-                        if start_sampling_time as i64 - *elem as i64 <= 5
-                            || start_sampling_time as i64 - *elem as i64 == 0
-                        {
-                            i += 1;
-                        }
-                        //Exceed the timestap in the window of sampling => exit the loop
-                        else {
-                            break;
-                        }
-                    }
-
-                    //Create interval with i and j to do a moy with the correct value
-                    let interval = i - j;
-
-                    //j is the begin of the window sampling and i the end of this window so we create an interval of data latencies ping to take for sampling with j and i
-                    //Do the sampling, also interval between i and j = scale value. 5 pings for 5 min scale, 15 pings for 30 min scale, 30 pings for 1h scale, ...
-                    let max_latency_sample = *self.latency_max()[j..i].iter().max().unwrap();
-                    let min_latency_sample = *self.latency_min()[j..i].iter().min().unwrap();
-                    let sum_latency_sample: u32 = self.latency_moy()[j..i].iter().sum();
-                    let moy_latency_sample = sum_latency_sample / interval as u32;
-
-                    //Push sampled data in the current object attributes.
-                    self.latency_max_sampled().push(max_latency_sample as u64);
-                    self.latency_moy_sampled().push(moy_latency_sample as u64);
-                    self.latency_min_sampled().push(min_latency_sample as u64);
-
-                    //For the next sampling, the window of sampling will change, so the end of the current window is the begining of the next window
-                    j = i;
-                }
-            } else if *self.elapsed_time() <= 1800 {
-                while i < l {
-                    let start_sampling_time = self.latency_time()[j] + 15;
-                    for elem in self.latency_time() {
-                        if start_sampling_time as i64 - *elem as i64 <= 15
-                            || start_sampling_time as i64 - *elem as i64 == 0
-                        {
-                            i += 1;
-                        } else {
-                            break;
-                        }
-                    }
-
-                    let interval = i - j;
-
-                    //When the scale just change we resample again all current data with the new scale of sampling => Avoid loosing data for graph
-                    if j == 0 {
-                        //Take number of latencies values to resample it entirely with the new scale of sampling
-                        let len = self.data().len();
-                        //Index used to browse all current data (ping latencies values)
-                        let mut o: usize = 0;
-
-                        //Loop to resampling all current data
-                        // len - 1 to not exceed the index in self.data()
-                        while o < len - 1 {
-                            //Index used to push a specific amount of data according to the value of scale
-                            let mut n: usize = 0;
-
-                            //Temporary vec for sampling
-                            let mut elem_temp: Vec<u32> = Vec::new();
-                            //Loop to push data in elem_temp while n < to the value scale for a sampling after
-                            while n < 15 {
-                                elem_temp.push(self.data()[o]);
-                                n += 1;
-
-                                //Detect if we are at the end of the vec that contain all ping datas. if it is the case we break this loop and because the main while is o < len -1 we quit also the main while loop
-                                if o < len - 1 {
-                                    o += 1;
-                                } else {
-                                    break;
-                                }
-                            }
-                            //Sampling all ping values scale by scale (elem numbers elem_temp = scale value)
-                            let max_latency_sample = elem_temp.iter().max().unwrap();
-                            let min_latency_sample = elem_temp.iter().min().unwrap();
-                            let sum_latency_sample: u32 = elem_temp.iter().sum();
-                            let moy_latency_sample = sum_latency_sample / 15 as u32;
-
-                            //Push just sampled all curent ping value to the current object attribute
-                            self.latency_max_sampled().push(*max_latency_sample as u64);
-                            self.latency_moy_sampled().push(moy_latency_sample as u64);
-                            self.latency_min_sampled().push(*min_latency_sample as u64);
-                        }
-                    }
-                    //If we not change the scale of sampling => Normal sampling
-                    else {
-                        let max_latency_sample = *self.latency_max()[j..i].iter().max().unwrap();
-                        let min_latency_sample = *self.latency_min()[j..i].iter().min().unwrap();
-                        let sum_latency_sample: u32 = self.latency_moy()[j..i].iter().sum();
-                        let moy_latency_sample = sum_latency_sample / interval as u32;
-
-                        self.latency_max_sampled().push(max_latency_sample as u64);
-                        self.latency_moy_sampled().push(moy_latency_sample as u64);
-                        self.latency_min_sampled().push(min_latency_sample as u64);
-                    }
-                    j = i;
-                }
-            } else if *self.elapsed_time() <= 3600 {
-                while i < l {
-                    let start_sampling_time = self.latency_time()[j] + 30;
-                    for elem in self.latency_time() {
-                        if start_sampling_time as i64 - *elem as i64 <= 30
-                            || start_sampling_time as i64 - *elem as i64 == 0
-                        {
-                            i += 1;
-                        } else {
-                            break;
-                        }
-                    }
-
-                    let interval = i - j;
-
-                    if j == 0 {
-                        let len = self.data().len();
-                        let mut o: usize = 0;
-                        while o < len - 1 {
-                            let mut n: usize = 0;
-                            let mut elem_temp: Vec<u32> = Vec::new();
-                            while n < 30 {
-                                elem_temp.push(self.data()[o]);
-                                n += 1;
-                                if o < len - 1 {
-                                    o += 1;
-                                } else {
-                                    break;
-                                }
-                            }
-
-                            let max_latency_sample = elem_temp.iter().max().unwrap();
-                            let min_latency_sample = elem_temp.iter().min().unwrap();
-                            let sum_latency_sample: u32 = elem_temp.iter().sum();
-                            let moy_latency_sample = sum_latency_sample / 30 as u32;
-
-                            self.latency_max_sampled().push(*max_latency_sample as u64);
-                            self.latency_moy_sampled().push(moy_latency_sample as u64);
-                            self.latency_min_sampled().push(*min_latency_sample as u64);
-                        }
-                    } else {
-                        let max_latency_sample = *self.latency_max()[j..i].iter().max().unwrap();
-                        let min_latency_sample = *self.latency_min()[j..i].iter().min().unwrap();
-                        let sum_latency_sample: u32 = self.latency_moy()[j..i].iter().sum();
-                        let moy_latency_sample = sum_latency_sample / interval as u32;
-
-                        self.latency_max_sampled().push(max_latency_sample as u64);
-                        self.latency_moy_sampled().push(moy_latency_sample as u64);
-                        self.latency_min_sampled().push(min_latency_sample as u64);
-                    }
-                    j = i;
-                }
-            } else if *self.elapsed_time() <= 7200 {
-                while i < l {
-                    let start_sampling_time = self.latency_time()[j] + 60;
-                    for elem in self.latency_time() {
-                        if start_sampling_time as i64 - *elem as i64 <= 60
-                            || start_sampling_time as i64 - *elem as i64 == 0
-                        {
-                            i += 1;
-                        } else {
-                            break;
-                        }
-                    }
-
-                    let interval = i - j;
-
-                    if j == 0 {
-                        let len = self.data().len();
-                        let mut o: usize = 0;
-                        while o < len - 1 {
-                            let mut n: usize = 0;
-                            let mut elem_temp: Vec<u32> = Vec::new();
-                            while n < 60 {
-                                elem_temp.push(self.data()[o]);
-                                n += 1;
-                                if o < len - 1 {
-                                    o += 1;
-                                } else {
-                                    break;
-                                }
-                            }
-
-                            let max_latency_sample = elem_temp.iter().max().unwrap();
-                            let min_latency_sample = elem_temp.iter().min().unwrap();
-                            let sum_latency_sample: u64 = elem_temp.iter().map(|x| *x as u64).sum();
-                            let moy_latency_sample = sum_latency_sample as u64 / 60 as u64;
-
-                            self.latency_max_sampled().push(*max_latency_sample as u64);
-                            self.latency_moy_sampled().push(moy_latency_sample as u64);
-                            self.latency_min_sampled().push(*min_latency_sample as u64);
-                        }
-                    } else {
-                        let max_latency_sample = *self.latency_max()[j..i].iter().max().unwrap();
-                        let min_latency_sample = *self.latency_min()[j..i].iter().min().unwrap();
-                        let sum_latency_sample: u64 =
-                            self.latency_moy()[j..i].iter().map(|x| *x as u64).sum();
-                        let moy_latency_sample = sum_latency_sample as u64 / interval as u64;
-
-                        self.latency_max_sampled().push(max_latency_sample as u64);
-                        self.latency_moy_sampled().push(moy_latency_sample as u64);
-                        self.latency_min_sampled().push(min_latency_sample as u64);
-                    }
-                    j = i;
-                }
-            } else if *self.elapsed_time() <= 14400 {
-                while i < l {
-                    let start_sampling_time = self.latency_time()[j] + 120;
-                    for elem in self.latency_time() {
-                        if start_sampling_time as i64 - *elem as i64 <= 120
-                            || start_sampling_time as i64 - *elem as i64 == 0
-                        {
-                            i += 1;
-                        } else {
-                            break;
-                        }
-                    }
-
-                    let interval = i - j;
-
-                    if j == 0 {
-                        let len = self.data().len();
-                        let mut o: usize = 0;
-                        while o < len - 1 {
-                            let mut n: usize = 0;
-                            let mut elem_temp: Vec<u32> = Vec::new();
-                            while n < 120 {
-                                elem_temp.push(self.data()[o]);
-                                n += 1;
-                                if o < len - 1 {
-                                    o += 1;
-                                } else {
-                                    break;
-                                }
-                            }
-
-                            let max_latency_sample = elem_temp.iter().max().unwrap();
-                            let min_latency_sample = elem_temp.iter().min().unwrap();
-                            let sum_latency_sample: u64 = elem_temp.iter().map(|x| *x as u64).sum();
-                            let moy_latency_sample = sum_latency_sample as u64 / 120 as u64;
-
-                            self.latency_max_sampled().push(*max_latency_sample as u64);
-                            self.latency_moy_sampled().push(moy_latency_sample as u64);
-                            self.latency_min_sampled().push(*min_latency_sample as u64);
-                        }
-                    } else {
-                        let max_latency_sample = *self.latency_max()[j..i].iter().max().unwrap();
-                        let min_latency_sample = *self.latency_min()[j..i].iter().min().unwrap();
-                        let sum_latency_sample: u64 =
-                            self.latency_moy()[j..i].iter().map(|x| *x as u64).sum();
-                        let moy_latency_sample = sum_latency_sample as u64 / interval as u64;
-
-                        self.latency_max_sampled().push(max_latency_sample as u64);
-                        self.latency_moy_sampled().push(moy_latency_sample as u64);
-                        self.latency_min_sampled().push(min_latency_sample as u64);
-                    }
-                    j = i;
-                }
-            } else if *self.elapsed_time() <= 28800 {
-                while i < l {
-                    let start_sampling_time = self.latency_time()[j] + 240;
-                    for elem in self.latency_time() {
-                        if start_sampling_time as i64 - *elem as i64 <= 240
-                            || start_sampling_time as i64 - *elem as i64 == 0
-                        {
-                            i += 1;
-                        } else {
-                            break;
-                        }
-                    }
-
-                    let interval = i - j;
-
-                    if j == 0 {
-                        let len = self.data().len();
-                        let mut o: usize = 0;
-                        while o < len - 1 {
-                            let mut n: usize = 0;
-                            let mut elem_temp: Vec<u32> = Vec::new();
-                            while n < 240 {
-                                elem_temp.push(self.data()[o]);
-                                n += 1;
-                                if o < len - 1 {
-                                    o += 1;
-                                } else {
-                                    break;
-                                }
-                            }
-
-                            let max_latency_sample = elem_temp.iter().max().unwrap();
-                            let min_latency_sample = elem_temp.iter().min().unwrap();
-                            let sum_latency_sample: u64 = elem_temp.iter().map(|x| *x as u64).sum();
-                            let moy_latency_sample = sum_latency_sample as u64 / 240 as u64;
-
-                            self.latency_max_sampled().push(*max_latency_sample as u64);
-                            self.latency_moy_sampled().push(moy_latency_sample as u64);
-                            self.latency_min_sampled().push(*min_latency_sample as u64);
-                        }
-                    } else {
-                        let max_latency_sample = *self.latency_max()[j..i].iter().max().unwrap();
-                        let min_latency_sample = *self.latency_min()[j..i].iter().min().unwrap();
-                        let sum_latency_sample: u64 =
-                            self.latency_moy()[j..i].iter().map(|x| *x as u64).sum();
-                        let moy_latency_sample = sum_latency_sample as u64 / interval as u64;
-
-                        self.latency_max_sampled().push(max_latency_sample as u64);
-                        self.latency_moy_sampled().push(moy_latency_sample as u64);
-                        self.latency_min_sampled().push(min_latency_sample as u64);
-                    }
-                    j = i;
-                }
-            } else if *self.elapsed_time() <= 57600 {
-                while i < l {
-                    let start_sampling_time = self.latency_time()[j] + 480;
-                    for elem in self.latency_time() {
-                        if start_sampling_time as i64 - *elem as i64 <= 480
-                            || start_sampling_time as i64 - *elem as i64 == 0
-                        {
-                            i += 1;
-                        } else {
-                            break;
-                        }
-                    }
-
-                    let interval = i - j;
-
-                    if j == 0 {
-                        let len = self.data().len();
-                        let mut o: usize = 0;
-                        while o < len - 1 {
-                            let mut n: usize = 0;
-                            let mut elem_temp: Vec<u32> = Vec::new();
-                            while n < 480 {
-                                elem_temp.push(self.data()[o]);
-                                n += 1;
-                                if o < len - 1 {
-                                    o += 1;
-                                } else {
-                                    break;
-                                }
-                            }
-
-                            let max_latency_sample = elem_temp.iter().max().unwrap();
-                            let min_latency_sample = elem_temp.iter().min().unwrap();
-                            let sum_latency_sample: u64 = elem_temp.iter().map(|x| *x as u64).sum();
-                            let moy_latency_sample = sum_latency_sample as u64 / 480 as u64;
-
-                            self.latency_max_sampled().push(*max_latency_sample as u64);
-                            self.latency_moy_sampled().push(moy_latency_sample as u64);
-                            self.latency_min_sampled().push(*min_latency_sample as u64);
-                        }
-                    } else {
-                        let max_latency_sample = *self.latency_max()[j..i].iter().max().unwrap();
-                        let min_latency_sample = *self.latency_min()[j..i].iter().min().unwrap();
-                        let sum_latency_sample: u64 =
-                            self.latency_moy()[j..i].iter().map(|x| *x as u64).sum();
-                        let moy_latency_sample = sum_latency_sample as u64 / interval as u64;
-
-                        self.latency_max_sampled().push(max_latency_sample as u64);
-                        self.latency_moy_sampled().push(moy_latency_sample as u64);
-                        self.latency_min_sampled().push(min_latency_sample as u64);
-                    }
-                    j = i;
-                }
-            } else if *self.elapsed_time() <= 115200 {
-                while i < l {
-                    let start_sampling_time = self.latency_time()[j] + 960;
-                    for elem in self.latency_time() {
-                        if start_sampling_time as i64 - *elem as i64 <= 960
-                            || start_sampling_time as i64 - *elem as i64 == 0
-                        {
-                            i += 1;
-                        } else {
-                            break;
-                        }
-                    }
-
-                    let interval = i - j;
-
-                    if j == 0 {
-                        let len = self.data().len();
-                        let mut o: usize = 0;
-                        while o < len - 1 {
-                            let mut n: usize = 0;
-                            let mut elem_temp: Vec<u32> = Vec::new();
-                            while n < 960 {
-                                elem_temp.push(self.data()[o]);
-                                n += 1;
-                                if o < len - 1 {
-                                    o += 1;
-                                } else {
-                                    break;
-                                }
-                            }
-
-                            let max_latency_sample = elem_temp.iter().max().unwrap();
-                            let min_latency_sample = elem_temp.iter().min().unwrap();
-                            let sum_latency_sample: u64 = elem_temp.iter().map(|x| *x as u64).sum();
-                            let moy_latency_sample = sum_latency_sample as u64 / 960 as u64;
-
-                            self.latency_max_sampled().push(*max_latency_sample as u64);
-                            self.latency_moy_sampled().push(moy_latency_sample as u64);
-                            self.latency_min_sampled().push(*min_latency_sample as u64);
-                        }
-                    } else {
-                        let max_latency_sample = *self.latency_max()[j..i].iter().max().unwrap();
-                        let min_latency_sample = *self.latency_min()[j..i].iter().min().unwrap();
-                        let sum_latency_sample: u64 =
-                            self.latency_moy()[j..i].iter().map(|x| *x as u64).sum();
-                        let moy_latency_sample = sum_latency_sample as u64 / interval as u64;
-
-                        self.latency_max_sampled().push(max_latency_sample as u64);
-                        self.latency_moy_sampled().push(moy_latency_sample as u64);
-                        self.latency_min_sampled().push(min_latency_sample as u64);
-                    }
-                    j = i;
-                }
-            } else if *self.elapsed_time() <= 230400 {
-                while i < l {
-                    let start_sampling_time = self.latency_time()[j] + 1920;
-                    for elem in self.latency_time() {
-                        if start_sampling_time as i64 - *elem as i64 <= 1920
-                            || start_sampling_time as i64 - *elem as i64 == 0
-                        {
-                            i += 1;
-                        } else {
-                            break;
-                        }
-                    }
-
-                    let interval = i - j;
-
-                    if j == 0 {
-                        let len = self.data().len();
-                        let mut o: usize = 0;
-                        while o < len - 1 {
-                            let mut n: usize = 0;
-                            let mut elem_temp: Vec<u32> = Vec::new();
-                            while n < 1920 {
-                                elem_temp.push(self.data()[o]);
-                                n += 1;
-                                if o < len - 1 {
-                                    o += 1;
-                                } else {
-                                    break;
-                                }
-                            }
-
-                            let max_latency_sample = elem_temp.iter().max().unwrap();
-                            let min_latency_sample = elem_temp.iter().min().unwrap();
-                            let sum_latency_sample: u64 = elem_temp.iter().map(|x| *x as u64).sum();
-                            let moy_latency_sample = sum_latency_sample as u64 / 1920 as u64;
-
-                            self.latency_max_sampled().push(*max_latency_sample as u64);
-                            self.latency_moy_sampled().push(moy_latency_sample as u64);
-                            self.latency_min_sampled().push(*min_latency_sample as u64);
-                        }
-                    }
-
-                    let max_latency_sample = *self.latency_max()[j..i].iter().max().unwrap();
-                    let min_latency_sample = *self.latency_min()[j..i].iter().min().unwrap();
-                    let sum_latency_sample: u64 =
-                        self.latency_moy()[j..i].iter().map(|x| *x as u64).sum();
-                    let moy_latency_sample = sum_latency_sample as u64 / interval as u64;
-
-                    self.latency_max_sampled().push(max_latency_sample as u64);
-                    self.latency_moy_sampled().push(moy_latency_sample as u64);
-                    self.latency_min_sampled().push(min_latency_sample as u64);
-                    j = i;
-                }
-            } else if *self.elapsed_time() <= 460800 {
-                while i < l {
-                    let start_sampling_time = self.latency_time()[j] + 3840;
-                    for elem in self.latency_time() {
-                        if start_sampling_time as i64 - *elem as i64 <= 3840
-                            || start_sampling_time as i64 - *elem as i64 == 0
-                        {
-                            i += 1;
-                        } else {
-                            break;
-                        }
-                    }
-
-                    let interval = i - j;
-                    if j == 0 {
-                        let len = self.data().len();
-                        let mut o: usize = 0;
-                        while o < len - 1 {
-                            let mut n: usize = 0;
-                            let mut elem_temp: Vec<u32> = Vec::new();
-                            while n < 3840 {
-                                elem_temp.push(self.data()[o]);
-                                n += 1;
-                                if o < len - 1 {
-                                    o += 1;
-                                } else {
-                                    break;
-                                }
-                            }
-
-                            let max_latency_sample = elem_temp.iter().max().unwrap();
-                            let min_latency_sample = elem_temp.iter().min().unwrap();
-                            let sum_latency_sample: u64 = elem_temp.iter().map(|x| *x as u64).sum();
-                            let moy_latency_sample = sum_latency_sample as u64 / 3840 as u64;
-
-                            self.latency_max_sampled().push(*max_latency_sample as u64);
-                            self.latency_moy_sampled().push(moy_latency_sample as u64);
-                            self.latency_min_sampled().push(*min_latency_sample as u64);
-                        }
-                    } else {
-                        let max_latency_sample = *self.latency_max()[j..i].iter().max().unwrap();
-                        let min_latency_sample = *self.latency_min()[j..i].iter().min().unwrap();
-                        let sum_latency_sample: u64 =
-                            self.latency_moy()[j..i].iter().map(|x| *x as u64).sum();
-                        let moy_latency_sample = sum_latency_sample as u64 / interval as u64;
-
-                        self.latency_max_sampled().push(max_latency_sample as u64);
-                        self.latency_moy_sampled().push(moy_latency_sample as u64);
-                        self.latency_min_sampled().push(min_latency_sample as u64);
-                    }
-                    j = i;
-                }
-            } else if *self.elapsed_time() <= 921600 {
-                while i < l {
-                    let start_sampling_time = self.latency_time()[j] + 7680;
-                    for elem in self.latency_time() {
-                        if start_sampling_time as i64 - *elem as i64 <= 7680
-                            || start_sampling_time as i64 - *elem as i64 == 0
-                        {
-                            i += 1;
-                        } else {
-                            break;
-                        }
-                    }
-
-                    let interval = i - j;
-
-                    if j == 0 {
-                        let len = self.data().len();
-                        let mut o: usize = 0;
-                        while o < len - 1 {
-                            let mut n: usize = 0;
-                            let mut elem_temp: Vec<u32> = Vec::new();
-                            while n < 7680 {
-                                elem_temp.push(self.data()[o]);
-                                n += 1;
-                                if o < len - 1 {
-                                    o += 1;
-                                } else {
-                                    break;
-                                }
-                            }
-
-                            let max_latency_sample = elem_temp.iter().max().unwrap();
-                            let min_latency_sample = elem_temp.iter().min().unwrap();
-                            let sum_latency_sample: u64 = elem_temp.iter().map(|x| *x as u64).sum();
-                            let moy_latency_sample = sum_latency_sample as u64 / 7680 as u64;
-
-                            self.latency_max_sampled().push(*max_latency_sample as u64);
-                            self.latency_moy_sampled().push(moy_latency_sample as u64);
-                            self.latency_min_sampled().push(*min_latency_sample as u64);
-                        }
-                    } else {
-                        let max_latency_sample = *self.latency_max()[j..i].iter().max().unwrap();
-                        let min_latency_sample = *self.latency_min()[j..i].iter().min().unwrap();
-                        let sum_latency_sample: u64 =
-                            self.latency_moy()[j..i].iter().map(|x| *x as u64).sum();
-                        let moy_latency_sample = sum_latency_sample as u64 / interval as u64;
-
-                        self.latency_max_sampled().push(max_latency_sample as u64);
-                        self.latency_moy_sampled().push(moy_latency_sample as u64);
-                        self.latency_min_sampled().push(min_latency_sample as u64);
-                    }
-                    j = i;
-                }
-            } else if *self.elapsed_time() <= 1843200 {
-                while i < l {
-                    let start_sampling_time = self.latency_time()[j] + 15360;
-                    for elem in self.latency_time() {
-                        if start_sampling_time as i64 - *elem as i64 <= 15360
-                            || start_sampling_time as i64 - *elem as i64 == 0
-                        {
-                            i += 1;
-                        } else {
-                            break;
-                        }
-                    }
-
-                    let interval = i - j;
-
-                    if j == 0 {
-                        let len = self.data().len();
-                        let mut o: usize = 0;
-                        while o < len - 1 {
-                            let mut n: usize = 0;
-                            let mut elem_temp: Vec<u32> = Vec::new();
-                            while n < 15360 {
-                                elem_temp.push(self.data()[o]);
-                                n += 1;
-                                if o < len - 1 {
-                                    o += 1;
-                                } else {
-                                    break;
-                                }
-                            }
-
-                            let max_latency_sample = elem_temp.iter().max().unwrap();
-                            let min_latency_sample = elem_temp.iter().min().unwrap();
-                            let sum_latency_sample: u64 = elem_temp.iter().map(|x| *x as u64).sum();
-                            let moy_latency_sample = sum_latency_sample as u64 / 15360 as u64;
-
-                            self.latency_max_sampled().push(*max_latency_sample as u64);
-                            self.latency_moy_sampled().push(moy_latency_sample as u64);
-                            self.latency_min_sampled().push(*min_latency_sample as u64);
-                        }
-                    } else {
-                        let max_latency_sample = *self.latency_max()[j..i].iter().max().unwrap();
-                        let min_latency_sample = *self.latency_min()[j..i].iter().min().unwrap();
-                        let sum_latency_sample: u64 =
-                            self.latency_moy()[j..i].iter().map(|x| *x as u64).sum();
-                        let moy_latency_sample = sum_latency_sample as u64 / interval as u64;
-
-                        self.latency_max_sampled().push(max_latency_sample as u64);
-                        self.latency_moy_sampled().push(moy_latency_sample as u64);
-                        self.latency_min_sampled().push(min_latency_sample as u64);
-                    }
-                    j = i;
-                }
+//Classify an io error => the reason of the failed probe
+impl From<&io::Error> for ProbeError {
+    fn from(e: &io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NetworkUnreachable | io::ErrorKind::HostUnreachable => ProbeError::Unreachable,
+            io::ErrorKind::ConnectionRefused => ProbeError::Refused,
+            io::ErrorKind::ConnectionReset => ProbeError::Reset,
+            io::ErrorKind::PermissionDenied => ProbeError::PermissionDenied,
+            _ => ProbeError::Other(e.to_string()),
+        }
+    }
+}
+
+//Result of one probe => a reply with its latency, a timeout, or an error with its reason
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProbeResult {
+    Reply(Duration), //Reply received, latency with µs precision
+    Timeout, //No reply before the timeout option
+    Error(ProbeError), //The probe failed
+}
+
+impl ProbeResult {
+    //Return the latency if it is a reply, None for a lost probe
+    pub fn latency(&self) -> Option<Duration> {
+        match self {
+            ProbeResult::Reply(latency) => Some(*latency),
+            _ => None,
+        }
+    }
+
+    //Return true if a reply has been received
+    pub fn is_reply(&self) -> bool {
+        matches!(self, ProbeResult::Reply(_))
+    }
+
+    //Return true if the probe failed with an ICMP error
+    pub fn is_icmp_error(&self) -> bool {
+        matches!(self, ProbeResult::Error(ProbeError::Icmp(_)))
+    }
+
+    //Status of the probe => used in CSV export
+    pub fn status(&self) -> String {
+        match self {
+            ProbeResult::Reply(_) => "reply".to_string(),
+            ProbeResult::Timeout => "timeout".to_string(),
+            ProbeResult::Error(e) => format!("error: {}", e),
+        }
+    }
+}
+
+//Statistics of a run => computed on all probes, latencies are only computed on replies
+//Latencies are None when no reply has been received
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Statistics {
+    pub transmitted: u32, //Number of probes sent
+    pub received: u32, //Number of replies, timeouts and errors are no received packets
+    pub loss_percent: f64, //% of packet loss
+    pub min: Option<Duration>,
+    pub avg: Option<Duration>,
+    pub max: Option<Duration>,
+    pub mdev: Option<Duration>, //Mean absolute deviation of the latencies from the avg
+    pub stddev: Option<Duration>, //Standard deviation of the latencies
+    pub p50: Option<Duration>,
+    pub p90: Option<Duration>,
+    pub p95: Option<Duration>,
+    pub p99: Option<Duration>,
+    pub jitter: Option<Duration>, //Interarrival jitter of RFC 3550 computed on consecutive replies
+    pub duplicates: u32, //Number of replies received again for an already answered probe
+    pub out_of_order: u32, //Number of replies received after the timeout of their probe
+    pub icmp_errors: u32, //Number of probes lost with an ICMP error => unreachable, time exceeded, ...
+    pub route_changes: u32, //Number of changes of the hop count of the replies
+    pub timings: Option<Timings>, //Average time of each phase of the replies, None for the probe types without phases
+    pub cert_expiry: Option<u64>, //End of validity of the certificate of the last TLS reply in s since UNIX_EPOCH
+}
+
+impl Statistics {
+    //Calculate the statistics of a list of probe results
+    pub fn from_results(results: &[ProbeResult]) -> Self {
+        let transmitted = results.len() as u32;
+
+        //Only replies have a latency => timeouts and errors are not in latencies stats
+        let latencies: Vec<Duration> = results.iter().filter_map(|x| x.latency()).collect();
+        let received = latencies.len() as u32;
+
+        let mut stats = Statistics {
+            transmitted,
+            received,
+            icmp_errors: results.iter().filter(|x| x.is_icmp_error()).count() as u32,
+            ..Default::default()
+        };
+
+        //Nothing to calculate if no probe was sent
+        if transmitted == 0 {
+            return stats;
+        }
+        stats.loss_percent = ((transmitted - received) as f64 / transmitted as f64) * 100.0;
+
+        //No reply => no latency stats
+        if latencies.is_empty() {
+            return stats;
+        }
+
+        let mut sorted = latencies.clone();
+        sorted.sort();
+
+        let sum_latency: Duration = latencies.iter().sum();
+        let avg = sum_latency / received;
+
+        //Mean deviation and population standard deviation, calculated in µs to keep the precision
+        let avg_us = avg.as_micros() as f64;
+        let mdev = latencies
+            .iter()
+            .map(|x| (x.as_micros() as f64 - avg_us).abs())
+            .sum::<f64>()
+            / received as f64;
+        let variance = latencies
+            .iter()
+            .map(|x| (x.as_micros() as f64 - avg_us).powi(2))
+            .sum::<f64>()
+            / received as f64;
+
+        //RFC 3550 jitter => J = J + (|D(i-1,i)| - J) / 16, D is the latency difference between two consecutive replies
+        let mut jitter_us = 0.0;
+        for pair in latencies.windows(2) {
+            let d = (pair[1].as_micros() as f64 - pair[0].as_micros() as f64).abs();
+            jitter_us += (d - jitter_us) / 16.0;
+        }
+
+        stats.min = sorted.first().copied();
+        stats.max = sorted.last().copied();
+        stats.avg = Some(avg);
+        stats.mdev = Some(Duration::from_micros(mdev.round() as u64));
+        stats.stddev = Some(Duration::from_micros(variance.sqrt().round() as u64));
+        stats.p50 = Some(percentile(&sorted, 50.0));
+        stats.p90 = Some(percentile(&sorted, 90.0));
+        stats.p95 = Some(percentile(&sorted, 95.0));
+        stats.p99 = Some(percentile(&sorted, 99.0));
+        stats.jitter = Some(Duration::from_micros(jitter_us.round() as u64));
+        stats
+    }
+
+    //Number of lost probes => timeouts + errors
+    pub fn lost(&self) -> u32 {
+        self.transmitted - self.received
+    }
+}
+
+//Display the statistics as the end-of-run summary
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "--- Statistics ---\n")?;
+
+        if self.transmitted == 0 {
+            return writeln!(f, "0 packet transmitted, 0 packet received");
+        }
+
+        write!(
+            f,
+            "{} packet transmitted, {} packet received, {:.2}% packet loss",
+            self.transmitted, self.received, self.loss_percent
+        )?;
+        if self.duplicates > 0 {
+            write!(f, ", +{} duplicates", self.duplicates)?;
+        }
+        if self.out_of_order > 0 {
+            write!(f, ", {} out-of-order", self.out_of_order)?;
+        }
+        if self.icmp_errors > 0 {
+            write!(f, ", {} ICMP errors", self.icmp_errors)?;
+        }
+        if self.route_changes > 0 {
+            write!(f, ", {} route changes", self.route_changes)?;
+        }
+        writeln!(f)?;
+
+        //No reply => no latency to display
+        if let (Some(min), Some(avg), Some(max)) = (self.min, self.avg, self.max) {
+            write!(
+                f,
+                "\nround-trip min/avg/max = {:.3}/{:.3}/{:.3} ms\n",
+                as_ms(min), as_ms(avg), as_ms(max)
+            )?;
+        }
+
+        if let (Some(p50), Some(p90), Some(p95), Some(p99)) = (self.p50, self.p90, self.p95, self.p99) {
+            write!(
+                f,
+                "\npercentiles p50/p90/p95/p99 = {:.3}/{:.3}/{:.3}/{:.3} ms\n",
+                as_ms(p50), as_ms(p90), as_ms(p95), as_ms(p99)
+            )?;
+        }
+
+        if let (Some(mdev), Some(stddev), Some(jitter)) = (self.mdev, self.stddev, self.jitter) {
+            write!(
+                f,
+                "\nmdev/stddev/jitter = {:.3}/{:.3}/{:.3} ms\n",
+                as_ms(mdev), as_ms(stddev), as_ms(jitter)
+            )?;
+        }
+
+        //Only the phases done by the probe type are displayed => "connect/tls = 12.410/45.872 ms"
+        if let Some(timings) = self.timings {
+            let (names, values): (Vec<&str>, Vec<String>) = timings
+                .phases()
+                .into_iter()
+                .filter_map(|(name, phase)| Some((name, format!("{:.3}", as_ms(phase?)))))
+                .unzip();
+            if !names.is_empty() {
+                write!(f, "\n{} = {} ms\n", names.join("/"), values.join("/"))?;
             }
         }
-        j
+        Ok(())
     }
+}
 
-    //Export result in CSV
-    fn export_csv(&mut self) -> Result<(), io::Error> {
-        //Create the output csv file
-        let file_export = File::create(&self.output());
-        let mut i: usize = 0;
+//Events emitted by a latency tool during its run => the front-end decides how to display them
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProbeEvent {
+    Start(String), //Description of the run => target, source, options
+    Probe {
+        seq: u32, //Number of the probe, starts at 0
+        outcome: ProbeOutcome,
+    },
+    RouteChange {
+        seq: u32, //Number of the probe whose reply crossed another number of routers
+        previous: u8, //Hop count of the previous replies
+        hops: u8, //Hop count of this reply
+        ttl: u8, //TTL or hop limit of this reply
+    },
+}
+
+//Commands sent by the front-end to a running latency tool
+#[derive(Debug)]
+pub enum ProbeCommand {
+    Stop, //Stop the run after the current probe
+    Snapshot(usize, oneshot::Sender<SampledSeries>), //Ask the latest sampled series with at most N values => used to display the graph
+}
 
-        //Take the error if we cannot create the output csv file
-        let file = match file_export {
-            Ok(f) => f,
-            Err(e) => {
-                panic!("Creation file error !: {}", e);
+//Sampled series of a latency tool in µs => one value per time bucket
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SampledSeries {
+    pub min: Vec<u64>,
+    pub moy: Vec<u64>,
+    pub max: Vec<u64>,
+    pub p95: Vec<u64>,
+    pub jitter: Vec<u64>,
+}
+
+//Link between a latency tool and its front-end, a tool without link runs silently until its end
+#[derive(Debug, Default)]
+pub struct ProbeLink {
+    events: Option<mpsc::UnboundedSender<ProbeEvent>>,
+    commands: Option<mpsc::UnboundedReceiver<ProbeCommand>>,
+}
+
+impl ProbeLink {
+    pub fn new(
+        events: mpsc::UnboundedSender<ProbeEvent>,
+        commands: mpsc::UnboundedReceiver<ProbeCommand>,
+    ) -> Self {
+        Self {
+            events: Some(events),
+            commands: Some(commands),
+        }
+    }
+
+    //Send an event to the front-end, ignored if nobody listens
+    pub fn emit(&self, event: ProbeEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+
+    //Wait until `until` => return earlier if a command is received
+    pub async fn next_command(&mut self, until: Instant) -> Option<ProbeCommand> {
+        let until = tokio::time::Instant::from_std(until);
+        let mut closed = false;
+
+        if let Some(commands) = self.commands.as_mut() {
+            tokio::select! {
+                command = commands.recv() => match command {
+                    Some(command) => return Some(command),
+                    None => closed = true,
+                },
+                _ = sleep_until(until) => return None,
             }
-        };
+        }
+
+        //The front-end is gone => no more command to wait
+        if closed {
+            self.commands = None;
+        }
+        sleep_until(until).await;
+        None
+    }
+}
+
+//Take one statistic of each time bucket in µs => used for the graph, a bucket without reply has 0 as value
+pub fn sampled_series(buckets: &[Statistics], value: fn(&Statistics) -> Option<Duration>) -> Vec<u64> {
+    buckets
+        .iter()
+        .map(|x| value(x).map_or(0, |v| v.as_micros() as u64))
+        .collect()
+}
+
+//Nearest-rank percentile of sorted latencies
+pub(crate) fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+//Probe trait => a probe type (ping, tcp ping, DNS request, ...) only sends one probe and returns its result
+//Scheduling, storage and statistics are done by ProbeRunner
+//Probes are Send => several targets can be probed concurrently in their own task
+pub trait Probe: Send {
+    fn name(&self) -> &'static str; //Get the name of the probe type
+    fn target(&self) -> &str; //Get the target of the probe
+
+    //Prepare the probes before the first one => resolve the target, open sockets, ...
+    //Return the description of the run for the front-end
+    fn prepare(&mut self) -> impl Future<Output = Result<Option<String>>> + Send {
+        async { Ok(None) }
+    }
+
+    //Send one probe and wait its result until the timeout
+    fn probe(&mut self, seq: u32, timeout: Duration) -> impl Future<Output = ProbeOutcome> + Send;
+
+    //Columns added to the csv export by the probe type, their values are the columns of the ReplyDetail of each probe
+    fn csv_columns(&self) -> &'static [&'static str] {
+        &[]
+    }
+}
+
+//Outcome of one probe => its result + a tool specific detail for the front-end (reply TCP flags, DNS answer, ...)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeOutcome {
+    pub result: ProbeResult,
+    pub info: Option<String>,
+    pub reply: ReplyDetail, //What the probe type knows about the reply => sequence, TTL, size, source
+    pub duplicates: u32, //Replies received again for already answered probes while waiting this one
+    pub out_of_order: u32, //Replies of previous probes received after their timeout while waiting this one
+}
+
+impl ProbeOutcome {
+    pub fn new(result: ProbeResult, info: Option<String>) -> Self {
+        Self {
+            result,
+            info,
+            reply: ReplyDetail::default(),
+            duplicates: 0,
+            out_of_order: 0,
+        }
+    }
+}
+
+impl From<ProbeResult> for ProbeOutcome {
+    fn from(result: ProbeResult) -> Self {
+        Self::new(result, None)
+    }
+}
+
+//Detail of a reply read in the reply packet, None when the probe type cannot read it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplyDetail {
+    pub seq: Option<u16>, //Sequence number of the reply => icmp_seq
+    pub ttl: Option<u8>, //TTL or hop limit of the reply
+    pub bytes: Option<usize>, //Size of the reply => ICMP header + data, body of an HTTP response
+    pub from: Option<IpAddr>, //Source of the reply
+    pub timings: Option<Timings>, //Time of each phase of the probe, None for the probe types without phases
+    pub cert_expiry: Option<u64>, //End of validity of the certificate of a TLS server in s since UNIX_EPOCH
+    pub columns: Vec<String>, //Values of the csv columns of the probe type => Probe::csv_columns
+}
+
+impl ReplyDetail {
+    //Number of routers crossed by the reply, None without TTL
+    pub fn hops(&self) -> Option<u8> {
+        self.ttl.map(hop_count)
+    }
+}
+
+//Time of each phase of a probe since its start like curl -w => the last phase done is the latency of the probe
+//None => the phase was not done
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    pub dns: Option<Duration>, //Name resolved => time_namelookup
+    pub connect: Option<Duration>, //TCP connection established => time_connect
+    pub tls: Option<Duration>, //TLS handshake done => time_appconnect
+    pub ttfb: Option<Duration>, //First byte of the response received => time_starttransfer
+}
 
-        //Create a writer
-        let mut writer = Writer::from_writer(file);
+//Number of phases of Timings
+pub(crate) const PHASES: usize = 4;
 
-        //Write columns title in the output csv file
-        if let Err(e) = writer.write_record(&["Date", "Ping Number", "Latency"]) {
-            eprintln!("Error! : {}", e);
+impl Timings {
+    //Name and time of each phase in the order they are done
+    pub fn phases(&self) -> [(&'static str, Option<Duration>); PHASES] {
+        [("dns", self.dns), ("connect", self.connect), ("tls", self.tls), ("ttfb", self.ttfb)]
+    }
+
+    //Timings from the times of the phases in the order of phases()
+    pub(crate) fn from_phases([dns, connect, tls, ttfb]: [Option<Duration>; PHASES]) -> Self {
+        Self { dns, connect, tls, ttfb }
+    }
+}
+
+//Shared runner of the latency tools => schedules the probes of a Probe type, stores their results and calculates stats
+//Results are kept in a ProbeStore => constant memory for infinite runs
+pub struct ProbeRunner<P: Probe> {
+    probe: P, //Probe type => ping, tcp ping, DNS request, ...
+    output: String, //Output csv filename
+    nb_ping: u32, //Number of probes, 0 => infinite
+    options: ProbeOptions, //Timing options => interval, timeout, deadline
+    store: ProbeStore, //Store the result and the timestamps of each probe => 1 s/1 min/1 h buckets + running totals
+    csv: Option<CsvExport>, //Csv file the probes are written to as they are done, None without export
+    begin_time: u64, //Wall-clock timestamp in ms of the begin of the run
+    begin_instant: Instant, //Monotonic begin of the run
+    hops: Option<u8>, //Hop count of the last reply with a TTL => a change is a change of the route
+    link: ProbeLink, //Link with the front-end => events and commands
+}
+
+impl<P: Probe> ProbeRunner<P> {
+    //Init attributes of the object
+    pub fn new(probe: P, output: &str, nb_ping: u32, options: ProbeOptions) -> Self {
+        Self {
+            probe,
+            output: output.to_string(),
+            nb_ping,
+            options,
+            store: ProbeStore::new(),
+            csv: None,
+            begin_time: 0,
+            begin_instant: Instant::now(),
+            hops: None,
+            link: ProbeLink::default(),
         }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.probe.name()
+    }
+
+    pub fn target(&self) -> &str {
+        self.probe.target()
+    }
+
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn nb_ping(&self) -> u32 {
+        self.nb_ping
+    }
+
+    pub fn options(&self) -> &ProbeOptions {
+        &self.options
+    }
+
+    //Results of the probes => time buckets and running totals
+    pub fn store(&self) -> &ProbeStore {
+        &self.store
+    }
+
+    //Wall-clock timestamp in ms of the begin of the run
+    pub fn begin_time(&self) -> u64 {
+        self.begin_time
+    }
+
+    //Write each probe to a csv file as it is done => set before the run, the file is shared by the runners of several targets
+    pub fn export_to(&mut self, csv: CsvExport) {
+        self.csv = Some(csv);
+    }
+
+    //Connect the current object to a front-end => events are sent to it and commands received from it
+    pub fn attach(&mut self, link: ProbeLink) {
+        self.link = link;
+    }
+
+    //Statistics of the run => count, received, % of packet loss, min/avg/max, stddev, percentiles and jitter
+    pub fn statistics(&self) -> Statistics {
+        self.store.statistics()
+    }
+
+    //Sampled series of the current object with at most max_points values => latest data for the graph
+    pub fn sampled_series(&self, max_points: usize) -> SampledSeries {
+        self.store.sampled_series(max_points)
+    }
+
+    //Run the probes while the number of probes is not reached or in infinity, and while the deadline is not reached
+    pub async fn run(&mut self) -> Result<()> {
+        //Prepare the probes and send the description of the run with the number of probes to the front-end
+        if let Some(description) = self.probe.prepare().await? {
+            self.link.emit(ProbeEvent::Start(format!("{} count={}", description, self.nb_ping)));
+        }
+
+        //used to do probes while i is < nb_ping
+        let mut i: u32 = 0;
+
+        self.begin_time = get_time();
+        self.begin_instant = Instant::now();
 
-        //Get the number of ping values for writing
-        let len = self.sys_time().len();
-
-        //Write ping latencies values in the csv output file.
-        while i < len {
-            let data = self.data()[i];
-            let j = i;
-            let sys_time = self.sys_time()[i];
-            if let Err(e) =
-                writer.write_record(&[sys_time.to_string(), j.to_string(), data.to_string()])
-            {
-                eprintln!("Error writing to CSV! {}", e);
+        while (i < self.nb_ping || self.nb_ping == 0) && !self.deadline_reached() {
+            //Start to count the time
+            let start = Instant::now();
+            //The last probe is not waited beyond the deadline
+            let timeout = self.remaining().map_or(self.options.timeout, |x| x.min(self.options.timeout));
+            let outcome = self.probe.probe(i, timeout).await;
+
+            //Without privileges no probe can be done => stop here
+            if outcome.result == ProbeResult::Error(ProbeError::PermissionDenied) {
+                return Err(Error::PermissionDenied(format!("{} failed: operation not permitted", self.name())));
+            }
+
+            let time = self.probe_time();
+            self.store.push(time, outcome.result.clone(), &outcome.reply);
+            if let Some(csv) = &self.csv {
+                csv.write(self.probe.target(), self.csv_record(i, time, &outcome))?;
+            }
+            self.store.count_unexpected(outcome.duplicates, outcome.out_of_order);
+            let reply_ttl = outcome.reply.ttl;
+            self.link.emit(ProbeEvent::Probe { seq: i, outcome });
+
+            //The replies cross another number of routers => the route toward the target changed, the front-end is alerted
+            if let Some(ttl) = reply_ttl {
+                let hops = hop_count(ttl);
+                if let Some(previous) = self.hops
+                    && previous != hops
+                {
+                    self.store.count_route_change();
+                    self.link.emit(ProbeEvent::RouteChange { seq: i, previous, hops, ttl });
+                }
+                self.hops = Some(hops);
             }
             i += 1;
+
+            //Sleep the rest of the interval option => by default a probe each 500 ms, no sleep after the last one, stop if the front-end asked it
+            if (self.nb_ping == 0 || i < self.nb_ping) && !self.wait_next_probe(start).await {
+                break;
+            }
         }
-        // Flush the writer to ensure all data is written
-        writer.flush().expect("Failed to flush writer");
         Ok(())
     }
 
-    //Little function to get the current system time timestamp
-    fn get_time(&self) -> u64 {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as u64;
-        timestamp
+    //Wait the interval option after a probe started at `start` while handling commands of the front-end, the wait ends at the deadline
+    //Return false if the front-end asked to stop the run
+    async fn wait_next_probe(&mut self, start: Instant) -> bool {
+        let mut until = start + self.options.interval;
+        if let Some(deadline) = self.options.deadline {
+            until = until.min(self.begin_instant + deadline);
+        }
+
+        while let Some(command) = self.link.next_command(until).await {
+            match command {
+                ProbeCommand::Stop => return false,
+                ProbeCommand::Snapshot(max_points, reply) => {
+                    let _ = reply.send(self.sampled_series(max_points));
+                }
+            }
+        }
+        true
     }
 
-    //resolve method
-    //Resolve hostname to get IP when the user give hostname instead of IP
-    fn resolve(&mut self) -> IpAddr {
-        let result_ip = lookup_host(&self.target())
-            .unwrap_or_else(|e| {
-                eprintln!("Error during the name lookup: {}", e);
-                std::process::exit(1);
-            })
-            .collect::<Vec<_>>();
-        result_ip[0]
+    //Columns of the csv export => the ones of a latency tool followed by the ones of the probe type
+    pub fn csv_columns(&self) -> Vec<&'static str> {
+        let mut columns = CSV_COLUMNS.to_vec();
+        columns.extend(self.probe.csv_columns());
+        columns
+    }
+
+    //CSV row of a probe => the columns of csv_columns()
+    //Date => wall-clock timestamp in ms, Elapsed => monotonic time since the first ping in ms, Latency => in ms with µs precision, empty if lost
+    //Status => reply, timeout or error with its reason
+    //TTL => TTL or hop limit of the reply, Initial TTL and Hops => initial value inferred from it and number of routers crossed
+    //The columns of the probe type follow => ex: phases and negotiated session of a TLS handshake
+    fn csv_record(&self, seq: u32, time: ProbeTime, outcome: &ProbeOutcome) -> Vec<String> {
+        let latency = match outcome.result.latency() {
+            Some(latency) => format!("{:.3}", as_ms(latency)),
+            None => String::new(),
+        };
+        let ttl = |value: fn(u8) -> u8| outcome.reply.ttl.map_or(String::new(), |x| value(x).to_string());
+        let mut record = vec![
+            time.wall_ms.to_string(),
+            format!("{:.3}", as_ms(time.mono)),
+            seq.to_string(),
+            latency,
+            outcome.result.status(),
+            ttl(|x| x),
+            ttl(initial_ttl),
+            ttl(hop_count),
+        ];
+
+        //A probe without reply detail has empty values in the columns of the probe type
+        record.extend(outcome.reply.columns.iter().cloned());
+        record.resize(CSV_COLUMNS.len() + self.probe.csv_columns().len(), String::new());
+        record
     }
+
+    //Return true when the deadline option is defined and exceeded since the begin of the run
+    fn deadline_reached(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+
+    //Time left before the deadline option since the begin of the run, None without deadline
+    fn remaining(&self) -> Option<Duration> {
+        self.options.deadline.map(|x| x.saturating_sub(self.begin_instant.elapsed()))
+    }
+
+    //Get both timestamps of a probe => wall-clock time + monotonic time since begin_instant
+    fn probe_time(&self) -> ProbeTime {
+        ProbeTime {
+            wall_ms: get_time(),
+            mono: self.begin_instant.elapsed(),
+        }
+    }
+}
+
+//Csv file the probes are written to as they are done => nothing is lost and the memory stays constant whatever the run time
+//Cloned to the runners of several targets, each row then starts with the target of the probe
+#[derive(Clone)]
+pub struct CsvExport {
+    path: String,
+    writer: Arc<Mutex<Writer<File>>>,
+    target: bool, //True when the rows start with the target
+}
+
+impl CsvExport {
+    //Create the csv file and write the columns title, target => the first column is the target of the probe
+    pub fn create(path: &str, columns: &[&str], target: bool) -> Result<Self> {
+        let export_error = |source: io::Error| Error::Export { path: path.to_string(), source };
+        let mut writer = Writer::from_writer(File::create(path).map_err(export_error)?);
+        let title = target.then_some("Target").into_iter().chain(columns.iter().copied());
+        writer.write_record(title).map_err(|e| export_error(e.into()))?;
+        writer.flush().map_err(export_error)?;
+        Ok(Self { path: path.to_string(), writer: Arc::new(Mutex::new(writer)), target })
+    }
+
+    //Write the row of one probe => flushed at once, the file is complete even if rndiag is killed
+    fn write(&self, target: &str, record: Vec<String>) -> Result<()> {
+        let export_error = |source: io::Error| Error::Export { path: self.path.clone(), source };
+        //A runner that panicked while writing left a complete row or nothing => the writer is still usable
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let target = self.target.then_some(target).into_iter();
+        writer
+            .write_record(target.chain(record.iter().map(String::as_str)))
+            .map_err(|e| export_error(e.into()))?;
+        writer.flush().map_err(export_error)
+    }
+}
+
+//Little function to get the current system time timestamp in ms
+pub fn get_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+//Resolve the target => parse it if it is an IP, else resolve the hostname and take the first IP
+pub fn resolve_target(target: &str) -> Result<IpAddr> {
+    if let Ok(ip) = target.parse::<IpAddr>() {
+        return Ok(ip);
+    }
+
+    let resolve_error = |reason: String| Error::Resolve { target: target.to_string(), reason };
+    lookup_host(target)
+        .map_err(|e| resolve_error(e.to_string()))?
+        .next()
+        .ok_or_else(|| resolve_error(String::from("no address found")))
 }
 
 #[allow(async_fn_in_trait)]
@@ -853,19 +746,62 @@ pub trait LatencyTool {
 pub trait ConnectTool {
     fn name(&self) -> &'static str; //Get the name of the object
     fn srv_addr(&self) -> &str; //Get srv_addr object attribute
-    async fn run(&mut self) -> std::io::Result<()>; //Run method object
-    async fn start_server(&mut self) -> std::io::Result<()>; //Start_server method => Launch server part and handle connections
-    async fn client(&mut self) -> std::io::Result<()>; //client method => Launch client part
+    async fn run(&mut self) -> Result<()>; //Run method object
+    async fn start_server(&mut self) -> Result<()>; //Start_server method => Launch server part and handle connections
+    async fn client(&mut self) -> Result<()>; //client method => Launch client part
 
     //resolve method
     //Resolve hostname to get IP when the user give hostname instead of IP
-    fn resolve(&mut self) -> IpAddr {
-        let result_ip = lookup_host(&self.srv_addr())
-            .unwrap_or_else(|e| {
-                eprintln!("Error during the name lookup: {}", e);
-                std::process::exit(1);
-            })
-            .collect::<Vec<_>>();
-        result_ip[0]
+    fn resolve(&mut self) -> Result<IpAddr> {
+        resolve_target(self.srv_addr())
+    }
+}
+
+
+//Convert a duration in ms with µs precision => used to display and export latencies
+pub fn as_ms(latency: Duration) -> f64 {
+    latency.as_micros() as f64 / 1000.0
+}
+
+//Usual initial TTL or hop limit of the IP stacks => 64 Linux/BSD/macOS, 128 Windows, 255 network devices
+const INITIAL_TTLS: [u8; 3] = [64, 128, 255];
+
+//Initial TTL of a received packet => the smallest usual initial value not below its TTL, 32 for old stacks
+pub fn initial_ttl(ttl: u8) -> u8 {
+    if ttl <= 32 {
+        return 32;
+    }
+    INITIAL_TTLS.into_iter().find(|x| *x >= ttl).unwrap_or(u8::MAX)
+}
+
+//Number of routers crossed by a received packet => its initial TTL minus its TTL
+pub fn hop_count(ttl: u8) -> u8 {
+    initial_ttl(ttl) - ttl
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn initial_ttl_of_the_usual_stacks() {
+        assert_eq!(initial_ttl(1), 32);
+        assert_eq!(initial_ttl(32), 32);
+        assert_eq!(initial_ttl(33), 64);
+        assert_eq!(initial_ttl(64), 64);
+        assert_eq!(initial_ttl(65), 128);
+        assert_eq!(initial_ttl(128), 128);
+        assert_eq!(initial_ttl(129), 255);
+        assert_eq!(initial_ttl(255), 255);
+    }
+
+    #[test]
+    fn hop_count_from_the_initial_ttl() {
+        assert_eq!(hop_count(64), 0);
+        assert_eq!(hop_count(52), 12);
+        assert_eq!(hop_count(117), 11);
+        assert_eq!(hop_count(255), 0);
+        assert_eq!(hop_count(240), 15);
+        assert_eq!(hop_count(30), 2);
     }
 }
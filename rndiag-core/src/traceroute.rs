@@ -0,0 +1,563 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use csv::Writer;
+use dns_lookup::lookup_addr;
+use pnet::packet::tcp::TcpFlags;
+use pnet::transport::TransportSender;
+use serde_json::json;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use tokio::io::unix::AsyncFd;
+use tokio::time::timeout_at;
+
+use crate::error::{Error, Result};
+use crate::icmp::{
+    ICMP_DEST_UNREACHABLE, ICMP_TIME_EXCEEDED, ICMPV6_DEST_UNREACHABLE, ICMPV6_TIME_EXCEEDED, IPPROTO_UDP, IcmpError,
+    IcmpMessage, IcmpSocket, Privileges, SocketKind, next_ident,
+};
+use crate::socket::source_ip;
+use crate::tcp_ping::{
+    Ipv4Fields, TcpReply, TcpSegment, open_ipv4_channel, open_ipv6_raw, reserve_port, send_segment_v4, send_segment_v6, wait_tcp_reply,
+};
+use crate::tool::{as_ms, resolve_target};
+
+//Default number of hops before giving up
+pub const DEFAULT_MAX_HOPS: u8 = 30;
+
+//Default number of probes per hop
+pub const DEFAULT_QUERIES: u8 = 3;
+
+//Default time to wait for the answer of a probe
+pub const DEFAULT_TRACE_TIMEOUT: Duration = Duration::from_millis(5000);
+
+//First destination port of the UDP probes => incremented for each probe like traceroute
+pub const UDP_BASE_PORT: u16 = 33434;
+
+//Default destination port of the TCP probes
+pub const TCP_DEFAULT_PORT: u16 = 80;
+
+//Number of data bytes of the ICMP and UDP probes
+const PROBE_PAYLOAD_SIZE: usize = 32;
+
+//Columns of the csv export of traceroute
+pub const TRACE_CSV_COLUMNS: [&str; 6] = ["Hop", "Probe", "Address", "Name", "RTT", "Status"];
+
+//Protocol of the probes of traceroute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceMode {
+    Icmp, //Echo requests => the target answers with an echo reply
+    Udp, //UDP datagrams to high ports => the target answers with port unreachable
+    Tcp, //TCP SYN => the target answers with SYN-ACK or RST, goes through firewalls that drop ICMP and UDP
+}
+
+impl TraceMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TraceMode::Icmp => "icmp",
+            TraceMode::Udp => "udp",
+            TraceMode::Tcp => "tcp",
+        }
+    }
+
+    //Default destination port of the mode, 0 for ICMP
+    pub fn default_port(&self) -> u16 {
+        match self {
+            TraceMode::Icmp => 0,
+            TraceMode::Udp => UDP_BASE_PORT,
+            TraceMode::Tcp => TCP_DEFAULT_PORT,
+        }
+    }
+}
+
+//Options of traceroute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceOptions {
+    pub mode: TraceMode,
+    pub port: u16, //Destination port of UDP and TCP probes, first port for UDP
+    pub max_hops: u8, //Maximum TTL
+    pub queries: u8, //Number of probes per hop
+    pub timeout: Duration, //Time to wait for the answer of a probe
+    pub resolve_names: bool, //Reverse DNS of the hops
+}
+
+impl Default for TraceOptions {
+    fn default() -> Self {
+        Self {
+            mode: TraceMode::Icmp,
+            port: 0,
+            max_hops: DEFAULT_MAX_HOPS,
+            queries: DEFAULT_QUERIES,
+            timeout: DEFAULT_TRACE_TIMEOUT,
+            resolve_names: true,
+        }
+    }
+}
+
+//Answer to one probe => an ICMP error of a router or the reply of the target
+#[derive(Debug, Clone, PartialEq)]
+pub struct HopReply {
+    pub from: IpAddr, //Router or target that answered
+    pub name: Option<String>, //Reverse DNS name of from
+    pub rtt: Duration,
+    pub error: Option<IcmpError>, //ICMP error sent by from, None for the reply of the target
+    pub reached: bool, //True if from is the target
+}
+
+impl HopReply {
+    //Status of the answer => used in exports
+    pub fn status(&self) -> String {
+        match &self.error {
+            None => String::from("reply"),
+            Some(e) => e.describe().1.to_string(),
+        }
+    }
+
+    //True if the answer is an ICMP time exceeded => a router on the path
+    pub fn is_time_exceeded(&self) -> bool {
+        self.error
+            .as_ref()
+            .is_some_and(|e| matches!((e.from.is_ipv6(), e.icmp_type), (false, ICMP_TIME_EXCEEDED) | (true, ICMPV6_TIME_EXCEEDED)))
+    }
+
+    //Annotation of traceroute for an unreachable => !N, !H, !P, !F, !X, ... None for time exceeded and the reply of the target
+    pub fn annotation(&self) -> Option<String> {
+        let error = self.error.as_ref()?;
+        let annotation = match (error.from.is_ipv6(), error.icmp_type, error.code) {
+            //Port unreachable of the target is the normal end of an UDP traceroute
+            (false, ICMP_DEST_UNREACHABLE, 3) | (true, ICMPV6_DEST_UNREACHABLE, 4) if self.reached => return None,
+            (false, ICMP_DEST_UNREACHABLE, 0) | (true, ICMPV6_DEST_UNREACHABLE, 0) => String::from("!N"),
+            (false, ICMP_DEST_UNREACHABLE, 1) | (true, ICMPV6_DEST_UNREACHABLE, 3) => String::from("!H"),
+            (false, ICMP_DEST_UNREACHABLE, 2) => String::from("!P"),
+            (false, ICMP_DEST_UNREACHABLE, 4) => String::from("!F"),
+            (false, ICMP_DEST_UNREACHABLE, 9 | 10 | 13) | (true, ICMPV6_DEST_UNREACHABLE, 1) => String::from("!X"),
+            (false, ICMP_DEST_UNREACHABLE, code) | (true, ICMPV6_DEST_UNREACHABLE, code) => format!("!<{}>", code),
+            _ => return None,
+        };
+        Some(annotation)
+    }
+}
+
+//Probes of one TTL
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hop {
+    pub ttl: u8,
+    pub replies: Vec<Option<HopReply>>, //One per probe, None => no answer before the timeout
+}
+
+impl Hop {
+    //True if the trace must stop after this hop => the target answered or the path is unreachable
+    pub fn is_last(&self) -> bool {
+        let answers: Vec<&HopReply> = self.replies.iter().flatten().collect();
+        answers.iter().any(|x| x.reached)
+            || (!answers.is_empty() && answers.iter().all(|x| x.annotation().is_some()))
+    }
+}
+
+//Sockets of traceroute => sends TTL-limited probes of a mode and waits their answer
+//Also used by mtr to probe each hop in loop
+pub struct TraceProber {
+    target_ip: IpAddr,
+    src_ip: IpAddr,
+    mode: TraceMode,
+    port: u16,
+    icmp: IcmpSocket, //Echo requests and replies in ICMP mode, ICMP errors of the routers in all modes
+    udp: Option<Socket>,
+    tcp_v4: Option<(TransportSender, AsyncFd<Socket>)>,
+    tcp_v6: Option<AsyncFd<Socket>>,
+    ident: u16,
+    seq: u16,
+}
+
+impl TraceProber {
+    //Open the sockets of the mode => ICMP mode works with unprivileged datagram sockets, UDP and TCP modes need raw sockets
+    pub fn open(target_ip: IpAddr, mode: TraceMode, port: u16, tool: &str) -> Result<Self> {
+        let permission = |e: io::Error| match (e.kind(), mode) {
+            (io::ErrorKind::PermissionDenied, TraceMode::Icmp) => Error::PermissionDenied(Privileges::detect().explain(tool)),
+            (io::ErrorKind::PermissionDenied, _) => Error::PermissionDenied(format!(
+                "{} {} failed: operation not permitted, raw sockets need root or CAP_NET_RAW",
+                tool,
+                mode.name()
+            )),
+            _ => Error::Io(e),
+        };
+
+        let icmp = match mode {
+            TraceMode::Icmp => IcmpSocket::open(target_ip),
+            _ => IcmpSocket::open_kind(target_ip, SocketKind::Raw),
+        }
+        .map_err(permission)?;
+
+        let domain = if target_ip.is_ipv6() { Domain::IPV6 } else { Domain::IPV4 };
+        let mut prober = Self {
+            target_ip,
+            src_ip: source_ip(target_ip, None)?,
+            mode,
+            port: if port == 0 { mode.default_port() } else { port },
+            icmp,
+            udp: None,
+            tcp_v4: None,
+            tcp_v6: None,
+            ident: next_ident(),
+            seq: 0,
+        };
+
+        match (mode, target_ip) {
+            (TraceMode::Udp, _) => {
+                let socket = Socket::new(domain, Type::DGRAM, Some(Protocol::UDP))?;
+                let any: SocketAddr = if target_ip.is_ipv6() { "[::]:0".parse().unwrap() } else { "0.0.0.0:0".parse().unwrap() };
+                socket.bind(&any.into())?;
+                prober.udp = Some(socket);
+            }
+            (TraceMode::Tcp, IpAddr::V4(_)) => {
+                prober.tcp_v4 = Some(open_ipv4_channel().map_err(permission)?);
+            }
+            (TraceMode::Tcp, IpAddr::V6(_)) => {
+                prober.tcp_v6 = Some(open_ipv6_raw().map_err(permission)?);
+            }
+            (TraceMode::Icmp, _) => {}
+        }
+        Ok(prober)
+    }
+
+    pub fn target_ip(&self) -> IpAddr {
+        self.target_ip
+    }
+
+    //Destination port of the UDP and TCP probes, first port for UDP
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    //Kind of the ICMP socket => datagram when ICMP mode runs without privileges
+    pub fn icmp_kind(&self) -> SocketKind {
+        self.icmp.kind()
+    }
+
+    //Send one probe with the TTL and wait its answer until the timeout, None if nothing answered
+    pub async fn probe(&mut self, ttl: u8, timeout: Duration) -> io::Result<Option<HopReply>> {
+        self.seq = self.seq.wrapping_add(1);
+        match self.mode {
+            TraceMode::Icmp => self.probe_icmp(ttl, timeout).await,
+            TraceMode::Udp => self.probe_udp(ttl, timeout).await,
+            TraceMode::Tcp => self.probe_tcp(ttl, timeout).await,
+        }
+    }
+
+    async fn probe_icmp(&mut self, ttl: u8, timeout: Duration) -> io::Result<Option<HopReply>> {
+        let (seq, ident) = (self.seq, self.ident);
+        self.icmp.set_ttl(ttl)?;
+
+        let start = Instant::now();
+        self.icmp.send_echo(self.target_ip, ident, seq, &[0u8; PROBE_PAYLOAD_SIZE])?;
+
+        let deadline = tokio::time::Instant::from_std(start + timeout);
+        loop {
+            let Ok(message) = timeout_at(deadline, self.icmp.recv()).await else {
+                return Ok(None);
+            };
+            let message = message?;
+
+            if self.icmp.own_echo_error(&message, ident) == Some(seq) {
+                if let Some(reply) = self.error_reply(&message, start) {
+                    return Ok(Some(reply));
+                }
+            } else if self.icmp.is_own_echo(&message, ident) && message.seq == seq && message.from == self.target_ip {
+                return Ok(Some(self.target_reply(message.received.saturating_duration_since(start))));
+            }
+        }
+    }
+
+    async fn probe_udp(&mut self, ttl: u8, timeout: Duration) -> io::Result<Option<HopReply>> {
+        let Some(udp) = self.udp.as_ref() else {
+            return Ok(None);
+        };
+        let dst_port = self.port.wrapping_add(self.seq);
+        let src_port = udp.local_addr()?.as_socket().map_or(0, |x| x.port());
+
+        if self.target_ip.is_ipv6() {
+            udp.set_unicast_hops_v6(ttl as u32)?;
+        } else {
+            udp.set_ttl_v4(ttl as u32)?;
+        }
+
+        let start = Instant::now();
+        udp.send_to(&[0u8; PROBE_PAYLOAD_SIZE], &SockAddr::from(SocketAddr::new(self.target_ip, dst_port)))?;
+
+        let deadline = tokio::time::Instant::from_std(start + timeout);
+        loop {
+            let Ok(message) = timeout_at(deadline, self.icmp.recv()).await else {
+                return Ok(None);
+            };
+            let message = message?;
+
+            let own = message.quoted.as_ref().is_some_and(|x| x.protocol == IPPROTO_UDP && x.ports() == Some((src_port, dst_port)));
+            if own && let Some(reply) = self.error_reply(&message, start) {
+                return Ok(Some(reply));
+            }
+        }
+    }
+
+    //TCP SYN built with the packet builders of tcp ping, the answer is waited like tcp ping
+    async fn probe_tcp(&mut self, ttl: u8, timeout: Duration) -> io::Result<Option<HopReply>> {
+        //The source port is reserved until the answer => the concurrent probers of mtr never share a port
+        let (_reservation, src_port) = reserve_port(self.src_ip)?;
+        let segment = TcpSegment::new(src_port, self.port, TcpFlags::SYN);
+        let start = Instant::now();
+
+        let reply = match (self.target_ip, self.src_ip) {
+            (IpAddr::V4(dst), IpAddr::V4(src)) => {
+                let Some((sender, receiver)) = self.tcp_v4.as_mut() else {
+                    return Ok(None);
+                };
+                send_segment_v4(sender, src, dst, &segment, Ipv4Fields { ttl, tos: 0 })?;
+                let reply = wait_tcp_reply(receiver, Some(&self.icmp), IpAddr::V4(dst), &segment, start, timeout).await?;
+
+                //The target answered SYN|ACK => its half-open connexion is closed
+                if let Some(TcpReply::Segment(_, flags, _, _)) = reply
+                    && let Some(reset) = segment.reset_for(flags)
+                {
+                    let _ = send_segment_v4(sender, src, dst, &reset, Ipv4Fields { ttl: 64, tos: 0 });
+                }
+                reply
+            }
+            (IpAddr::V6(dst), IpAddr::V6(src)) => {
+                let Some(receiver) = self.tcp_v6.as_ref() else {
+                    return Ok(None);
+                };
+                let socket = receiver.get_ref();
+                socket.set_unicast_hops_v6(ttl as u32)?;
+                send_segment_v6(socket, src, dst, &segment)?;
+                let reply = wait_tcp_reply(receiver, Some(&self.icmp), IpAddr::V6(dst), &segment, start, timeout).await?;
+
+                if let Some(TcpReply::Segment(_, flags, _, _)) = reply
+                    && let Some(reset) = segment.reset_for(flags)
+                {
+                    socket.set_unicast_hops_v6(64)?;
+                    let _ = send_segment_v6(socket, src, dst, &reset);
+                }
+                reply
+            }
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "source and target of different IP versions")),
+        };
+
+        Ok(match reply {
+            Some(TcpReply::Segment(rtt, _, _, _)) => Some(self.target_reply(rtt)),
+            Some(TcpReply::Icmp(rtt, error)) => Some(HopReply {
+                from: error.from,
+                name: None,
+                rtt,
+                reached: error.from == self.target_ip,
+                error: Some(error),
+            }),
+            None => None,
+        })
+    }
+
+    //Answer of an ICMP error about a probe sent to the target
+    fn error_reply(&self, message: &IcmpMessage, start: Instant) -> Option<HopReply> {
+        let error = message.error.clone()?;
+        if message.quoted.as_ref()?.dst != self.target_ip {
+            return None;
+        }
+        Some(HopReply {
+            from: error.from,
+            name: None,
+            rtt: message.received.saturating_duration_since(start),
+            reached: error.from == self.target_ip,
+            error: Some(error),
+        })
+    }
+
+    fn target_reply(&self, rtt: Duration) -> HopReply {
+        HopReply { from: self.target_ip, name: None, rtt, error: None, reached: true }
+    }
+}
+
+//Reverse DNS of the hops, each address is resolved once
+#[derive(Debug, Default)]
+pub struct NameCache {
+    names: HashMap<IpAddr, Option<String>>,
+}
+
+impl NameCache {
+    //Name of an address already resolved
+    pub fn get(&self, ip: IpAddr) -> Option<String> {
+        self.names.get(&ip).cloned().flatten()
+    }
+
+    //Name of an address, None if it has no name
+    pub async fn resolve(&mut self, ip: IpAddr) -> Option<String> {
+        if let Some(name) = self.names.get(&ip) {
+            return name.clone();
+        }
+
+        //getnameinfo is blocking => run in a dedicated thread
+        let name = tokio::task::spawn_blocking(move || lookup_addr(&ip).ok()).await.ok().flatten();
+        self.names.insert(ip, name.clone());
+        name
+    }
+}
+
+//Traceroute tool => sends TTL-limited probes from 1 to max_hops, stops when the target answers
+pub struct TracerouteTool {
+    pub target: String, //IP or host to trace
+    options: TraceOptions,
+    output: String, //Output filename => json if it ends with .json, else csv
+    prober: Option<TraceProber>, //Opened by prepare
+    hops: Vec<Hop>,
+    names: NameCache,
+    finished: bool,
+}
+
+impl TracerouteTool {
+    //Init attributes of the object
+    pub fn new(target: &str, options: TraceOptions, output: &str) -> Self {
+        Self {
+            target: target.to_string(),
+            options,
+            output: output.to_string(),
+            prober: None,
+            hops: Vec::new(),
+            names: NameCache::default(),
+            finished: false,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        "traceroute"
+    }
+
+    pub fn hops(&self) -> &[Hop] {
+        &self.hops
+    }
+
+    //Resolve the target and open the sockets, return the description of the trace
+    pub async fn prepare(&mut self) -> Result<String> {
+        let target_ip = resolve_target(&self.target)?;
+        let prober = TraceProber::open(target_ip, self.options.mode, self.options.port, self.name())?;
+
+        let mode = match (prober.icmp_kind(), self.options.mode) {
+            (SocketKind::Datagram, TraceMode::Icmp) => String::from("icmp probes (unprivileged ICMP datagram socket)"),
+            (_, TraceMode::Icmp) => String::from("icmp probes"),
+            (_, mode) => format!("{} probes to port {}", mode.name(), prober.port()),
+        };
+        self.prober = Some(prober);
+
+        Ok(format!(
+            "traceroute to {} ({}), {} hops max, {}",
+            self.target, target_ip, self.options.max_hops, mode
+        ))
+    }
+
+    //Probe the next hop, None when the trace is finished => target reached, unreachable or max hops
+    pub async fn next_hop(&mut self) -> Result<Option<&Hop>> {
+        let ttl = self.hops.len() as u8 + 1;
+        if self.finished || ttl > self.options.max_hops {
+            return Ok(None);
+        }
+        let Some(prober) = self.prober.as_mut() else {
+            return Ok(None);
+        };
+
+        let mut hop = Hop { ttl, replies: Vec::with_capacity(self.options.queries as usize) };
+        for _ in 0..self.options.queries {
+            let mut reply = prober.probe(ttl, self.options.timeout).await.map_err(|e| Error::from_io("traceroute", e))?;
+            if self.options.resolve_names && let Some(reply) = reply.as_mut() {
+                reply.name = self.names.resolve(reply.from).await;
+            }
+            hop.replies.push(reply);
+        }
+
+        self.finished = hop.is_last();
+        self.hops.push(hop);
+        Ok(self.hops.last())
+    }
+
+    //Run the whole trace without front-end
+    pub async fn run(&mut self) -> Result<()> {
+        self.prepare().await?;
+        while self.next_hop().await?.is_some() {}
+        Ok(())
+    }
+
+    //Export the hops in json if the output ends with .json, else in csv
+    pub fn export(&self) -> Result<()> {
+        if self.output.ends_with(".json") {
+            self.export_json()
+        } else {
+            self.export_csv()
+        }
+    }
+
+    //Export the hops in csv => one row per probe, address and name are empty for a timeout
+    pub fn export_csv(&self) -> Result<()> {
+        let export_error = |source: io::Error| Error::Export { path: self.output.clone(), source };
+        let file = File::create(&self.output).map_err(export_error)?;
+        let mut writer = Writer::from_writer(file);
+
+        writer.write_record(TRACE_CSV_COLUMNS).map_err(|e| export_error(e.into()))?;
+
+        for hop in &self.hops {
+            for (probe, reply) in hop.replies.iter().enumerate() {
+                let record = match reply {
+                    Some(reply) => [
+                        hop.ttl.to_string(),
+                        (probe + 1).to_string(),
+                        reply.from.to_string(),
+                        reply.name.clone().unwrap_or_default(),
+                        format!("{:.3}", as_ms(reply.rtt)),
+                        reply.status(),
+                    ],
+                    None => [hop.ttl.to_string(), (probe + 1).to_string(), String::new(), String::new(), String::new(), String::from("timeout")],
+                };
+                writer.write_record(&record).map_err(|e| export_error(e.into()))?;
+            }
+        }
+        writer.flush().map_err(export_error)?;
+        Ok(())
+    }
+
+    //Export the trace in json => target, mode and hops with the answer of each probe, null for a timeout
+    pub fn export_json(&self) -> Result<()> {
+        let export_error = |source: io::Error| Error::Export { path: self.output.clone(), source };
+
+        let hops: Vec<serde_json::Value> = self
+            .hops
+            .iter()
+            .map(|hop| {
+                let probes: Vec<serde_json::Value> = hop
+                    .replies
+                    .iter()
+                    .map(|reply| match reply {
+                        Some(reply) => json!({
+                            "address": reply.from.to_string(),
+                            "name": reply.name,
+                            "rtt_ms": as_ms(reply.rtt),
+                            "status": reply.status(),
+                            "icmp_type": reply.error.as_ref().map(|e| e.icmp_type),
+                            "icmp_code": reply.error.as_ref().map(|e| e.code),
+                            "reached": reply.reached,
+                        }),
+                        None => serde_json::Value::Null,
+                    })
+                    .collect();
+                json!({ "ttl": hop.ttl, "probes": probes })
+            })
+            .collect();
+
+        let target_ip = self.prober.as_ref().map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |x| x.target_ip());
+        let trace = json!({
+            "target": self.target,
+            "target_ip": target_ip.to_string(),
+            "mode": self.options.mode.name(),
+            "hops": hops,
+        });
+
+        let mut file = File::create(&self.output).map_err(export_error)?;
+        serde_json::to_writer_pretty(&mut file, &trace).map_err(|e| export_error(e.into()))?;
+        file.write_all(b"\n").map_err(export_error)
+    }
+}
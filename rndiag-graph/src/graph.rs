@@ -32,16 +32,18 @@ impl Sparklines {
 }
 
 
-//Tuple of view => To see different view in graph, max values latencies, moy values latencies, min values latencies
+//Tuple of view => To see different view in graph, max values latencies, moy values latencies, min values latencies, p95 latencies, jitter
 #[allow(non_camel_case_types)]
 enum View {
     Trend_min,
     Trend_moy,
-    Trend_max
+    Trend_max,
+    Trend_p95,
+    Trend_jitter
 }
 
-//Raw terminal object
-struct TerminalCleanup;
+//Raw terminal object => raw mode and alternate screen are left when it is dropped
+pub(crate) struct TerminalCleanup;
 impl Drop for TerminalCleanup{
     fn drop(&mut self){
         let _ = crossterm::terminal::disable_raw_mode();
@@ -54,7 +56,8 @@ impl Drop for TerminalCleanup{
 
 
 //Main function for graph building layouting and displaying
-pub fn graph_display(latency_min_sampled: &Vec<u64>, latency_moy_sampled: &Vec<u64>, latency_max_sampled: &Vec<u64>) -> Result <(), io::Error>{
+//Sampled latencies are given in µs and displayed in ms
+pub fn graph_display(latency_min_sampled: &[u64], latency_moy_sampled: &[u64], latency_max_sampled: &[u64], latency_p95_sampled: &[u64], jitter_sampled: &[u64]) -> Result <(), io::Error>{
     
     // Enables terminal raw mode to capture keyboard input
     enable_raw_mode()?;
@@ -87,6 +90,8 @@ pub fn graph_display(latency_min_sampled: &Vec<u64>, latency_moy_sampled: &Vec<u
         View::Trend_min => ("Trend graph: Min latency (ms)", Color::Cyan, latency_min_sampled),
         View::Trend_moy => ("Trend graph: Avg latency (ms)", Color::Green, latency_moy_sampled),
         View::Trend_max => ("Trend graph: Max latency (ms)", Color::Red, latency_max_sampled),
+        View::Trend_p95 => ("Trend graph: p95 latency (ms)", Color::Yellow, latency_p95_sampled),
+        View::Trend_jitter => ("Trend graph: Jitter (ms)", Color::Magenta, jitter_sampled),
     };
 
     //Display no data on the graph if we have no data
@@ -117,9 +122,9 @@ pub fn graph_display(latency_min_sampled: &Vec<u64>, latency_moy_sampled: &Vec<u
             }
 
             // Draw the bar from bottom to top
-            for row in 0..graph_height as usize {
+            for (row, line) in lines.iter_mut().enumerate() {
                 let char_to_push = if row >= graph_height as usize - bar_height { '█' } else { ' ' };
-                lines[row].push(char_to_push);
+                line.push(char_to_push);
             }
         }
 
@@ -130,7 +135,7 @@ pub fn graph_display(latency_min_sampled: &Vec<u64>, latency_moy_sampled: &Vec<u
             .rev()
             .map(|i| {
                 let val = min_val + range * i as u64 / graph_height;
-                format!("{:>4}ms", val)
+                format!("{:>8.2}ms", val as f64 / 1000.0)
             })
             .collect();
         let scale_text = scale_labels.join("\n");
@@ -148,7 +153,7 @@ pub fn graph_display(latency_min_sampled: &Vec<u64>, latency_moy_sampled: &Vec<u
         // Layout horizontal : [scale][graph]
         let chunks = Layout::default()
             .direction(Direction::Horizontal)
-            .constraints([Constraint::Length(6), Constraint::Min(0)])
+            .constraints([Constraint::Length(10), Constraint::Min(0)])
             .split(layout[0]);
 
         //Ratatui widget graph + scale displaying
@@ -158,7 +163,7 @@ pub fn graph_display(latency_min_sampled: &Vec<u64>, latency_moy_sampled: &Vec<u
 
     // User Help
     let help = Paragraph::new(Line::from(Span::raw(
-        "q: quit    a: min view    m: avg view    i: max view",
+        "q: quit    a: min view    m: avg view    i: max view    p: p95 view    j: jitter view",
     )))
     .block(Block::default().borders(Borders::ALL).title("Options"));
     f.render_widget(help, layout[1]);
@@ -169,16 +174,17 @@ pub fn graph_display(latency_min_sampled: &Vec<u64>, latency_moy_sampled: &Vec<u
         
     
     // Keyboard input management (events)
-    if event::poll(Duration::from_millis(500))? {
-            if let Event::Key(key) = event::read()? {
-                //Change view depending of the pressed key or quit the graph
-                match key.code {
-                    KeyCode::Char('q') => break,
-                    KeyCode::Char('a') => current_view = View::Trend_min,
-                    KeyCode::Char('m') => current_view = View::Trend_moy,
-                    KeyCode::Char('i') => current_view = View::Trend_max,
-                        _ => {}
-                }
+    if event::poll(Duration::from_millis(500))?
+        && let Event::Key(key) = event::read()? {
+            //Change view depending of the pressed key or quit the graph
+            match key.code {
+                KeyCode::Char('q') => break,
+                KeyCode::Char('a') => current_view = View::Trend_min,
+                KeyCode::Char('m') => current_view = View::Trend_moy,
+                KeyCode::Char('i') => current_view = View::Trend_max,
+                KeyCode::Char('p') => current_view = View::Trend_p95,
+                KeyCode::Char('j') => current_view = View::Trend_jitter,
+                    _ => {}
             }
         }
     }
@@ -1 +1,2 @@
-pub mod graph;
\ No newline at end of file
+pub mod graph;
+pub mod mtr;
\ No newline at end of file
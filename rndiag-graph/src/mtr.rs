@@ -0,0 +1,147 @@
+use std::io::{self, stdout};
+use std::time::Duration;
+
+use crossterm::{
+    execute,
+    terminal::{EnterAlternateScreen, enable_raw_mode},
+    event::{self, Event, KeyCode, KeyModifiers},
+};
+
+use ratatui::{
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState},
+    prelude::*,
+};
+
+use crate::graph::TerminalCleanup;
+
+//Time between two refreshes of the table
+const REFRESH: Duration = Duration::from_millis(500);
+
+//One line of the mtr table
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MtrRow {
+    pub hop: u8,
+    pub host: String, //Name and address of the hop, ??? if no probe was answered
+    pub loss_percent: f64,
+    pub sent: u32,
+    pub last: Option<Duration>,
+    pub avg: Option<Duration>,
+    pub best: Option<Duration>,
+    pub worst: Option<Duration>,
+    pub stddev: Option<Duration>,
+}
+
+//Live table of mtr => the rows are asked to `rows` at each refresh, None when the run is finished
+//Up/Down select a hop, 'g' calls `graph` with the TTL of the selected hop, 'q' or Ctrl-C quit the table
+pub fn mtr_display<R, G>(title: &str, mut rows: R, mut graph: G) -> Result<(), io::Error>
+where
+    R: FnMut() -> Option<Vec<MtrRow>>,
+    G: FnMut(u8) -> Result<(), io::Error>,
+{
+    enable_raw_mode()?;
+    let mut stdout = stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let _cleanup = TerminalCleanup;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    let mut state = TableState::default().with_selected(Some(0));
+
+    //None => the run is finished, the table is left and the caller prints the report
+    while let Some(current) = rows() {
+        if let Some(selected) = state.selected()
+            && selected >= current.len()
+        {
+            state.select(Some(current.len().saturating_sub(1)));
+        }
+
+        terminal.draw(|f| {
+            let layout = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(3)])
+                .split(f.area());
+
+            let header = Row::new(["Hop", "Host", "Loss%", "Snt", "Last", "Avg", "Best", "Wrst", "StDev"])
+                .style(Style::default().add_modifier(Modifier::BOLD));
+
+            let lines: Vec<Row> = current
+                .iter()
+                .map(|row| {
+                    //A hop that loses probes is highlighted => the loss of a hop only matters if the next hops also lose probes
+                    let color = if row.loss_percent >= 50.0 {
+                        Color::Red
+                    } else if row.loss_percent > 0.0 {
+                        Color::Yellow
+                    } else {
+                        Color::Reset
+                    };
+                    Row::new([
+                        Cell::from(format!("{:>3}.", row.hop)),
+                        Cell::from(row.host.clone()),
+                        Cell::from(format!("{:>5.1}%", row.loss_percent)).style(Style::default().fg(color)),
+                        Cell::from(format!("{:>5}", row.sent)),
+                        Cell::from(ms(row.last)),
+                        Cell::from(ms(row.avg)),
+                        Cell::from(ms(row.best)),
+                        Cell::from(ms(row.worst)),
+                        Cell::from(ms(row.stddev)),
+                    ])
+                })
+                .collect();
+
+            let widths = [
+                Constraint::Length(4),
+                Constraint::Min(20),
+                Constraint::Length(7),
+                Constraint::Length(6),
+                Constraint::Length(9),
+                Constraint::Length(9),
+                Constraint::Length(9),
+                Constraint::Length(9),
+                Constraint::Length(9),
+            ];
+            let table = Table::new(lines, widths)
+                .header(header)
+                .block(Block::default().borders(Borders::ALL).title(title.to_string()))
+                .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            f.render_stateful_widget(table, layout[0], &mut state);
+
+            let help = Paragraph::new(Line::from(Span::raw(
+                "q: quit    up/down: select a hop    g: trend graph of the selected hop",
+            )))
+            .block(Block::default().borders(Borders::ALL).title("Options"));
+            f.render_widget(help, layout[1]);
+        })?;
+
+        if event::poll(REFRESH)?
+            && let Event::Key(key) = event::read()?
+        {
+            match (key.code, key.modifiers) {
+                (KeyCode::Char('q'), _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => break,
+                (KeyCode::Up, _) => state.select_previous(),
+                (KeyCode::Down, _) if state.selected().is_some_and(|x| x + 1 < current.len()) => state.select_next(),
+                (KeyCode::Char('g'), KeyModifiers::NONE) => {
+                    if let Some(row) = state.selected().and_then(|x| current.get(x)) {
+                        graph(row.hop)?;
+
+                        //The graph leaves the alternate screen and the raw mode when it is closed => back to the table
+                        enable_raw_mode()?;
+                        execute!(terminal.backend_mut(), EnterAlternateScreen)?;
+                        terminal.clear()?;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+//Latency in ms of a cell, empty without value
+fn ms(value: Option<Duration>) -> String {
+    value.map_or(String::new(), |x| format!("{:>8.3}", x.as_secs_f64() * 1000.0))
+}
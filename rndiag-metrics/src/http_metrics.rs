@@ -0,0 +1,57 @@
+use crate::metrics::MetricsLatency;
+use rndiag_core::error::Result;
+use rndiag_core::tool::Statistics;
+
+//http object metrics definition
+pub struct HttpMetrics {
+    statistics: Statistics, //Store the statistics of last requests of equivalent tool object
+    latency_level: u8, //Store the status of the latency by level. 0 => OK, 1 => Warning, 2 => Critical
+    dst: String, //Store the url to display it on metrics
+    output_exporter: Vec<String>, //Contain formated as prom text format text ready to be write in metric file
+}
+
+//Methods specifically defined for the HttpMetrics object about the inherited NetworkTool Trait
+impl MetricsLatency for HttpMetrics {
+    //Return only the name of the object
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    //Return the statistics attribute
+    fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    //Return the latency status/level
+    fn latency_level(&mut self) -> &mut u8 {
+        &mut self.latency_level
+    }
+
+    //Return the dst
+    fn dst(&self) -> String {
+        self.dst.clone()
+    }
+
+    //Return the content of output_exporter (formated prometheus format text)
+    fn output_exporter(&mut self) -> &mut Vec<String> {
+        &mut self.output_exporter
+    }
+
+    //Main method of the object
+    fn run(&mut self) -> Result<()> {
+        //Calling the methods defined in the Trait, the latency is the total time of the requests
+        self.packet_latency();
+        self.output_format();
+        self.timings_format(&["dns", "connect", "tls", "ttfb"]);
+
+        Ok(())
+    }
+}
+
+//Specific method that not inerhited by the trait for this object
+impl HttpMetrics {
+    //Init object method
+    pub fn new(statistics: Statistics, dst: String) -> Self {
+        Self { statistics, dst, latency_level: 0, output_exporter: Vec::new() }
+    }
+}
@@ -1,4 +1,6 @@
 pub mod metrics;
 pub mod ping_metrics;
 pub mod tping_metrics;
-pub mod resolver_metrics;
\ No newline at end of file
+pub mod resolver_metrics;
+pub mod tls_metrics;
+pub mod http_metrics;
\ No newline at end of file
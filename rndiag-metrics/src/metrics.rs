@@ -1,28 +1,37 @@
-use anyhow::Result;
-use std::io::{self};
+use rndiag_core::error::Result;
+use rndiag_core::tool::Statistics;
+use std::time::Duration;
 
 //NetworkTool trait => The trait for all networktool (ping, DNS resolving, telnet connection, ...)
 pub trait MetricsLatency {
     fn name(&self) -> &'static str; //Get the name of the object
-    fn latency_moy_sampled(&self) -> u64; //Get the object latency_moy_sampled attribute
+    fn statistics(&self) -> &Statistics; //Get the object statistics attribute
     fn latency_level(&mut self) -> &mut u8; //Get the latency level object attribute
     fn dst(&self) -> String; //Get the dst object attribute
     fn output_exporter(&mut self) -> &mut Vec<String>; //Get the output_exporter object attribute
-    fn run(&mut self) -> Result <(), io::Error>; //Run method
+    fn run(&mut self) -> Result<()>; //Run method
+
+    //Get the moy latency in µs from the statistics, None if no reply
+    fn latency_moy_sampled(&self) -> Option<u64> {
+        self.statistics().avg.map(|x| x.as_micros() as u64)
+    }
 
     //packet_latency method definition
     //Define the latency status/level with the provided moy latency of lasts pings tests with equivalent tools objects
     fn packet_latency(&mut self){
         
-        //Latencies threshold in ms
-        let latency1: u64 = 59;
-        let latency2: u64 = 130;
+        //Latencies threshold in µs
+        let latency1: u64 = 59_000;
+        let latency2: u64 = 130_000;
 
-        if self.latency_moy_sampled() >= latency2{
+        //No reply => the latency is considered as critical
+        let latency = self.latency_moy_sampled().unwrap_or(u64::MAX);
+
+        if latency >= latency2{
             *self.latency_level() = 2; // => critical status
 
         }
-        else if self.latency_moy_sampled() >= latency1 {
+        else if latency >= latency1 {
             *self.latency_level() = 1; // => warning status
         }
         else {
@@ -45,15 +54,15 @@ pub trait MetricsLatency {
         let mut metrics_latency: String = String::from(self.name());
 
         //metrics_latency = metrics_latency + "{target=\"{}\"} " + self.latency_moy_sampled().to_string().as_str() + "\n";
-        metrics_latency = metrics_latency + "{target=\"" + self.dst().as_str() + "\"} " + self.latency_moy_sampled().to_string().as_str() + "\n";
+        metrics_latency = metrics_latency + "{target=\"" + self.dst().as_str() + "\"} " + self.latency_moy_sampled().map_or("NaN".to_string(), |x| format!("{:.3}", x as f64 / 1000.0)).as_str() + "\n";
 
         //------------------Latencies status metrics format------------------
 
         let mut help_latency_state = String::from("# HELP ");
         let mut type_latency_state = String::from("# TYPE ");
 
-        help_latency_state = help_latency_state + self.name() + " state: 0 OK, 1 Warning, 2 NOK\n";
-        type_latency_state = type_latency_state + self.name() + " gauge\n";
+        help_latency_state = help_latency_state + self.name() + "_state " + self.name() + " state: 0 OK, 1 Warning, 2 NOK\n";
+        type_latency_state = type_latency_state + self.name() + "_state gauge\n";
         
         let mut metrics_latency_state: String = String::from(self.name());
         //metrics_latency_state = metrics_latency_state + "_state" + "{target=\"{}\"} " + self.latency_level().to_string().as_str() + "\n";
@@ -66,7 +75,69 @@ pub trait MetricsLatency {
         self.output_exporter().push(help_latency_state);
         self.output_exporter().push(type_latency_state);
         self.output_exporter().push(metrics_latency_state);
+
+        //------------------Percentiles, deviations and jitter metrics format------------------
+        let stats = self.statistics().clone();
+        let extra_metrics: [(&str, &str, Option<Duration>); 7] = [
+            ("p50", "p50 latency", stats.p50),
+            ("p90", "p90 latency", stats.p90),
+            ("p95", "p95 latency", stats.p95),
+            ("p99", "p99 latency", stats.p99),
+            ("mdev", "mean deviation of the latency", stats.mdev),
+            ("stddev", "standard deviation of the latency", stats.stddev),
+            ("jitter", "RFC 3550 interarrival jitter", stats.jitter),
+        ];
+
+        for (suffix, desc, value) in extra_metrics {
+            let metric_name = String::from(self.name()) + "_" + suffix;
+            let help = String::from("# HELP ") + metric_name.as_str() + " " + self.name() + " " + desc + " in ms\n";
+            let type_metric = String::from("# TYPE ") + metric_name.as_str() + " gauge\n";
+            let metric = metric_name + "{target=\"" + self.dst().as_str() + "\"} " + value.map_or("NaN".to_string(), |x| format!("{:.3}", x.as_micros() as f64 / 1000.0)).as_str() + "\n";
+
+            self.output_exporter().push(help);
+            self.output_exporter().push(type_metric);
+            self.output_exporter().push(metric);
+        }
+
+        //------------------ICMP errors metrics format------------------
+        let metric_name = String::from(self.name()) + "_icmp_errors";
+        let help = String::from("# HELP ") + metric_name.as_str() + " " + self.name() + " probes lost with an ICMP error (unreachable, time exceeded, ...)\n";
+        let type_metric = String::from("# TYPE ") + metric_name.as_str() + " gauge\n";
+        let metric = metric_name + "{target=\"" + self.dst().as_str() + "\"} " + stats.icmp_errors.to_string().as_str() + "\n";
+
+        self.output_exporter().push(help);
+        self.output_exporter().push(type_metric);
+        self.output_exporter().push(metric);
     }
 
-    
+    //timings_format method definition
+    //Format in prometheus format text the average time of the given phases of the replies => time since the start of the probe like curl -w
+    //Only the phases done by the probe type are given => the other ones would always be NaN
+    fn timings_format(&mut self, phases: &[&str]) {
+        //No reply => NaN for each phase
+        let timings = self.statistics().timings.unwrap_or_default();
+        for (phase, value) in timings.phases().into_iter().filter(|(phase, _)| phases.contains(phase)) {
+            let metric_name = String::from(self.name()) + "_" + phase;
+            let help = String::from("# HELP ") + metric_name.as_str() + " " + self.name() + " average time to the end of the " + phase + " phase in ms\n";
+            let type_metric = String::from("# TYPE ") + metric_name.as_str() + " gauge\n";
+            let metric = metric_name + "{target=\"" + self.dst().as_str() + "\"} " + value.map_or("NaN".to_string(), |x| format!("{:.3}", x.as_micros() as f64 / 1000.0)).as_str() + "\n";
+
+            self.output_exporter().push(help);
+            self.output_exporter().push(type_metric);
+            self.output_exporter().push(metric);
+        }
+    }
+
+    //cert_expiry_format method definition
+    //Format in prometheus format text the end of validity of the certificate of the server => NaN when no handshake succeeded
+    fn cert_expiry_format(&mut self) {
+        let metric_name = String::from(self.name()) + "_cert_expiry";
+        let help = String::from("# HELP ") + metric_name.as_str() + " " + self.name() + " end of validity of the certificate of the server in seconds since the epoch\n";
+        let type_metric = String::from("# TYPE ") + metric_name.as_str() + " gauge\n";
+        let metric = metric_name + "{target=\"" + self.dst().as_str() + "\"} " + self.statistics().cert_expiry.map_or("NaN".to_string(), |x| x.to_string()).as_str() + "\n";
+
+        self.output_exporter().push(help);
+        self.output_exporter().push(type_metric);
+        self.output_exporter().push(metric);
+    }
 }
\ No newline at end of file
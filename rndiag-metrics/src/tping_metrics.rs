@@ -1,8 +1,10 @@
 use crate::metrics::MetricsLatency;
+use rndiag_core::error::Result;
+use rndiag_core::tool::Statistics;
 
 //tping object metrics definition
 pub struct TPingMetrics {
-    latency_moy_sampled: u64, //Store the moy latency of last pings of equivalent tool object
+    statistics: Statistics, //Store the statistics of last pings of equivalent tool object
     latency_level: u8,  //Store the status of the latency by level. 0 => OK, 1 => Warning, 2 => Critical
     dst: String, //Store the dst to display it on metrics
     output_exporter: Vec<String>, //Contain formated as prom text format text ready to be write in metric file
@@ -14,9 +16,9 @@ impl MetricsLatency for TPingMetrics {
     fn name(&self) -> &'static str {
         "tping"
     }
-    //Return the moy latency attribute
-    fn latency_moy_sampled(&self) -> u64 {
-        self.latency_moy_sampled
+    //Return the statistics attribute
+    fn statistics(&self) -> &Statistics {
+        &self.statistics
     }
 
     //Return the latency status/level
@@ -35,7 +37,7 @@ impl MetricsLatency for TPingMetrics {
     }
 
     //Main method of the object
-    fn run(&mut self) -> anyhow::Result<(), std::io::Error> {
+    fn run(&mut self) -> Result<()> {
         //Calling two methods defined in the Trait
         self.packet_latency();
         self.output_format();
@@ -47,8 +49,8 @@ impl MetricsLatency for TPingMetrics {
 //Specific method that not inerhited by the trait for this object
 impl TPingMetrics{
     //Init object method
-    pub fn new(latency_moy_sampled: u64, dst: String) -> Self{
-        Self { latency_moy_sampled: latency_moy_sampled, dst: dst, latency_level: 0, output_exporter: Vec::new()}
+    pub fn new(statistics: Statistics, dst: String) -> Self{
+        Self { statistics, dst, latency_level: 0, output_exporter: Vec::new()}
 
     }
 }
\ No newline at end of file
@@ -1,23 +1,37 @@
-use rndiag_core::tool::LatencyTool;
+use rndiag_core::multi::{DEFAULT_CONCURRENCY, run_concurrent};
+use rndiag_core::tool::{Probe, ProbeOptions, ProbeRunner, Statistics};
 use warp::{http::StatusCode, reply::Reply, Filter};
 use std::{convert::Infallible, sync::Arc};
 use tokio::time::{sleep, Duration};
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::{self};
+use std::net::Ipv4Addr;
 
+use rndiag_core::error::{self, Error};
+use rndiag_core::icmp::DEFAULT_PAYLOAD_SIZE;
+
+use rndiag_core::http;
 use rndiag_core::ping;
+use rndiag_core::socket::SocketOptions;
 use rndiag_core::nslookup;
 use rndiag_core::tcp_ping;
+use rndiag_core::tls::{self, TLS_PORT, TlsOptions};
+use rndiag_metrics::http_metrics;
 use rndiag_metrics::metrics::MetricsLatency;
 use rndiag_metrics::ping_metrics;
 use rndiag_metrics::resolver_metrics;
+use rndiag_metrics::tls_metrics;
 use rndiag_metrics::tping_metrics;
 
-pub async fn launch_srv(parsing_time: u64, addr: &str, addr_srv: &str, port: u16, port_srv: u16, filename: &str, output: &str, nb_ping: u32, flag: u8) {
-    let addr_string = addr.to_string();
+#[allow(clippy::too_many_arguments)]
+pub async fn launch_srv(parsing_time: u64, targets: &[String], tls_options: Option<TlsOptions>, urls: &[String], addr_srv: &str, port: u16, port_srv: u16, filename: &str, output: &str, nb_ping: u32, flag: u8, options: ProbeOptions) -> error::Result<()> {
+    //Check the web-server address before launching the tools (needed for warp web-server crate)
+    let ip_srv: Ipv4Addr = addr_srv.parse().map_err(|_| Error::InvalidAddress(addr_srv.to_string()))?;
+
+    let targets = targets.to_vec();
+    let urls = urls.to_vec();
     let output_clone = output.to_string();
-    let addr_srv_string = addr_srv.to_string();
     let filename_string = filename.to_string();
 
     // Spawn the metrics collection task in the background
@@ -26,79 +40,39 @@ pub async fn launch_srv(parsing_time: u64, addr: &str, addr_srv: &str, port: u16
     
         loop {
 
-            // Initialize objects ONCE outside the loop as mutable
-            let mut ping = ping::PingTool::new(&addr_string, &output_clone, nb_ping);
-            let mut tping = tcp_ping::TCPPingTool::new(&addr_string, &output_clone, nb_ping, port, flag);
-            let mut nping = nslookup::NSlookup::new(&addr_string, &output_clone, nb_ping);
-
-            // Run the tool
-            ping.run().await.unwrap_or_else(|e|{
-                eprintln!("Error during rndiag launching: {}", e);
-            });
-
-            tping.run().await.unwrap_or_else(|e|{
-                eprintln!("Error during rndiag launching: {}", e);
-            });
-
-            nping.run().await.unwrap_or_else(|e|{
-                eprintln!("Error during rndiag launching: {}", e);
-            });
-
-            // Create equivalents metrics objects with mutable
-            let mut pmetrics = ping_metrics::PingMetrics::new(ping.latency_moy_sampled()[0], addr_string.clone());
-            let mut tmetrics = tping_metrics::TPingMetrics::new(tping.latency_moy_sampled()[0], addr_string.clone());
-            let mut rmetrics = resolver_metrics::NSLookupMetrics::new(nping.latency_moy_sampled()[0], addr_string.clone());
-
-            //Run metrics tools => take and process needed data
-            pmetrics.run().unwrap_or_else(|e|{
-                eprintln!("Error during rndiag metrics building: {}", e);
-            });
-
-            tmetrics.run().unwrap_or_else(|e|{
-                eprintln!("Error during rndiag metrics building: {}", e);
-            });
-
-            rmetrics.run().unwrap_or_else(|e|{
-                eprintln!("Error during rndiag metrics building: {}", e);
-            });
-
-            //Create metrics file
-            let metrics_file = File::create(&output_clone).unwrap_or_else(|e| {
-                eprintln!("Error during the metrics file creation: {}", e);
-                std::process::exit(1);
-            });
-
-            //Create a writer to write later in the metrics file
-            let mut writer = io::BufWriter::new(metrics_file);
-
-            /*Use output_exporter atribute object that contain metrics + desc formated as prometheus text format to write
-              in metrics file
-             */
-            for elem in pmetrics.output_exporter(){
-                writer.write_all(elem.as_bytes()).unwrap_or_else(|e| {
-                eprintln!("Error during metrics writing in file !: {}", e);
-                std::process::exit(1);
-                });
-            }
-
-            for elem in tmetrics.output_exporter(){
-                writer.write_all(elem.as_bytes()).unwrap_or_else(|e| {
+            // Initialize one object per tool and per target
+            let pings = targets.iter().map(|dst| ProbeRunner::new(ping::PingTool::new(dst, DEFAULT_PAYLOAD_SIZE, &[], SocketOptions::default()), &output_clone, nb_ping, options)).collect();
+            let tpings = targets.iter().map(|dst| ProbeRunner::new(tcp_ping::TCPPingTool::new(dst, port, tcp_ping::TcpPingMode::Raw(flag, tcp_ping::TcpOptions::default()), SocketOptions::default()), &output_clone, nb_ping, options)).collect();
+            let npings = targets.iter().map(|dst| ProbeRunner::new(nslookup::NSlookup::new(dst, SocketOptions::default()), &output_clone, nb_ping, options)).collect();
+            // The tls probe is only run when the user asked it, on the HTTPS port of the targets
+            let handshakes = match &tls_options {
+                Some(tls_options) => targets.iter().map(|dst| ProbeRunner::new(tls::TlsTool::new(dst, TLS_PORT, tls_options.clone(), SocketOptions::default()), &output_clone, nb_ping, options)).collect(),
+                None => Vec::new(),
+            };
+            // The http probe is only run on the urls given by the user
+            let requests = urls.iter().map(|url| ProbeRunner::new(http::HttpTool::new(url, false, SocketOptions::default()), &output_clone, nb_ping, options)).collect();
+
+            // Run the tools of all targets concurrently
+            let (pings, tpings, npings, handshakes, requests) = tokio::join!(
+                run_concurrent(pings, DEFAULT_CONCURRENCY),
+                run_concurrent(tpings, DEFAULT_CONCURRENCY),
+                run_concurrent(npings, DEFAULT_CONCURRENCY),
+                run_concurrent(handshakes, DEFAULT_CONCURRENCY),
+                run_concurrent(requests, DEFAULT_CONCURRENCY),
+            );
+
+            // Create equivalents metrics objects => formated prometheus text of each tool and each target
+            let mut exporters = metrics_output(&pings, ping_metrics::PingMetrics::new);
+            exporters.extend(metrics_output(&tpings, tping_metrics::TPingMetrics::new));
+            exporters.extend(metrics_output(&npings, resolver_metrics::NSLookupMetrics::new));
+            exporters.extend(metrics_output(&handshakes, tls_metrics::TlsMetrics::new));
+            exporters.extend(metrics_output(&requests, http_metrics::HttpMetrics::new));
+
+            //Write metrics in the metrics file, on error the metrics are written again at the next refresh
+            if let Err(e) = write_metrics(&output_clone, &exporters) {
                 eprintln!("Error during metrics writing in file !: {}", e);
-                std::process::exit(1);
-                });
             }
 
-            for elem in rmetrics.output_exporter(){
-                writer.write_all(elem.as_bytes()).unwrap_or_else(|e| {
-                eprintln!("Error during metrics writing in file !: {}", e);
-                std::process::exit(1);
-                });
-            }
-
-            writer.flush().unwrap_or_else(|e| {
-                eprintln!("Error during the metrics file closing: {}", e);
-            });
-            
             //Each parsing_time value in sec, launch again tools and metrics object to refresh metrics
             sleep(Duration::from_secs(parsing_time)).await;
         }
@@ -115,19 +89,72 @@ pub async fn launch_srv(parsing_time: u64, addr: &str, addr_srv: &str, port: u16
             .and_then(move || handle_serve_ips(fname_shared_clone.clone()))
     };
 
-    //Split given addr to get each IP bit individually (needed for warp web-server crate)
-    let ip_srv_vec_str: Vec<&str> = addr_srv_string.split('.').collect();
-    let mut ip_srv_vec_u8: Vec<u8> = Vec::new();
-    for elem in ip_srv_vec_str {
-        ip_srv_vec_u8.push(elem.parse().unwrap());
-    }
-    
+    //Launch the web-server with warp (IP + port), the error is returned if the address cannot be bound
+    let (_, server) = warp::serve(route)
+        .try_bind_ephemeral((ip_srv, port_srv))
+        .map_err(|e| Error::Io(io::Error::other(e)))?;
+
     println!("\n\nWeb server is running: http://{}:{}/{}\n\n", addr_srv, port_srv, filename);
-    
-    //Launch the web-server with warp (IP + port)
-    warp::serve(route)
-        .run(([ip_srv_vec_u8[0], ip_srv_vec_u8[1], ip_srv_vec_u8[2], ip_srv_vec_u8[3]], port_srv))
-        .await;
+    server.await;
+    Ok(())
+}
+
+//Build the metrics object of each run tool and take its formated prometheus text
+fn metrics_output<P: Probe, M: MetricsLatency>(results: &[(ProbeRunner<P>, error::Result<()>)], new: fn(Statistics, String) -> M) -> Vec<Vec<String>> {
+    results
+        .iter()
+        .map(|(tool, result)| {
+            if let Err(e) = result {
+                eprintln!("Error during rndiag launching: {}", e);
+            }
+
+            //Run metrics tools => take and process needed data
+            let mut metrics = new(tool.statistics(), tool.target().to_string());
+            metrics.run().unwrap_or_else(|e|{
+                eprintln!("Error during rndiag metrics building: {}", e);
+            });
+            metrics.output_exporter().clone()
+        })
+        .collect()
+}
+
+/*Use output_exporter atribute of metrics objects that contain metrics + desc formated as prometheus text format to write
+  in metrics file
+  Prometheus needs the samples of a metric grouped with only one HELP and TYPE => the lines of all targets are grouped by metric name
+ */
+fn write_metrics(output: &str, exporters: &[Vec<String>]) -> error::Result<()> {
+    let mut families: Vec<(&str, Vec<&str>)> = Vec::new();
+    for line in exporters.iter().flatten() {
+        let name = metric_name(line);
+        match families.iter_mut().find(|(family, _)| *family == name) {
+            Some((_, lines)) => {
+                if !line.starts_with('#') || !lines.contains(&line.as_str()) {
+                    lines.push(line);
+                }
+            }
+            None => families.push((name, vec![line])),
+        }
+    }
+
+    let export_error = |source: io::Error| Error::Export { path: output.to_string(), source };
+
+    //Create metrics file
+    let metrics_file = File::create(output).map_err(export_error)?;
+
+    //Create a writer to write later in the metrics file
+    let mut writer = io::BufWriter::new(metrics_file);
+
+    for line in families.iter().flat_map(|(_, lines)| lines) {
+        writer.write_all(line.as_bytes()).map_err(export_error)?;
+    }
+
+    writer.flush().map_err(export_error)
+}
+
+//Name of the metric of a prometheus text line => "# HELP name ...", "# TYPE name ..." or "name{labels} value"
+fn metric_name(line: &str) -> &str {
+    let line = line.strip_prefix("# HELP ").or_else(|| line.strip_prefix("# TYPE ")).unwrap_or(line);
+    line.split(['{', ' ']).next().unwrap_or(line)
 }
 
 //Manage response of webserver when the program receive a request